@@ -0,0 +1,279 @@
+use crate::config::PACKAGE_TMPL_JSON;
+use crate::error::KhadimError;
+use crate::graph::DependencyGraph;
+use crate::output;
+use crate::root::find_root_dir;
+use crate::workspace::{Project, Workspace};
+use log::{debug, info};
+use serde_json::json;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What to compare against when looking for changed files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeSource {
+    /// Uncommitted changes in the working tree (staged, unstaged and untracked) against HEAD.
+    WorkingTree,
+    /// Changes committed on HEAD since it diverged from the given base ref, e.g. `origin/main`.
+    Base(String),
+}
+
+/// Prints the directories of the projects affected by the changes from the
+/// given source to stdout, one per line.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * The workspace cannot be discovered or contains a dependency cycle
+/// * The changed files cannot be determined with git
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::affected::{print_affected, ChangeSource};
+///
+/// print_affected(&ChangeSource::WorkingTree).unwrap();
+/// ```
+pub fn print_affected(source: &ChangeSource) -> Result<(), KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let graph = DependencyGraph::build(&workspace)?;
+    let changed = changed_files(&root_dir, source)?;
+
+    let affected = affected_projects(&graph, &changed);
+    let items: Vec<_> = affected
+        .iter()
+        .map(|project| json!({ "project": project.name, "dir": project.relative_dir() }))
+        .collect();
+    output::report("affected", &items);
+    if affected.is_empty() {
+        info!("✅ No projects affected by {} changed files", changed.len());
+    }
+    // The dirs are the command's output, e.g. for `xargs`; in JSON mode stdout only carries events
+    if !output::is_json() {
+        for project in affected {
+            println!("{}", project.relative_dir().display());
+        }
+    }
+    Ok(())
+}
+
+/// Lists the files changed according to git, relative to the root directory.
+///
+/// Files outside of the root directory are ignored.
+///
+/// # Arguments
+///
+/// * `root_dir` - The monorepo root directory.
+/// * `source` - Whether to look at the working tree or at a base ref.
+///
+/// # Errors
+///
+/// This function will return an error if git cannot be executed or exits with
+/// a non-zero status, e.g. because the base ref does not exist.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::affected::{changed_files, ChangeSource};
+/// use std::path::Path;
+///
+/// let base = ChangeSource::Base("origin/main".to_string());
+/// for file in changed_files(Path::new("/path/to/project"), &base).unwrap() {
+///     println!("{}", file.display());
+/// }
+/// ```
+pub fn changed_files(root_dir: &Path, source: &ChangeSource) -> Result<Vec<PathBuf>, KhadimError> {
+    let mut files = BTreeSet::new();
+
+    match source {
+        ChangeSource::WorkingTree => {
+            files.extend(run_git(
+                root_dir,
+                &["diff", "--name-only", "--relative", "HEAD"],
+            )?);
+            files.extend(run_git(
+                root_dir,
+                &["ls-files", "--others", "--exclude-standard"],
+            )?);
+        }
+        ChangeSource::Base(base) => {
+            let range = format!("{}...HEAD", base);
+            files.extend(run_git(
+                root_dir,
+                &["diff", "--name-only", "--relative", &range],
+            )?);
+        }
+    }
+
+    Ok(files.into_iter().map(PathBuf::from).collect())
+}
+
+/// Determines the projects affected by a set of changed files.
+///
+/// A project is affected when one of the changed files lives inside its
+/// directory, or when it depends (directly or transitively) on an affected
+/// project. A change to the root `package-tmpl.json` affects every project.
+///
+/// # Arguments
+///
+/// * `graph` - The workspace dependency graph.
+/// * `changed_files` - Changed file paths relative to the root directory.
+///
+/// # Returns
+///
+/// The affected projects in dependency order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::affected::affected_projects;
+/// use khadim_rs::graph::DependencyGraph;
+/// use khadim_rs::workspace::Workspace;
+/// use std::path::{Path, PathBuf};
+///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+/// let graph = DependencyGraph::build(&workspace).unwrap();
+/// let changed = vec![PathBuf::from("libs/hello/src/index.ts")];
+///
+/// // libs/hello and every app depending on it
+/// let affected = affected_projects(&graph, &changed);
+/// ```
+pub fn affected_projects<'a>(
+    graph: &DependencyGraph<'a>,
+    changed_files: &[PathBuf],
+) -> Vec<&'a Project> {
+    let projects = graph.topological_order();
+
+    if changed_files
+        .iter()
+        .any(|file| file == Path::new(PACKAGE_TMPL_JSON))
+    {
+        return projects;
+    }
+
+    let mut affected: HashSet<&Path> = HashSet::new();
+    let mut pending: Vec<&'a Project> = projects
+        .iter()
+        .copied()
+        .filter(|project| {
+            let project_dir = project.relative_dir();
+            changed_files
+                .iter()
+                .any(|file| file.starts_with(&project_dir))
+        })
+        .collect();
+
+    // Expand to transitive dependents
+    while let Some(project) = pending.pop() {
+        if affected.insert(&project.path) {
+            pending.extend(graph.dependents_of(project));
+        }
+    }
+
+    projects
+        .into_iter()
+        .filter(|project| affected.contains(project.path.as_path()))
+        .collect()
+}
+
+/// Runs a git command in the given directory and returns its non-empty output lines.
+pub(crate) fn run_git(dir: &Path, args: &[&str]) -> Result<Vec<String>, KhadimError> {
+    debug!("Running `git {}` in {}", args.join(" "), dir.display());
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| KhadimError::spawn("git", e))?;
+
+    if !output.status.success() {
+        return Err(KhadimError::subprocess(
+            &format!("git {}", args.join(" ")),
+            dir,
+            output.status.code(),
+            &output.stderr,
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::Workspace;
+    use std::fs;
+
+    /// Creates a workspace where `web` depends on `ui`, which depends on
+    /// `core`, and `api` depends on nothing.
+    fn workspace() -> (tempfile::TempDir, Workspace) {
+        let root = tempfile::tempdir().unwrap();
+        for (dir, manifest) in [
+            ("libs/core", r#"{ "name": "@libs/core" }"#),
+            (
+                "libs/ui",
+                r#"{ "name": "@libs/ui", "dependencies": { "@libs/core": "^1" } }"#,
+            ),
+            (
+                "apps/web",
+                r#"{ "name": "web", "dependencies": { "@libs/ui": "file:../../libs/ui" } }"#,
+            ),
+            ("apps/api", r#"{ "name": "api" }"#),
+        ] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+            fs::write(root.path().join(dir).join("package.json"), manifest).unwrap();
+        }
+        let workspace = Workspace::discover(root.path()).unwrap();
+        (root, workspace)
+    }
+
+    fn affected(workspace: &Workspace, changed_files: &[&str]) -> Vec<String> {
+        let graph = DependencyGraph::build(workspace).unwrap();
+        let changed_files: Vec<PathBuf> = changed_files.iter().map(PathBuf::from).collect();
+        affected_projects(&graph, &changed_files)
+            .iter()
+            .map(|project| project.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn expands_to_transitive_dependents() {
+        let (_root, workspace) = workspace();
+        assert_eq!(
+            affected(&workspace, &["libs/core/index.ts"]),
+            ["core", "ui", "web"]
+        );
+        assert_eq!(
+            affected(&workspace, &["libs/ui/package.json"]),
+            ["ui", "web"]
+        );
+        assert_eq!(
+            affected(
+                &workspace,
+                &["apps/api/src/main.ts", "apps/web/next.config.js"]
+            ),
+            ["api", "web"]
+        );
+    }
+
+    #[test]
+    fn ignores_files_outside_projects() {
+        let (_root, workspace) = workspace();
+        assert!(affected(&workspace, &["README.md", "libs/core-old/index.ts"]).is_empty());
+    }
+
+    #[test]
+    fn template_changes_affect_every_project() {
+        let (_root, workspace) = workspace();
+        assert_eq!(
+            affected(&workspace, &["package-tmpl.json"]),
+            ["core", "api", "ui", "web"]
+        );
+    }
+}
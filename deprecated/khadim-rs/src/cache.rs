@@ -0,0 +1,617 @@
+use crate::affected::run_git;
+use crate::config::CACHE_DIR;
+use crate::dependencies::DEPENDENCY_KEYS;
+use crate::error::KhadimError;
+use crate::logger::LOG_DIR;
+use crate::workspace::Project;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directories that never contribute to a project's cache key: dependencies,
+/// virtualenvs, common build outputs and khadim's own cache and logs.
+const IGNORED_DIRS: [&str; 10] = [
+    "node_modules",
+    ".git",
+    "venv",
+    ".venv",
+    ".next",
+    "dist",
+    "coverage",
+    "__pycache__",
+    CACHE_DIR,
+    LOG_DIR,
+];
+
+const ENTRY_FILE: &str = "entry.json";
+const LOG_FILE: &str = "log.txt";
+const OUTPUTS_DIR: &str = "outputs";
+
+/// The metadata stored for a cached script run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub project: String,
+    pub script: String,
+    pub command: String,
+    pub exit_code: i32,
+    /// The output directories, relative to the project, that were saved with the entry.
+    pub outputs: Vec<String>,
+}
+
+/// A local cache of script runs, keyed by a hash of everything the run depends on.
+///
+/// Each entry is a directory named after its key that contains the entry
+/// metadata, the captured log and a copy of the project's declared output
+/// directories (the `project.outputs` list in its package.json, e.g.
+/// `[".next"]` or `["dist"]`).
+#[derive(Debug, Clone)]
+pub struct TaskCache {
+    dir: PathBuf,
+}
+
+impl TaskCache {
+    /// Creates a cache stored in the given directory. The directory is created on first write.
+    pub fn new(dir: &Path) -> TaskCache {
+        TaskCache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Computes the cache key for running a script in a project.
+    ///
+    /// The key is a SHA-256 hash over:
+    /// * The script name and command
+    /// * The content hash of the project, see `content_hash`
+    /// * The content hashes of every workspace project it depends on, directly
+    ///   or not, so that a change in a lib invalidates the apps built on top of
+    ///   it, whether or not the lib has the script itself
+    /// * The hash of the packages installed for the project according to the
+    ///   root package-lock.json, see `locked_packages_hash`
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - The project the script runs in.
+    /// * `script` - The name of the script.
+    /// * `content_hash` - The content hash of the project.
+    /// * `dependency_hashes` - The content hashes of its transitive dependencies,
+    ///   in a stable order such as `DependencyGraph::transitive_dependencies_of`.
+    /// * `locked_hash` - The hash of its locked packages, empty without a root lockfile.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the script is not defined.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::cache::TaskCache;
+    /// use khadim_rs::graph::DependencyGraph;
+    /// use khadim_rs::workspace::Workspace;
+    /// use std::path::Path;
+    ///
+    /// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+    /// let graph = DependencyGraph::build(&workspace).unwrap();
+    /// let project = workspace.find("hello").unwrap();
+    /// let dependency_hashes: Vec<String> = graph
+    ///     .transitive_dependencies_of(project)
+    ///     .into_iter()
+    ///     .map(|dependency| TaskCache::content_hash(dependency).unwrap())
+    ///     .collect();
+    /// let content_hash = TaskCache::content_hash(project).unwrap();
+    /// let lockfile = std::fs::read_to_string("/path/to/project/package-lock.json").unwrap();
+    /// let locked_hash =
+    ///     TaskCache::locked_packages_hash(&serde_json::from_str(&lockfile).unwrap(), project);
+    /// let key =
+    ///     TaskCache::key(project, "build", &content_hash, &dependency_hashes, &locked_hash)
+    ///         .unwrap();
+    /// println!("{}", key);
+    /// ```
+    pub fn key(
+        project: &Project,
+        script: &str,
+        content_hash: &str,
+        dependency_hashes: &[String],
+        locked_hash: &str,
+    ) -> Result<String, KhadimError> {
+        let command =
+            project.manifest.scripts.get(script).ok_or_else(|| {
+                format!("❌ Script '{}' not found in {} 😢", script, project.name)
+            })?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(script.as_bytes());
+        hasher.update([0]);
+        hasher.update(command.as_bytes());
+        hasher.update([0]);
+        hasher.update(content_hash.as_bytes());
+        for dependency_hash in dependency_hashes {
+            hasher.update([0]);
+            hasher.update(dependency_hash.as_bytes());
+        }
+        hasher.update([1]);
+        hasher.update(locked_hash.as_bytes());
+        Ok(hex(hasher))
+    }
+
+    /// Computes a SHA-256 hash over the entries of a root package-lock.json
+    /// that the project resolves to: the packages it declares, looked up the
+    /// way Node.js does from the project directory up to the root, then the
+    /// packages those depend on, following the links to workspace projects.
+    ///
+    /// Upgrading a package in the lockfile, even a transitive one, thus
+    /// changes the hash of the projects that use it, and only of those. Only
+    /// lockfiles with a `packages` section, from npm 7 on, are supported; the
+    /// hash of an older lockfile covers no entry.
+    pub fn locked_packages_hash(lockfile: &Value, project: &Project) -> String {
+        let empty = Map::new();
+        let packages = lockfile["packages"].as_object().unwrap_or(&empty);
+        let project_key = project
+            .relative_dir()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut pending: Vec<(String, String)> = DEPENDENCY_KEYS
+            .iter()
+            .filter_map(|dep_key| project.manifest.dependency_section(dep_key))
+            .flat_map(|deps| deps.keys())
+            .map(|name| (project_key.clone(), name.clone()))
+            .collect();
+        let mut locked: BTreeMap<&str, &Value> = BTreeMap::new();
+        while let Some((from, name)) = pending.pop() {
+            let Some((key, entry)) = resolve_locked(packages, &from, &name) else {
+                continue;
+            };
+            if locked.insert(key, entry).is_some() {
+                continue;
+            }
+            // A workspace project is linked, and its own entry lists its dependencies
+            let (dir, entry) = match entry["resolved"].as_str() {
+                Some(target) if entry["link"] == true => match packages.get_key_value(target) {
+                    Some((target, target_entry)) => {
+                        locked.insert(target, target_entry);
+                        (target.as_str(), target_entry)
+                    }
+                    None => continue,
+                },
+                _ => (key, entry),
+            };
+            for dep_key in DEPENDENCY_KEYS {
+                if let Some(deps) = entry[dep_key].as_object() {
+                    pending.extend(deps.keys().map(|name| (dir.to_string(), name.clone())));
+                }
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        for (key, entry) in locked {
+            hasher.update(key.as_bytes());
+            hasher.update([0]);
+            hasher.update(entry.to_string().as_bytes());
+            hasher.update([0]);
+        }
+        hex(hasher)
+    }
+
+    /// Computes a SHA-256 hash over every file in the project directory,
+    /// including its package.json and package-lock.json, except dependencies,
+    /// virtualenvs, build outputs and declared outputs.
+    ///
+    /// Inside a git repository, the files are the ones git tracks or would
+    /// track, so that gitignored files are left out as well.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a project file cannot be read.
+    pub fn content_hash(project: &Project) -> Result<String, KhadimError> {
+        let outputs = &project.manifest.project.outputs;
+        let mut files = match git_files(&project.path) {
+            Some(files) => files
+                .into_iter()
+                .filter(|file| is_input(file, outputs) && project.path.join(file).is_file())
+                .collect(),
+            None => {
+                let mut files = Vec::new();
+                collect_source_files(&project.path, &project.path, outputs, &mut files)?;
+                files
+            }
+        };
+        files.sort();
+
+        let mut hasher = Sha256::new();
+        for file in files {
+            hasher.update(file.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(fs::read(project.path.join(&file))?);
+            hasher.update([0]);
+        }
+        Ok(hex(hasher))
+    }
+
+    /// Returns the entry stored under the key, if any.
+    pub fn lookup(&self, key: &str) -> Option<CacheEntry> {
+        let content = fs::read_to_string(self.dir.join(key).join(ENTRY_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Returns the log captured when the entry was stored.
+    pub fn log(&self, key: &str) -> Result<Vec<u8>, KhadimError> {
+        Ok(fs::read(self.dir.join(key).join(LOG_FILE))?)
+    }
+
+    /// Stores an entry together with its log and the project's existing output directories.
+    ///
+    /// The entry is written to a temporary directory first and then moved in
+    /// place, so that an interrupted write never leaves a partial entry behind.
+    pub fn store(
+        &self,
+        key: &str,
+        project: &Project,
+        mut entry: CacheEntry,
+        log: &[u8],
+    ) -> Result<(), KhadimError> {
+        let entry_dir = self.dir.join(key);
+        let staging_dir = self.dir.join(format!("{}.tmp", key));
+        if staging_dir.exists() {
+            fs::remove_dir_all(&staging_dir)?;
+        }
+        fs::create_dir_all(staging_dir.join(OUTPUTS_DIR))?;
+
+        entry.outputs.clear();
+        for output in &project.manifest.project.outputs {
+            let source = project.path.join(output);
+            if source.is_dir() {
+                copy_dir(&source, &staging_dir.join(OUTPUTS_DIR).join(output))?;
+                entry.outputs.push(output.clone());
+            }
+        }
+
+        fs::write(staging_dir.join(LOG_FILE), log)?;
+        let entry_file = staging_dir.join(ENTRY_FILE);
+        let content = serde_json::to_string_pretty(&entry)
+            .map_err(|e| KhadimError::io(&entry_file, e.into()))?;
+        fs::write(&entry_file, content)?;
+
+        if entry_dir.exists() {
+            fs::remove_dir_all(&entry_dir)?;
+        }
+        fs::rename(&staging_dir, &entry_dir)?;
+        Ok(())
+    }
+
+    /// Replaces the project's output directories with the ones saved in the entry.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an output resolves outside the
+    /// project directory, e.g. through a symbolic link, or cannot be replaced.
+    pub fn restore_outputs(
+        &self,
+        key: &str,
+        project: &Project,
+        entry: &CacheEntry,
+    ) -> Result<(), KhadimError> {
+        for output in &entry.outputs {
+            let target = project.path.join(output);
+            if target.exists() {
+                if !is_inside(&project.path, &target)? {
+                    return Err(format!(
+                        "❌ Refusing to replace {}, it is outside {} 😢",
+                        target.display(),
+                        project.path.display()
+                    )
+                    .into());
+                }
+                fs::remove_dir_all(&target)?;
+            }
+            copy_dir(&self.dir.join(key).join(OUTPUTS_DIR).join(output), &target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if a path, with symbolic links resolved, is inside the directory.
+fn is_inside(dir: &Path, path: &Path) -> Result<bool, KhadimError> {
+    let dir = dir.canonicalize().map_err(|e| KhadimError::io(dir, e))?;
+    Ok(path
+        .canonicalize()
+        .map(|path| path.starts_with(&dir) && path != dir)
+        .unwrap_or(false))
+}
+
+/// Returns the digest of a hasher as lowercase hexadecimal.
+fn hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Lists the files git tracks or would track in a directory, relative to it,
+/// or `None` when the directory is not in a git repository.
+fn git_files(dir: &Path) -> Option<Vec<PathBuf>> {
+    let files = run_git(
+        dir,
+        &[
+            "-c",
+            "core.quotePath=false",
+            "ls-files",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+            "--",
+            ".",
+        ],
+    )
+    .ok()?;
+    Some(files.into_iter().map(PathBuf::from).collect())
+}
+
+/// Returns `true` if a file, relative to the project, is not inside an ignored
+/// directory or a declared output.
+fn is_input(relative: &Path, outputs: &[String]) -> bool {
+    let in_ignored_dir = relative.parent().is_some_and(|parent| {
+        parent.components().any(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .is_some_and(|name| IGNORED_DIRS.contains(&name))
+        })
+    });
+    let in_output = outputs
+        .iter()
+        .any(|output| relative.starts_with(output_path(output)));
+    !in_ignored_dir && !in_output
+}
+
+/// Returns a declared output without `.` components, e.g. `dist` for `./dist`.
+fn output_path(output: &str) -> PathBuf {
+    Path::new(output)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+/// Collects the paths, relative to `base`, of all files that make up a project's inputs.
+fn collect_source_files(
+    base: &Path,
+    dir: &Path,
+    outputs: &[String],
+    files: &mut Vec<PathBuf>,
+) -> Result<(), KhadimError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path
+            .strip_prefix(base)
+            .map_err(|e| KhadimError::Other(e.to_string()))?
+            .to_path_buf();
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+
+        if file_type.is_dir() {
+            let is_ignored = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| IGNORED_DIRS.contains(&name));
+            let is_output = outputs.iter().any(|output| relative == output_path(output));
+            if !is_ignored && !is_output {
+                collect_source_files(base, &path, outputs, files)?;
+            }
+        } else if file_type.is_file() {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Recursively copies a directory, creating the target if needed.
+fn copy_dir(source: &Path, target: &Path) -> Result<(), KhadimError> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target_path = target.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&path, &target_path)?;
+        } else {
+            fs::copy(&path, &target_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Finds the lockfile entry that a package required from `from`, a
+/// directory relative to the root, resolves to: the closest
+/// `node_modules/<name>` entry going up from `from` to the root.
+fn resolve_locked<'a>(
+    packages: &'a Map<String, Value>,
+    from: &str,
+    name: &str,
+) -> Option<(&'a str, &'a Value)> {
+    let mut dir = from;
+    loop {
+        let key = if dir.is_empty() {
+            format!("node_modules/{}", name)
+        } else {
+            format!("{}/node_modules/{}", dir, name)
+        };
+        if let Some((key, entry)) = packages.get_key_value(&key) {
+            return Some((key.as_str(), entry));
+        }
+        if dir.is_empty() {
+            return None;
+        }
+        dir = dir.rsplit_once('/').map_or("", |(parent, _)| parent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::Workspace;
+    use std::process::Command;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn hash_of_a(root: &Path) -> String {
+        let workspace = Workspace::discover(root).unwrap();
+        TaskCache::content_hash(workspace.find("a").unwrap()).unwrap()
+    }
+
+    fn fixture() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        write(
+            &root.path().join("libs/a/package.json"),
+            r#"{ "name": "a", "project": { "outputs": ["./build"] } }"#,
+        );
+        write(&root.path().join("libs/a/index.js"), "1\n");
+        root
+    }
+
+    #[test]
+    fn content_hash_ignores_build_artifacts_and_khadim_dirs() {
+        let root = fixture();
+        let a = root.path().join("libs/a");
+        let before = hash_of_a(root.path());
+
+        for dir in [
+            ".next",
+            "dist",
+            "coverage",
+            "__pycache__",
+            "node_modules/x",
+            ".khadim-cache",
+            ".khadim-logs",
+            "build",
+        ] {
+            write(&a.join(dir).join("artifact.txt"), dir);
+        }
+        assert_eq!(hash_of_a(root.path()), before);
+
+        write(&a.join("index.js"), "2\n");
+        assert_ne!(hash_of_a(root.path()), before);
+    }
+
+    #[test]
+    fn content_hash_ignores_gitignored_files() {
+        let root = fixture();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(root.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        git(&["init", "-q"]);
+        write(&root.path().join(".gitignore"), "*.log\n");
+        let before = hash_of_a(root.path());
+
+        write(&root.path().join("libs/a/debug.log"), "noise");
+        assert_eq!(hash_of_a(root.path()), before);
+
+        write(&root.path().join("libs/a/extra.js"), "3\n");
+        assert_ne!(hash_of_a(root.path()), before);
+    }
+
+    const LOCKFILE: &str = r#"{
+        "lockfileVersion": 3,
+        "packages": {
+            "": { "workspaces": ["apps/*", "libs/*"] },
+            "apps/web": { "dependencies": { "@libs/ui": "*", "react": "^18" } },
+            "apps/api": { "dependencies": { "react": "^17" } },
+            "apps/api/node_modules/react": { "version": "17.0.2" },
+            "libs/ui": { "dependencies": { "clsx": "^2" } },
+            "node_modules/@libs/ui": { "resolved": "libs/ui", "link": true },
+            "node_modules/clsx": { "version": "2.1.0" },
+            "node_modules/loose-envify": { "version": "1.4.0" },
+            "node_modules/lodash": { "version": "4.17.21" },
+            "node_modules/react": { "version": "18.3.1", "dependencies": { "loose-envify": "^1.1.0" } }
+        }
+    }"#;
+
+    /// Returns the locked packages hash of each project for a lockfile
+    /// edited with `edit`.
+    fn locked_hashes(edit: impl Fn(&mut Value)) -> BTreeMap<String, String> {
+        let root = tempfile::tempdir().unwrap();
+        write(
+            &root.path().join("apps/web/package.json"),
+            r#"{ "name": "web", "dependencies": { "@libs/ui": "*", "react": "^18" } }"#,
+        );
+        write(
+            &root.path().join("apps/api/package.json"),
+            r#"{ "name": "api", "peerDependencies": { "react": "^17" } }"#,
+        );
+        write(
+            &root.path().join("libs/ui/package.json"),
+            r#"{ "name": "@libs/ui", "dependencies": { "clsx": "^2" } }"#,
+        );
+        let workspace = Workspace::discover(root.path()).unwrap();
+        let mut lockfile: Value = serde_json::from_str(LOCKFILE).unwrap();
+        edit(&mut lockfile);
+        workspace
+            .projects
+            .iter()
+            .map(|project| {
+                let hash = TaskCache::locked_packages_hash(&lockfile, project);
+                (project.name.clone(), hash)
+            })
+            .collect()
+    }
+
+    /// Returns the projects whose locked packages hash changes with an upgrade.
+    fn changed_by(package: &str, version: &str) -> Vec<String> {
+        let before = locked_hashes(|_| {});
+        let after = locked_hashes(|lockfile| {
+            lockfile["packages"][package]["version"] = version.into();
+        });
+        before
+            .keys()
+            .filter(|name| before[*name] != after[*name])
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn locked_packages_hash_covers_the_resolved_packages() {
+        assert_eq!(changed_by("node_modules/react", "18.3.2"), ["web"]);
+        // Only reached through react
+        assert_eq!(changed_by("node_modules/loose-envify", "1.4.1"), ["web"]);
+        // Only reached through the link to libs/ui
+        assert_eq!(changed_by("node_modules/clsx", "2.1.1"), ["ui", "web"]);
+        // api resolves its own nested copy of react
+        assert_eq!(changed_by("apps/api/node_modules/react", "17.0.3"), ["api"]);
+        assert!(changed_by("node_modules/lodash", "4.17.22").is_empty());
+
+        let hashes = locked_hashes(|_| {});
+        let without_packages = locked_hashes(|lockfile| {
+            lockfile["packages"] = Value::Null;
+        });
+        assert_ne!(hashes["web"], without_packages["web"]);
+        assert_eq!(without_packages["web"], without_packages["api"]);
+    }
+
+    #[test]
+    fn key_changes_with_the_locked_packages() {
+        let root = fixture();
+        write(
+            &root.path().join("libs/a/package.json"),
+            r#"{ "name": "a", "scripts": { "build": "tsc" } }"#,
+        );
+        let workspace = Workspace::discover(root.path()).unwrap();
+        let a = workspace.find("a").unwrap();
+        let key = |locked_hash| TaskCache::key(a, "build", "content", &[], locked_hash).unwrap();
+
+        assert_eq!(key("locked"), key("locked"));
+        assert_ne!(key("locked"), key("upgraded"));
+        assert_ne!(key(""), key("locked"));
+    }
+}
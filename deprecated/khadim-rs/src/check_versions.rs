@@ -0,0 +1,270 @@
+use crate::error::KhadimError;
+use crate::outdated::{collect_declarations, stage_ranges, Declaration};
+use crate::output;
+use crate::root::find_root_dir;
+use crate::transaction::ManifestTransaction;
+use crate::version_range::VersionRange;
+use crate::workspace::Workspace;
+use log::info;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// A package declared with different ranges across the workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionConflict {
+    pub package: String,
+    /// Every declaration of the package, in workspace order.
+    pub declarations: Vec<DeclaredRange>,
+    /// The range all declarations should use.
+    pub suggested: String,
+}
+
+/// One declaration of a package in a conflict.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeclaredRange {
+    /// `root` for package-tmpl.json, or a project directory such as `apps/organic-lever-web`.
+    pub location: String,
+    /// `dependencies` or `devDependencies`.
+    pub dep_key: String,
+    pub range: String,
+}
+
+/// Finds the packages that package-tmpl.json and the apps and libs declare
+/// with different ranges.
+///
+/// The suggested range for each conflict is the declared range with the
+/// highest minimum version, preferring the most common one and then the first
+/// declared one on ties, so that
+/// unifying never downgrades a project. Only registry dependencies are
+/// compared; `file:`, git and `npm:` alias dependencies are skipped.
+///
+/// # Returns
+///
+/// * `Result<Vec<VersionConflict>, KhadimError>` - The conflicts, sorted by package name.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * package-tmpl.json or a project's package.json cannot be read or parsed
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::check_versions::{check_versions, print_conflicts};
+///
+/// let conflicts = check_versions().unwrap();
+/// print_conflicts(&conflicts);
+/// if !conflicts.is_empty() {
+///     std::process::exit(1);
+/// }
+/// ```
+pub fn check_versions() -> Result<Vec<VersionConflict>, KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let declarations = collect_declarations(&workspace)?;
+
+    let mut by_package: BTreeMap<&str, Vec<&Declaration>> = BTreeMap::new();
+    for declaration in &declarations {
+        by_package
+            .entry(declaration.package.as_str())
+            .or_default()
+            .push(declaration);
+    }
+
+    let mut conflicts = Vec::new();
+    for (package, declarations) in by_package {
+        let first = &declarations[0].range;
+        if declarations
+            .iter()
+            .all(|declaration| declaration.range == *first)
+        {
+            continue;
+        }
+
+        conflicts.push(VersionConflict {
+            package: package.to_string(),
+            suggested: suggest_range(&declarations),
+            declarations: declarations
+                .iter()
+                .map(|declaration| DeclaredRange {
+                    location: declaration.location.clone(),
+                    dep_key: declaration.dep_key.to_string(),
+                    range: declaration.range.clone(),
+                })
+                .collect(),
+        });
+    }
+    Ok(conflicts)
+}
+
+/// Prints each conflict with its declarations and the suggested range.
+pub fn print_conflicts(conflicts: &[VersionConflict]) {
+    output::report("check-versions", &conflicts);
+    if conflicts.is_empty() {
+        info!("✅ All dependency versions are consistent across the workspace 🎉");
+        return;
+    }
+
+    for conflict in conflicts {
+        info!(
+            "⚠️ {} is declared with {} different ranges:",
+            conflict.package,
+            distinct_ranges(conflict)
+        );
+        let location_width = conflict
+            .declarations
+            .iter()
+            .map(|declaration| declaration.location.len())
+            .max()
+            .unwrap_or(0);
+        for declaration in &conflict.declarations {
+            info!(
+                "    {:<location_width$}  {:<15}  {}",
+                declaration.location, declaration.dep_key, declaration.range
+            );
+        }
+        info!("  💡 Suggested range: {}", conflict.suggested);
+    }
+
+    info!(
+        "\n❌ {} packages have inconsistent versions 😢",
+        conflicts.len()
+    );
+}
+
+/// Rewrites every conflicting declaration to the suggested range.
+///
+/// Root declarations are written to both package-tmpl.json and package.json.
+/// Dependencies are not reinstalled; run `khadim-rs init` afterwards.
+///
+/// # Arguments
+///
+/// * `conflicts` - The conflicts found by `check_versions`.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * There are issues reading or writing a package.json or package-tmpl.json
+pub fn fix_versions(conflicts: &[VersionConflict]) -> Result<(), KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let declarations = collect_declarations(&workspace)?;
+
+    // New ranges, grouped by the directory of the manifest they belong to
+    let mut fixes: BTreeMap<PathBuf, Vec<(&Declaration, String)>> = BTreeMap::new();
+    for declaration in &declarations {
+        let conflict = conflicts
+            .iter()
+            .find(|conflict| conflict.package == declaration.package);
+        if let Some(conflict) = conflict {
+            if declaration.range != conflict.suggested {
+                fixes
+                    .entry(declaration.dir.clone())
+                    .or_default()
+                    .push((declaration, conflict.suggested.clone()));
+            }
+        }
+    }
+
+    let mut transaction = ManifestTransaction::new();
+    for (dir, changes) in &fixes {
+        stage_ranges(&mut transaction, &root_dir, dir, changes)?;
+        for (declaration, range) in changes {
+            info!(
+                "🔧 Unified {} {} → {} in {}",
+                declaration.package, declaration.range, range, declaration.location
+            );
+            output::dependency_changed(
+                &declaration.package,
+                "unified",
+                &declaration.location,
+                declaration.dep_key,
+                Some(&declaration.range),
+                Some(range),
+            );
+        }
+    }
+
+    transaction.commit()?;
+
+    info!("✅ Dependency versions unified, run `khadim-rs init` to reinstall 🎉");
+    Ok(())
+}
+
+/// Picks the declared range with the highest minimum version, then the most
+/// common one, then the one declared first, i.e. package-tmpl.json before the
+/// apps and libs.
+fn suggest_range(declarations: &[&Declaration]) -> String {
+    // (count, index of the first declaration) for each range
+    let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for (index, declaration) in declarations.iter().enumerate() {
+        counts
+            .entry(declaration.range.as_str())
+            .or_insert((0, index))
+            .0 += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(range, (count, first))| {
+            let min_version = VersionRange::parse(range)
+                .ok()
+                .and_then(|range| range.min_version());
+            (min_version, *count, Reverse(*first))
+        })
+        .map(|(range, _)| range.to_string())
+        .unwrap_or_default()
+}
+
+/// Counts the different ranges of a conflict.
+fn distinct_ranges(conflict: &VersionConflict) -> usize {
+    let mut ranges: Vec<&str> = conflict
+        .declarations
+        .iter()
+        .map(|declaration| declaration.range.as_str())
+        .collect();
+    ranges.sort_unstable();
+    ranges.dedup();
+    ranges.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggest(ranges: &[&str]) -> String {
+        let declarations: Vec<Declaration> = ranges
+            .iter()
+            .enumerate()
+            .map(|(index, range)| Declaration {
+                package: "react".to_string(),
+                location: format!("apps/app-{}", index),
+                dir: PathBuf::from(format!("apps/app-{}", index)),
+                dep_key: "dependencies",
+                range: range.to_string(),
+            })
+            .collect();
+        let declarations: Vec<&Declaration> = declarations.iter().collect();
+        suggest_range(&declarations)
+    }
+
+    #[test]
+    fn suggests_the_highest_minimum_version() {
+        assert_eq!(suggest(&["^18.2.0", "^18.3.1", "^18.2.0"]), "^18.3.1");
+        assert_eq!(suggest(&["^17", "18.x", ">=16 <19"]), "18.x");
+        assert_eq!(suggest(&["latest", "^1.0.0"]), "^1.0.0");
+    }
+
+    #[test]
+    fn breaks_ties_by_count_then_declaration_order() {
+        // All have the minimum version 18.2.0
+        assert_eq!(suggest(&["^18.2.0", "~18.2.0", "~18.2.0"]), "~18.2.0");
+        assert_eq!(suggest(&["~18.2.0", "^18.2.0"]), "~18.2.0");
+        assert_eq!(suggest(&["^18.2.0", "~18.2.0"]), "^18.2.0");
+        assert_eq!(suggest(&["18.2", "^18.2.0", "18.2.x"]), "18.2");
+    }
+}
@@ -0,0 +1,251 @@
+use crate::error::KhadimError;
+use crate::root::{find_root_dir, set_root_dir};
+use crate::workspace::Workspace;
+use clap::App;
+use clap_complete::{generate, Shell};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The hidden command that the completion scripts run to complete values
+/// that depend on the workspace, such as project and script names.
+pub const COMPLETE_COMMAND: &str = "__complete";
+
+/// The options that take a value, so that their value is not mistaken for a
+/// command or an argument.
+const OPTIONS_WITH_VALUE: [&str; 7] = [
+    "--root",
+    "--output",
+    "--save-prefix",
+    "--project",
+    "--base",
+    "--cache-dir",
+    "--only",
+];
+
+/// A value that is completed from the workspace rather than from the
+/// command-line definitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynamicValue {
+    /// The name of an app or lib, e.g. for `run` or `--project`.
+    Project,
+    /// The name of a script of the given project, e.g. for `run <project>`.
+    Script { project: String },
+    /// The name of a script of any project, e.g. for `run-all`.
+    AnyScript,
+}
+
+/// Generates the completion script of a shell, with the completion of
+/// project and script names added to what clap generates.
+///
+/// The script runs `khadim-rs __complete` with the words before the cursor
+/// to complete project and script names, and falls back to the generated
+/// completion when it exits with an error.
+///
+/// # Arguments
+///
+/// * `app` - The command-line definitions to complete.
+/// * `shell` - The shell to generate the script for.
+/// * `bin_name` - The name of the binary, e.g. `khadim-rs`.
+///
+/// # Errors
+///
+/// This function will return an error if the shell is not bash, zsh, fish or PowerShell.
+///
+/// # Examples
+///
+/// ```no_run
+/// use clap::App;
+/// use clap_complete::Shell;
+/// use khadim_rs::completions::generate_completions;
+///
+/// let mut app = App::new("khadim-rs").subcommand(App::new("init"));
+/// let script = generate_completions(&mut app, Shell::Bash, "khadim-rs").unwrap();
+/// println!("{}", script);
+/// ```
+pub fn generate_completions(
+    app: &mut App,
+    shell: Shell,
+    bin_name: &str,
+) -> Result<String, KhadimError> {
+    let mut script = Vec::new();
+    generate(shell, app, bin_name, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+
+    // The lookup goes first in the generated function, or is added next to the generated rules
+    let completed = match shell {
+        Shell::Bash => script.replacen(
+            &format!("_{}() {{\n", bin_name),
+            &format!(
+                "_{}() {{\n{}",
+                bin_name,
+                BASH_LOOKUP.replace("{bin}", bin_name)
+            ),
+            1,
+        ),
+        Shell::Zsh => script.replacen(
+            &format!("_{}() {{\n", bin_name),
+            &format!(
+                "_{}() {{\n{}",
+                bin_name,
+                ZSH_LOOKUP.replace("{bin}", bin_name)
+            ),
+            1,
+        ),
+        Shell::Fish => format!("{}{}", script, FISH_LOOKUP.replace("{bin}", bin_name)),
+        Shell::PowerShell => script.replacen(
+            "param($wordToComplete, $commandAst, $cursorPosition)\n",
+            &format!(
+                "param($wordToComplete, $commandAst, $cursorPosition)\n{}",
+                POWERSHELL_LOOKUP.replace("{bin}", bin_name)
+            ),
+            1,
+        ),
+        other => {
+            return Err(format!(
+                "❌ Completions for {} are not supported, use bash, zsh, fish or powershell 😢",
+                other
+            )
+            .into())
+        }
+    };
+    Ok(completed)
+}
+
+const BASH_LOOKUP: &str = r#"    local candidates
+    if candidates=$({bin} __complete -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null); then
+        COMPREPLY=($(compgen -W "${candidates}" -- "${COMP_WORDS[COMP_CWORD]}"))
+        return 0
+    fi
+
+"#;
+
+const ZSH_LOOKUP: &str = r#"    local candidates
+    if candidates=$({bin} __complete -- "${(@)words[2,CURRENT-1]}" 2>/dev/null); then
+        compadd -- ${(f)candidates}
+        return
+    fi
+
+"#;
+
+const FISH_LOOKUP: &str = r#"complete -c {bin} -f -a "({bin} __complete -- (commandline -opc)[2..-1] 2>/dev/null)"
+"#;
+
+const POWERSHELL_LOOKUP: &str = r#"
+    $words = @($commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object { $_.ToString() })
+    if ($wordToComplete -and $words.Count -gt 0) {
+        $words = @($words | Select-Object -SkipLast 1)
+    }
+    $candidates = @(& '{bin}' __complete -- @words 2>$null)
+    if ($LASTEXITCODE -eq 0) {
+        $candidates | Where-Object { $_ -like "$wordToComplete*" } | ForEach-Object {
+            [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)
+        }
+        return
+    }
+"#;
+
+/// Returns the value that the word after the given words completes to, if
+/// it depends on the workspace.
+///
+/// # Arguments
+///
+/// * `words` - The words of the command line before the cursor, without the binary name.
+///
+/// # Examples
+///
+/// ```
+/// use khadim_rs::completions::{dynamic_value, DynamicValue};
+///
+/// let words = ["-v".to_string(), "run".to_string(), "organic-lever-web".to_string()];
+/// assert_eq!(
+///     dynamic_value(&words),
+///     Some(DynamicValue::Script { project: "organic-lever-web".to_string() })
+/// );
+/// assert_eq!(dynamic_value(&["add".to_string(), "--project".to_string()]), Some(DynamicValue::Project));
+/// assert_eq!(dynamic_value(&["sync".to_string()]), None);
+/// ```
+pub fn dynamic_value(words: &[String]) -> Option<DynamicValue> {
+    let mut command = None;
+    let mut arguments = Vec::new();
+    let mut words = words.iter().peekable();
+
+    while let Some(word) = words.next() {
+        if word == "--" {
+            // Everything after `--` is passed on to the npm script
+            return None;
+        }
+        if OPTIONS_WITH_VALUE.contains(&word.as_str()) {
+            if words.next().is_none() {
+                // The cursor is on the value of this option
+                return (word == "--project").then_some(DynamicValue::Project);
+            }
+        } else if word.starts_with('-') {
+            continue;
+        } else if command.is_none() {
+            command = Some(word.as_str());
+        } else {
+            arguments.push(word);
+        }
+    }
+
+    match (command, arguments.as_slice()) {
+        (Some("run"), []) => Some(DynamicValue::Project),
+        (Some("run"), [project]) => Some(DynamicValue::Script {
+            project: project.to_string(),
+        }),
+        (Some("run-all"), []) => Some(DynamicValue::AnyScript),
+        _ => None,
+    }
+}
+
+/// Returns the candidates for the word after the given words, read from the
+/// workspace, or `None` if the word does not complete to a workspace value.
+///
+/// A `--root` among the words is used to find the workspace.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::completions::complete;
+///
+/// let words = vec!["run".to_string()];
+/// for project in complete(&words).unwrap_or_default() {
+///     println!("{}", project);
+/// }
+/// ```
+pub fn complete(words: &[String]) -> Option<Vec<String>> {
+    let value = dynamic_value(words)?;
+    if let Some(root_dir) = words
+        .iter()
+        .position(|word| word == "--root")
+        .and_then(|index| words.get(index + 1))
+    {
+        set_root_dir(Path::new(root_dir));
+    }
+    let workspace = find_root_dir()
+        .and_then(|root_dir| Workspace::discover(&root_dir))
+        .ok()?;
+
+    let candidates = match value {
+        DynamicValue::Project => workspace
+            .projects
+            .iter()
+            .map(|project| project.name.clone())
+            .collect(),
+        DynamicValue::Script { project } => workspace
+            .find(&project)?
+            .manifest
+            .scripts
+            .keys()
+            .cloned()
+            .collect(),
+        DynamicValue::AnyScript => workspace
+            .projects
+            .iter()
+            .flat_map(|project| project.manifest.scripts.keys().cloned())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect(),
+    };
+    Some(candidates)
+}
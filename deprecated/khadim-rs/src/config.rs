@@ -1,4 +1,239 @@
+use crate::error::KhadimError;
+use crate::workspace::{Project, ProjectKind};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path};
+
 pub const PACKAGE_JSON: &str = "package.json";
 pub const PACKAGE_TMPL_JSON: &str = "package-tmpl.json";
-pub const APPS_DIR: &str = "apps";
-pub const LIBS_DIR: &str = "libs";
+pub const PACKAGE_LOCK_JSON: &str = "package-lock.json";
+pub const CONFIG_FILE: &str = "khadim.json";
+pub const CACHE_DIR: &str = ".khadim-cache";
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+/// The settings of a monorepo, read from `khadim.json` at its root.
+///
+/// Every key is optional, and the keys of the F# sibling's `config.json`
+/// (`maxParallelism`, `appsDir` and `libsDir`) mean the same thing here.
+///
+/// ```json
+/// {
+///   "appsDir": "apps",
+///   "libsDir": "libs",
+///   "maxParallelism": 4,
+///   "projects": { "include": ["*"], "exclude": ["apps/legacy-*"] },
+///   "devScript": { "exclude": ["*-e2e"] },
+///   "tools": ["volta", "npm", "node"]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct Config {
+    /// The directory, relative to the root, that holds the application projects.
+    pub apps_dir: String,
+    /// The directory, relative to the root, that holds the library projects.
+    pub libs_dir: String,
+    /// The most projects handled at once, or 0 for all CPU cores but one.
+    pub max_parallelism: usize,
+    /// Which apps and libs are part of the workspace.
+    pub projects: ProjectFilter,
+    /// The root `dev` script generated by `init`.
+    pub dev_script: DevScript,
+    /// The commands checked by `doctor`, each run with `--version`.
+    pub tools: Vec<String>,
+}
+
+/// Names or globs of the projects to include in, and exclude from, the workspace.
+///
+/// Patterns match a project's directory name, npm package name or relative
+/// directory, as with `--project`, e.g. `organic-lever-web`, `apps/*` or `*-web`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectFilter {
+    /// Only these projects are included; every project when empty.
+    pub include: Vec<String>,
+    /// These projects are left out, even when included.
+    pub exclude: Vec<String>,
+}
+
+/// The settings of the root `dev` script, which runs every app's `dev` script at once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DevScript {
+    /// Names or globs of the apps left out of the `dev` script, such as end-to-end test apps.
+    pub exclude: Vec<String>,
+}
+
+impl Default for DevScript {
+    fn default() -> DevScript {
+        DevScript {
+            exclude: vec!["*-e2e".to_string()],
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            apps_dir: "apps".to_string(),
+            libs_dir: "libs".to_string(),
+            max_parallelism: 0,
+            projects: ProjectFilter::default(),
+            dev_script: DevScript::default(),
+            tools: vec!["volta".to_string(), "npm".to_string(), "node".to_string()],
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the `khadim.json` of a root directory, or returns
+    /// the default configuration if there is none.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The root directory of the monorepo.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * The file cannot be read
+    /// * The file is not valid JSON, or has an unknown key or a value of the wrong type
+    /// * A value is invalid, see `validate`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::config::Config;
+    /// use std::path::Path;
+    ///
+    /// let config = Config::load(Path::new("/path/to/project")).unwrap();
+    /// println!("Apps are in {}", config.apps_dir);
+    /// ```
+    pub fn load(root_dir: &Path) -> Result<Config, KhadimError> {
+        let path = root_dir.join(CONFIG_FILE);
+        if !path.exists() {
+            debug!(
+                "No {} in {}, using defaults",
+                CONFIG_FILE,
+                root_dir.display()
+            );
+            return Ok(Config::default());
+        }
+
+        debug!("Reading configuration from {}", path.display());
+        let content = fs::read_to_string(&path).map_err(|e| KhadimError::io(&path, e))?;
+        let config: Config =
+            serde_json::from_str(&content).map_err(|e| KhadimError::InvalidConfig {
+                path: path.clone(),
+                problems: vec![e.to_string()],
+            })?;
+
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(KhadimError::InvalidConfig { path, problems });
+        }
+        Ok(config)
+    }
+
+    /// Returns every problem with the values of the configuration, or an empty
+    /// list if it is valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::config::Config;
+    ///
+    /// let config = Config {
+    ///     libs_dir: "../libs".to_string(),
+    ///     ..Config::default()
+    /// };
+    /// assert_eq!(
+    ///     config.validate(),
+    ///     ["libsDir must be a directory inside the root, got '../libs'"]
+    /// );
+    /// ```
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (key, dir) in [("appsDir", &self.apps_dir), ("libsDir", &self.libs_dir)] {
+            let is_inside_root = !dir.trim().is_empty()
+                && Path::new(dir)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !is_inside_root {
+                problems.push(format!(
+                    "{} must be a directory inside the root, got '{}'",
+                    key, dir
+                ));
+            }
+        }
+        if Path::new(&self.apps_dir) == Path::new(&self.libs_dir) {
+            problems.push(format!(
+                "appsDir and libsDir must be different directories, both are '{}'",
+                self.apps_dir
+            ));
+        }
+
+        let patterns = [
+            ("projects.include", &self.projects.include),
+            ("projects.exclude", &self.projects.exclude),
+            ("devScript.exclude", &self.dev_script.exclude),
+        ];
+        for (key, patterns) in patterns {
+            for pattern in patterns {
+                if let Err(e) = glob::Pattern::new(pattern) {
+                    problems.push(format!(
+                        "{} has an invalid pattern '{}': {}",
+                        key, pattern, e
+                    ));
+                }
+            }
+        }
+
+        if self.tools.iter().any(|tool| tool.trim().is_empty()) {
+            problems.push("tools must not contain an empty command".to_string());
+        }
+
+        problems
+    }
+
+    /// Returns the directory, relative to the root, that holds projects of a kind.
+    pub fn dir_of(&self, kind: ProjectKind) -> &str {
+        match kind {
+            ProjectKind::Lib => &self.libs_dir,
+            ProjectKind::App => &self.apps_dir,
+        }
+    }
+
+    /// Returns the number of worker threads for parallel steps: `maxParallelism`,
+    /// or all CPU cores but one when it is 0.
+    pub fn max_workers(&self) -> usize {
+        match self.max_parallelism {
+            0 => std::cmp::max(1, num_cpus::get().saturating_sub(1)),
+            max_parallelism => max_parallelism,
+        }
+    }
+
+    /// Returns `true` if a project is included by `projects.include` and not
+    /// excluded by `projects.exclude`.
+    pub fn includes(&self, project: &Project) -> bool {
+        let is_included =
+            self.projects.include.is_empty() || matches_any(project, &self.projects.include);
+        is_included && !matches_any(project, &self.projects.exclude)
+    }
+
+    /// Returns `true` if an app's `dev` script belongs in the root `dev` script.
+    pub fn in_dev_script(&self, app: &Project) -> bool {
+        !matches_any(app, &self.dev_script.exclude)
+    }
+}
+
+/// Returns `true` if a project matches one of the patterns. Invalid patterns,
+/// which `validate` reports, match nothing.
+fn matches_any(project: &Project, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+        .any(|pattern| project.matches(&pattern))
+}
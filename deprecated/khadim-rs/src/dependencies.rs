@@ -1,9 +1,18 @@
-use crate::config::{PACKAGE_JSON, PACKAGE_TMPL_JSON};
-use crate::BoxError;
-use serde_json::{json, Value};
+use crate::config::{PACKAGE_JSON, PACKAGE_LOCK_JSON, PACKAGE_TMPL_JSON};
+use crate::error::KhadimError;
+use crate::init::run_npm_install;
+use crate::json_file::{insert_sorted, remove_entry, JsonFile};
+use crate::output;
+use crate::package_spec::{PackageSpec, SavePrefix};
+use crate::registry::RegistryClient;
+use crate::root::find_root_dir;
+use crate::transaction::ManifestTransaction;
+use crate::workspace::{Project, Workspace};
+use log::info;
+use rayon::prelude::*;
+use serde_json::json;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 
 /// Adds dependencies to the project's package.json and package-tmpl.json files.
 ///
@@ -12,46 +21,62 @@ use std::process::Command;
 /// that the 'concurrently' package is added as a dev dependency if regular
 /// dependencies are being added.
 ///
+/// When a target project is given, the packages are added to the package.json
+/// of every app or lib it matches instead, and `npm install` only runs in those
+/// project directories.
+///
 /// # Arguments
 ///
-/// * `packages` - A vector of string slices representing the packages to be added.
+/// * `packages` - A vector of npm-style specifiers of the packages to be added,
+///   e.g. `react`, `react@18.2.0`, `next@~14.2`, `@scope/pkg@1`,
+///   `hello@file:../hello`, `tool@git+https://...` or `react18@npm:react@18`.
 /// * `is_dev` - A boolean indicating whether the packages should be added as
-///              development dependencies (true) or regular dependencies (false).
+///   development dependencies (true) or regular dependencies (false).
+/// * `save_prefix` - The prefix used for versions resolved from a dist-tag or
+///   an exact version. Ranges are saved as given.
+/// * `project` - An optional project name, package name or glob such as
+///   `organic-lever-web` or `apps/*`. `None` targets the root.
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if the dependencies are
+/// * `Result<(), KhadimError>` - Ok(()) if the dependencies are
 ///   added successfully, or an error if any part of the process fails.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 /// * The root directory cannot be found
+/// * A package specifier is invalid
+/// * The target project pattern matches no app or lib
 /// * There are issues reading or writing to package.json or package-tmpl.json
-/// * Fetching the latest version of a package fails
+/// * Resolving the version of a package fails
 ///
 /// # Example
 ///
-/// ```
-/// use khadim-rs::dependencies::add_dependencies;
+/// ```no_run
+/// use khadim_rs::dependencies::add_dependencies;
+/// use khadim_rs::package_spec::SavePrefix;
 ///
-/// let packages = vec!["package1", "package2"];
-/// let result = add_dependencies(packages, false);
+/// let packages = vec!["next@14.2.8", "@tanstack/react-query@^5"];
+/// let result = add_dependencies(packages, false, SavePrefix::Exact, None);
 /// // Note: This test might fail in a CI environment without npm
 /// // assert!(result.is_ok());
+///
+/// // Only in apps/organic-lever-web
+/// let result = add_dependencies(vec!["zod"], false, SavePrefix::Caret, Some("organic-lever-web"));
 /// ```
-pub fn add_dependencies(packages: Vec<&str>, is_dev: bool) -> Result<(), BoxError> {
-    let root_dir = find_root_dir()?;
-    let package_json_path = root_dir.join(PACKAGE_JSON);
-    let package_tmpl_json_path = root_dir.join(PACKAGE_TMPL_JSON);
+pub fn add_dependencies(
+    packages: Vec<&str>,
+    is_dev: bool,
+    save_prefix: SavePrefix,
+    project: Option<&str>,
+) -> Result<(), KhadimError> {
+    let specs = packages
+        .into_iter()
+        .map(PackageSpec::parse)
+        .collect::<Result<Vec<_>, _>>()?;
 
-    // Read and parse package.json
-    let package_json_content = fs::read_to_string(&package_json_path)?;
-    let mut package_json: Value = serde_json::from_str(&package_json_content)?;
-
-    // Read and parse package-tmpl.json
-    let package_tmpl_json_content = fs::read_to_string(&package_tmpl_json_path)?;
-    let mut package_tmpl_json: Value = serde_json::from_str(&package_tmpl_json_content)?;
+    let root_dir = find_root_dir()?;
 
     // Determine which dependency object to update
     let dep_key = if is_dev {
@@ -60,195 +85,375 @@ pub fn add_dependencies(packages: Vec<&str>, is_dev: bool) -> Result<(), BoxErro
         "dependencies"
     };
 
-    // Update package.json and package-tmpl.json
-    for package in packages {
-        let version = get_latest_version(package)?;
-        let version_string = format!("^{}", version);
+    // Resolve all versions concurrently
+    let registry = RegistryClient::for_root(&root_dir);
+    let versions = specs
+        .par_iter()
+        .map(|spec| spec.resolve(&registry, save_prefix))
+        .collect::<Result<Vec<_>, _>>()?;
 
-        if let Some(deps) = package_json[dep_key].as_object_mut() {
-            deps.insert(package.to_string(), json!(version_string));
-        } else {
-            package_json[dep_key] = json!({ package: version_string });
-        }
+    if let Some(pattern) = project {
+        let workspace = Workspace::discover(&root_dir)?;
+        let projects = workspace.select(pattern)?;
+        add_to_projects(&projects, &specs, &versions, dep_key, run_npm_install)?;
 
-        if let Some(deps) = package_tmpl_json[dep_key].as_object_mut() {
-            deps.insert(package.to_string(), json!(version_string));
-        } else {
-            package_tmpl_json[dep_key] = json!({ package: version_string });
-        }
+        info!("✅ Dependencies added and installed successfully! 🎉");
+        return Ok(());
+    }
+
+    let package_json_path = root_dir.join(PACKAGE_JSON);
+    let package_tmpl_json_path = root_dir.join(PACKAGE_TMPL_JSON);
+
+    // Read and parse package.json
+    let mut package_json = JsonFile::read(&package_json_path)?;
 
-        println!("✅ Added {} {} to {} 📦", package, version_string, dep_key);
+    // Read and parse package-tmpl.json
+    let mut package_tmpl_json = JsonFile::read(&package_tmpl_json_path)?;
+
+    // Update package.json and package-tmpl.json
+    for (spec, version_string) in specs.iter().zip(&versions) {
+        let package = spec.name.as_str();
+        insert_sorted(
+            &mut package_json.value,
+            dep_key,
+            package,
+            json!(version_string),
+        );
+        insert_sorted(
+            &mut package_tmpl_json.value,
+            dep_key,
+            package,
+            json!(version_string),
+        );
+        info!("✅ Added {} {} to {} 📦", package, version_string, dep_key);
+        output::dependency_changed(
+            package,
+            "added",
+            "root",
+            dep_key,
+            None,
+            Some(version_string),
+        );
     }
 
     // Add concurrently as a dev dependency
     if !is_dev {
-        let concurrently_version = get_latest_version("concurrently")?;
-        if let Some(dev_deps) = package_json["devDependencies"].as_object_mut() {
-            dev_deps.insert(
-                "concurrently".to_string(),
-                json!(format!("^{}", concurrently_version)),
-            );
-        } else {
-            package_json["devDependencies"] =
-                json!({ "concurrently": format!("^{}", concurrently_version) });
-        }
-        if let Some(dev_deps) = package_tmpl_json["devDependencies"].as_object_mut() {
-            dev_deps.insert(
-                "concurrently".to_string(),
-                json!(format!("^{}", concurrently_version)),
-            );
-        } else {
-            package_tmpl_json["devDependencies"] =
-                json!({ "concurrently": format!("^{}", concurrently_version) });
-        }
-        println!(
+        let concurrently_version = format!("^{}", registry.resolve("concurrently", "latest")?);
+        insert_sorted(
+            &mut package_json.value,
+            "devDependencies",
+            "concurrently",
+            json!(concurrently_version),
+        );
+        insert_sorted(
+            &mut package_tmpl_json.value,
+            "devDependencies",
+            "concurrently",
+            json!(concurrently_version),
+        );
+        info!(
             "✅ Added concurrently {} to devDependencies 📦",
             concurrently_version
         );
+        output::dependency_changed(
+            "concurrently",
+            "added",
+            "root",
+            "devDependencies",
+            None,
+            Some(&concurrently_version),
+        );
     }
 
-    // Write updated package.json
-    let updated_package_json = serde_json::to_string_pretty(&package_json)?;
-    fs::write(package_json_path, updated_package_json)?;
+    // Write updated package.json and package-tmpl.json, then run npm install,
+    // restoring both files and the lockfile if anything fails
+    let mut transaction = ManifestTransaction::new();
+    transaction.stage(&package_json)?;
+    transaction.stage(&package_tmpl_json)?;
+    transaction.protect(&root_dir.join(PACKAGE_LOCK_JSON));
+    transaction.commit_and_then(|| run_npm_install(&root_dir))?;
 
-    // Write updated package-tmpl.json
-    let updated_package_tmpl_json = serde_json::to_string_pretty(&package_tmpl_json)?;
-    fs::write(package_tmpl_json_path, updated_package_tmpl_json)?;
+    info!("✅ Dependencies added and installed successfully! 🎉");
+    Ok(())
+}
 
-    // Run npm install
-    run_npm_install(&root_dir)?;
+/// Adds the resolved packages to the package.json of each project, then runs
+/// `install` in every project directory.
+///
+/// The package.json files and lockfiles of all the projects are restored if
+/// writing any of them, or any install, fails.
+fn add_to_projects<F>(
+    projects: &[&Project],
+    specs: &[PackageSpec],
+    versions: &[String],
+    dep_key: &str,
+    install: F,
+) -> Result<(), KhadimError>
+where
+    F: Fn(&Path) -> Result<(), KhadimError>,
+{
+    let mut transaction = ManifestTransaction::new();
+    for project in projects {
+        let mut package_json = JsonFile::read(&project.path.join(PACKAGE_JSON))?;
 
-    println!("✅ Dependencies added and installed successfully! 🎉");
-    Ok(())
+        for (spec, version_string) in specs.iter().zip(versions) {
+            insert_sorted(
+                &mut package_json.value,
+                dep_key,
+                &spec.name,
+                json!(version_string),
+            );
+            info!(
+                "✅ Added {} {} to {} of {} 📦",
+                spec.name,
+                version_string,
+                dep_key,
+                project.relative_dir().display()
+            );
+            output::dependency_changed(
+                &spec.name,
+                "added",
+                &project.relative_dir().to_string_lossy(),
+                dep_key,
+                None,
+                Some(version_string),
+            );
+        }
+
+        transaction.stage(&package_json)?;
+        transaction.protect(&project.path.join(PACKAGE_LOCK_JSON));
+    }
+
+    // Every project is rolled back if any install fails
+    transaction.commit_and_then(|| {
+        for project in projects {
+            install(&project.path)?;
+        }
+        Ok(())
+    })
 }
 
-/// Retrieves the latest version of a specified npm package.
+/// The package.json sections that can declare a dependency.
+pub(crate) const DEPENDENCY_KEYS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "optionalDependencies",
+    "peerDependencies",
+];
+
+/// Source file extensions scanned for imports of a removed package.
+const SOURCE_EXTENSIONS: [&str; 6] = ["js", "jsx", "mjs", "cjs", "ts", "tsx"];
+
+/// Directories skipped when scanning for imports.
+const SKIPPED_DIRS: [&str; 6] = ["node_modules", ".next", "dist", "out", "venv", ".git"];
+
+/// Removes dependencies from the project's package.json and package-tmpl.json files.
 ///
-/// This function uses the `npm view` command to fetch the latest version
-/// of the specified package from the npm registry.
+/// This function deletes each package from `dependencies`, `devDependencies`,
+/// `optionalDependencies` and `peerDependencies` of both files, warns about
+/// workspace projects that still declare or import the package, and then runs
+/// `npm install` once in the root directory.
 ///
 /// # Arguments
 ///
-/// * `package` - A string slice that holds the name of the npm package.
+/// * `packages` - The names of the packages to remove.
 ///
 /// # Returns
 ///
-/// * `Result<String, BoxError>` - A Result containing either:
-///   - `Ok(String)`: The latest version of the package as a String.
-///   - `Err(BoxError)`: An error if the version couldn't be retrieved.
+/// * `Result<(), KhadimError>` - Ok(()) if the dependencies are removed
+///   successfully, or an error if any part of the process fails.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
-/// * The `npm view` command fails to execute.
-/// * The command output cannot be parsed as UTF-8.
-/// * The npm registry returns an error for the specified package.
+/// * The root directory cannot be found
+/// * None of the packages is declared in package.json or package-tmpl.json
+/// * There are issues reading or writing to package.json or package-tmpl.json
+/// * The npm install process fails
 ///
 /// # Example
 ///
-/// ```
-/// use khadim-rs::dependencies::get_latest_version;
+/// ```no_run
+/// use khadim_rs::dependencies::remove_dependencies;
 ///
-/// let result = get_latest_version("package_name");
-/// assert!(result.is_ok());
+/// let result = remove_dependencies(vec!["react-query"]);
 /// ```
-pub fn get_latest_version(package: &str) -> Result<String, BoxError> {
-    let output = Command::new("npm")
-        .args(&["view", package, "version"])
-        .output()?;
+pub fn remove_dependencies(packages: Vec<&str>) -> Result<(), KhadimError> {
+    let root_dir = find_root_dir()?;
+    let package_json_path = root_dir.join(PACKAGE_JSON);
+    let package_tmpl_json_path = root_dir.join(PACKAGE_TMPL_JSON);
 
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
-    } else {
-        Err(format!("Failed to get latest version for {}", package).into())
+    // Read and parse package.json
+    let mut package_json = JsonFile::read(&package_json_path)?;
+
+    // Read and parse package-tmpl.json
+    let mut package_tmpl_json = JsonFile::read(&package_tmpl_json_path)?;
+
+    let mut removed_any = false;
+    for package in &packages {
+        let mut removed_from = Vec::new();
+        for dep_key in DEPENDENCY_KEYS {
+            let in_package_json = remove_entry(&mut package_json.value, dep_key, package);
+            let in_package_tmpl_json = remove_entry(&mut package_tmpl_json.value, dep_key, package);
+            if in_package_json || in_package_tmpl_json {
+                removed_from.push(dep_key);
+            }
+        }
+
+        if removed_from.is_empty() {
+            output::warn(&format!("{} is not a dependency, skipping", package));
+        } else {
+            removed_any = true;
+            info!("🗑️ Removed {} from {}", package, removed_from.join(", "));
+            for dep_key in &removed_from {
+                output::dependency_changed(package, "removed", "root", dep_key, None, None);
+            }
+        }
+    }
+
+    if !removed_any {
+        return Err(format!(
+            "❌ None of {} is declared in {} or {} 😢",
+            packages.join(", "),
+            PACKAGE_JSON,
+            PACKAGE_TMPL_JSON
+        )
+        .into());
     }
+
+    // Warn about projects that still need the package
+    let workspace = Workspace::discover(&root_dir)?;
+    for package in &packages {
+        for usage in find_usages(&workspace, package) {
+            output::warn(&format!("{} is still used: {}", package, usage));
+        }
+    }
+
+    // Write updated package.json and package-tmpl.json, then run npm install,
+    // restoring both files and the lockfile if anything fails
+    let mut transaction = ManifestTransaction::new();
+    transaction.stage(&package_json)?;
+    transaction.stage(&package_tmpl_json)?;
+    transaction.protect(&root_dir.join(PACKAGE_LOCK_JSON));
+    transaction.commit_and_then(|| run_npm_install(&root_dir))?;
+
+    info!("✅ Dependencies removed and reinstalled successfully! 🎉");
+    Ok(())
 }
 
-/// Finds the root directory of the project.
-///
-/// This function searches for the root directory by looking for the 'package-tmpl.json' file
-/// in the current directory and its ancestors.
-///
-/// # Returns
-///
-/// * `Result<PathBuf, BoxError>` - The path to the root directory if found,
-///   or an error if the root directory cannot be determined.
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// * The current directory cannot be determined
-/// * The root directory (containing 'package-tmpl.json') is not found in the current directory or its ancestors
-///
-/// # Examples
-///
-/// ```
-/// use khadim-rs::dependencies::find_root_dir;
-///
-/// let result = find_root_dir();
-/// assert!(result.is_ok());
-/// ```
-pub fn find_root_dir() -> Result<PathBuf, BoxError> {
-    let current_dir = std::env::current_dir()?;
-    current_dir
-        .ancestors()
-        .find(|p| p.join(PACKAGE_TMPL_JSON).exists())
-        .map(|p| p.to_path_buf())
-        .ok_or_else(|| BoxError::from("❌ Cannot find root directory 😢"))
+/// Describes where workspace projects still declare or import a package.
+fn find_usages(workspace: &Workspace, package: &str) -> Vec<String> {
+    let mut usages = Vec::new();
+    for project in &workspace.projects {
+        for dep_key in DEPENDENCY_KEYS {
+            let declared = project
+                .manifest
+                .dependency_section(dep_key)
+                .is_some_and(|deps| deps.contains_key(package));
+            if declared {
+                usages.push(format!(
+                    "declared in {} {}",
+                    project.relative_dir().join(PACKAGE_JSON).display(),
+                    dep_key
+                ));
+            }
+        }
+
+        let mut files = Vec::new();
+        find_importing_files(&project.path, package, &mut files);
+        for file in files {
+            let relative = file.strip_prefix(&workspace.root_dir).unwrap_or(&file);
+            usages.push(format!("imported in {}", relative.display()));
+        }
+    }
+    usages
+}
+
+/// Recursively collects the source files that import a package or one of its subpaths.
+fn find_importing_files(dir: &Path, package: &str, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_ref()) {
+                find_importing_files(&path, package, files);
+            }
+        } else if path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| SOURCE_EXTENSIONS.contains(&extension))
+        {
+            let imports = fs::read_to_string(&path)
+                .map(|content| imports_package(&content, package))
+                .unwrap_or(false);
+            if imports {
+                files.push(path);
+            }
+        }
+    }
 }
 
-/// Runs `npm install` in the specified directory.
+/// Returns `true` if the source imports or requires the package, e.g.
+/// `from "react"`, `require('react')`, `import("react/jsx-runtime")`.
+fn imports_package(content: &str, package: &str) -> bool {
+    ['"', '\'', '`'].iter().any(|quote| {
+        [
+            format!("{}{}{}", quote, package, quote),
+            format!("{}{}/", quote, package),
+        ]
+        .iter()
+        .any(|needle| {
+            content.match_indices(needle.as_str()).any(|(index, _)| {
+                let before = content[..index].trim_end();
+                before.ends_with("from")
+                    || before.ends_with("require(")
+                    || before.ends_with("import(")
+                    || before.ends_with("import")
+            })
+        })
+    })
+}
+
+/// Retrieves the latest version of a specified npm package.
 ///
-/// This function executes the `npm install` command in the given directory,
-/// which installs all dependencies specified in the package.json file.
+/// This function queries the npm registry for the package's `latest` dist-tag
+/// using a `RegistryClient` for the project root (or the current directory if
+/// no root is found). When the registry cannot be reached, the cached registry
+/// metadata or the version installed according to the lockfile is used instead.
 ///
 /// # Arguments
 ///
-/// * `dir` - A reference to a `PathBuf` representing the directory where `npm install` should be run.
+/// * `package` - A string slice that holds the name of the npm package.
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if the installation is successful,
-///   or an error if the installation fails.
+/// * `Result<String, KhadimError>` - A Result containing either:
+///   - `Ok(String)`: The latest version of the package as a String.
+///   - `Err(KhadimError)`: An error if the version couldn't be retrieved.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
-/// * The `npm install` command fails to execute
-/// * The command executes but returns a non-zero exit status
+/// * The registry cannot be reached and no cached or locked version exists.
+/// * The registry does not know the package.
 ///
-/// # Examples
+/// # Example
 ///
-/// ```
-/// use khadim-rs::init::run_npm_install;
-/// use std::path::Path;
+/// ```no_run
+/// use khadim_rs::dependencies::get_latest_version;
 ///
-/// let project_dir = Path::new(".");
-/// let result = run_npm_install(&project_dir);
+/// let result = get_latest_version("package_name");
 /// assert!(result.is_ok());
 /// ```
-fn run_npm_install(dir: &PathBuf) -> Result<(), BoxError> {
-    println!("🛠️ Running npm install in {}...", dir.display());
-    let output = Command::new("npm")
-        .arg("install")
-        .current_dir(dir)
-        .output()?;
-
-    if output.status.success() {
-        println!(
-            "✅ npm install completed successfully in {} 🎉",
-            dir.display()
-        );
-        Ok(())
-    } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        Err(format!(
-            "❌ npm install failed in {}: {} 😢",
-            dir.display(),
-            error_message
-        )
-        .into())
-    }
+pub fn get_latest_version(package: &str) -> Result<String, KhadimError> {
+    let root_dir = find_root_dir().or_else(|_| std::env::current_dir())?;
+    RegistryClient::for_root(&root_dir).resolve(package, "latest")
 }
 
 /// Adds a development dependency to the project's package.json and package-tmpl.json files.
@@ -263,7 +468,7 @@ fn run_npm_install(dir: &PathBuf) -> Result<(), BoxError> {
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if the dependency is added successfully,
+/// * `Result<(), KhadimError>` - Ok(()) if the dependency is added successfully,
 ///   or an error if any part of the process fails.
 ///
 /// # Errors
@@ -275,53 +480,272 @@ fn run_npm_install(dir: &PathBuf) -> Result<(), BoxError> {
 ///
 /// # Example
 ///
-/// ```
-/// use khadim-rs::dependencies::add_dev_dependency;
+/// ```no_run
+/// use khadim_rs::dependencies::add_dev_dependency;
 ///
 /// let result = add_dev_dependency("jest");
 /// assert!(result.is_ok());
 /// ```
-pub fn add_dev_dependency(package: &str) -> Result<(), BoxError> {
+pub fn add_dev_dependency(package: &str) -> Result<(), KhadimError> {
     let root_dir = find_root_dir()?;
     let package_json_path = root_dir.join(PACKAGE_JSON);
     let package_tmpl_json_path = root_dir.join(PACKAGE_TMPL_JSON);
 
     // Read and parse package.json
-    let package_json_content = fs::read_to_string(&package_json_path)?;
-    let mut package_json: Value = serde_json::from_str(&package_json_content)?;
+    let mut package_json = JsonFile::read(&package_json_path)?;
 
     // Read and parse package-tmpl.json
-    let package_tmpl_json_content = fs::read_to_string(&package_tmpl_json_path)?;
-    let mut package_tmpl_json: Value = serde_json::from_str(&package_tmpl_json_content)?;
+    let mut package_tmpl_json = JsonFile::read(&package_tmpl_json_path)?;
 
-    let version = get_latest_version(package)?;
+    let version = RegistryClient::for_root(&root_dir).resolve(package, "latest")?;
     let version_string = format!("^{}", version);
 
     // Update package.json
-    if let Some(dev_deps) = package_json["devDependencies"].as_object_mut() {
-        dev_deps.insert(package.to_string(), json!(version_string));
-    } else {
-        package_json["devDependencies"] = json!({ package: version_string });
-    }
+    insert_sorted(
+        &mut package_json.value,
+        "devDependencies",
+        package,
+        json!(version_string),
+    );
 
     // Update package-tmpl.json
-    if let Some(dev_deps) = package_tmpl_json["devDependencies"].as_object_mut() {
-        dev_deps.insert(package.to_string(), json!(version_string));
-    } else {
-        package_tmpl_json["devDependencies"] = json!({ package: version_string });
-    }
-
-    // Write updated package.json
-    let updated_package_json = serde_json::to_string_pretty(&package_json)?;
-    fs::write(package_json_path, updated_package_json)?;
+    insert_sorted(
+        &mut package_tmpl_json.value,
+        "devDependencies",
+        package,
+        json!(version_string),
+    );
 
-    // Write updated package-tmpl.json
-    let updated_package_tmpl_json = serde_json::to_string_pretty(&package_tmpl_json)?;
-    fs::write(package_tmpl_json_path, updated_package_tmpl_json)?;
+    // Write updated package.json and package-tmpl.json together
+    let mut transaction = ManifestTransaction::new();
+    transaction.stage(&package_json)?;
+    transaction.stage(&package_tmpl_json)?;
+    transaction.commit()?;
 
-    println!(
+    info!(
         "✅ Added {} {} to devDependencies 📦",
         package, version_string
     );
+    output::dependency_changed(
+        package,
+        "added",
+        "root",
+        "devDependencies",
+        None,
+        Some(&version_string),
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEB: &str = r#"{
+  "name": "web",
+  "dependencies": {
+    "next": "14.2.8",
+    "react": "^18"
+  }
+}
+"#;
+    const ADMIN: &str = r#"{ "name": "admin" }"#;
+    const TEMPLATE: &str = r#"{ "name": "root", "dependencies": { "react": "^18" } }"#;
+
+    /// Creates a workspace with the `web` and `admin` apps, and a lockfile in `web`.
+    fn workspace() -> (tempfile::TempDir, Workspace) {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(PACKAGE_TMPL_JSON), TEMPLATE).unwrap();
+        for (dir, manifest) in [("apps/web", WEB), ("apps/admin", ADMIN)] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+            fs::write(root.path().join(dir).join(PACKAGE_JSON), manifest).unwrap();
+        }
+        fs::write(root.path().join("apps/web").join(PACKAGE_LOCK_JSON), "{}").unwrap();
+        let workspace = Workspace::discover(root.path()).unwrap();
+        (root, workspace)
+    }
+
+    fn specs(packages: &[&str]) -> Vec<PackageSpec> {
+        packages
+            .iter()
+            .map(|package| PackageSpec::parse(package).unwrap())
+            .collect()
+    }
+
+    fn read(root: &Path, path: &str) -> String {
+        fs::read_to_string(root.join(path)).unwrap()
+    }
+
+    #[test]
+    fn adds_sorted_entries_to_the_selected_projects() {
+        let (root, workspace) = workspace();
+        let projects = workspace.select("web").unwrap();
+        let installed = std::cell::RefCell::new(Vec::new());
+
+        add_to_projects(
+            &projects,
+            &specs(&["zod", "@tanstack/react-query"]),
+            &["^3.23.8".to_string(), "^5.0.0".to_string()],
+            "dependencies",
+            |dir| {
+                installed.borrow_mut().push(dir.to_path_buf());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            read(root.path(), "apps/web/package.json"),
+            r#"{
+  "name": "web",
+  "dependencies": {
+    "@tanstack/react-query": "^5.0.0",
+    "next": "14.2.8",
+    "react": "^18",
+    "zod": "^3.23.8"
+  }
+}
+"#
+        );
+        assert_eq!(installed.into_inner(), [root.path().join("apps/web")]);
+        assert_eq!(read(root.path(), "apps/admin/package.json"), ADMIN);
+        assert_eq!(read(root.path(), PACKAGE_TMPL_JSON), TEMPLATE);
+        assert!(!root.path().join(PACKAGE_JSON).exists());
+    }
+
+    #[test]
+    fn rolls_every_project_back_when_an_install_fails() {
+        let (root, workspace) = workspace();
+        let projects = workspace.select("apps/*").unwrap();
+
+        let error = add_to_projects(
+            &projects,
+            &specs(&["zod"]),
+            &["^3.23.8".to_string()],
+            "devDependencies",
+            |dir| {
+                // npm rewrites the lockfile before failing
+                fs::write(dir.join(PACKAGE_LOCK_JSON), "{ \"broken\": true }").unwrap();
+                if dir.ends_with("web") {
+                    Err("❌ npm install failed 😢".into())
+                } else {
+                    Ok(())
+                }
+            },
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "❌ npm install failed 😢");
+        assert_eq!(read(root.path(), "apps/web/package.json"), WEB);
+        assert_eq!(read(root.path(), "apps/web/package-lock.json"), "{}");
+        assert_eq!(read(root.path(), "apps/admin/package.json"), ADMIN);
+        assert!(!root
+            .path()
+            .join("apps/admin")
+            .join(PACKAGE_LOCK_JSON)
+            .exists());
+        assert_eq!(read(root.path(), PACKAGE_TMPL_JSON), TEMPLATE);
+    }
+
+    #[test]
+    fn writes_nothing_when_a_manifest_cannot_be_staged() {
+        let (root, workspace) = workspace();
+        let projects = workspace.select("apps/*").unwrap();
+        // admin comes first, web is read second
+        fs::write(root.path().join("apps/web/package.json"), "{ \"name\": ").unwrap();
+
+        let error = add_to_projects(
+            &projects,
+            &specs(&["zod"]),
+            &["^3.23.8".to_string()],
+            "dependencies",
+            |_| panic!("nothing should be installed"),
+        )
+        .unwrap_err();
+
+        assert!(matches!(error, KhadimError::ManifestParse { .. }));
+        assert_eq!(read(root.path(), "apps/admin/package.json"), ADMIN);
+        assert_eq!(read(root.path(), "apps/web/package.json"), "{ \"name\": ");
+    }
+
+    #[test]
+    fn detects_imports_of_the_package_and_its_subpaths() {
+        for source in [
+            "import { useQuery } from 'react-query';",
+            "import Query from \"react-query\"",
+            "export * from 'react-query'",
+            "import 'react-query';",
+            "const query = require('react-query');",
+            "const query = require( \"react-query\" )",
+            "const devtools = await import('react-query/devtools');",
+            "import { QueryClient } from\n  `react-query/core`;",
+        ] {
+            assert!(imports_package(source, "react-query"), "{}", source);
+        }
+    }
+
+    #[test]
+    fn ignores_other_packages_and_strings() {
+        for source in [
+            "import { useQuery } from 'react-query-other';",
+            "import { useQuery } from '@tanstack/react-query';",
+            "const name = 'react-query';",
+            "console.log(\"react-query/devtools\")",
+            "// uses react-query",
+        ] {
+            assert!(!imports_package(source, "react-query"), "{}", source);
+        }
+    }
+
+    #[test]
+    fn finds_declarations_and_imports_in_the_workspace() {
+        let root = tempfile::tempdir().unwrap();
+        let files = [
+            (
+                "apps/web/package.json",
+                r#"{ "name": "web", "dependencies": { "pkg": "^1" } }"#,
+            ),
+            (
+                "libs/ui/package.json",
+                r#"{ "name": "ui", "peerDependencies": { "pkg": "*" }, "optionalDependencies": { "pkg-other": "^1" } }"#,
+            ),
+            (
+                "libs/core/package.json",
+                r#"{ "name": "core", "devDependencies": { "pkg-other": "^1" } }"#,
+            ),
+            (
+                "apps/web/src/page.tsx",
+                "import { thing } from 'pkg/subpath';",
+            ),
+            ("apps/web/src/readme.md", "import { thing } from 'pkg';"),
+            ("apps/web/node_modules/dep/index.js", "require('pkg')"),
+            ("libs/ui/index.js", "const pkg = require('pkg');"),
+            ("libs/core/index.ts", "import other from 'pkg-other';"),
+        ];
+        for (path, content) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let workspace = Workspace::discover(root.path()).unwrap();
+
+        assert_eq!(
+            find_usages(&workspace, "pkg"),
+            [
+                "declared in libs/ui/package.json peerDependencies",
+                "imported in libs/ui/index.js",
+                "declared in apps/web/package.json dependencies",
+                "imported in apps/web/src/page.tsx",
+            ]
+        );
+        assert_eq!(
+            find_usages(&workspace, "pkg-other"),
+            [
+                "declared in libs/core/package.json devDependencies",
+                "imported in libs/core/index.ts",
+                "declared in libs/ui/package.json optionalDependencies",
+            ]
+        );
+        assert!(find_usages(&workspace, "left-pad").is_empty());
+    }
+}
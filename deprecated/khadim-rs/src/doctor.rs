@@ -1,13 +1,20 @@
-use crate::BoxError;
+use crate::config::Config;
+use crate::error::KhadimError;
+use crate::output::Step;
+use crate::root::find_root_dir;
+use log::info;
+use std::env;
 use std::process::Command;
 
 /// Runs a series of checks to ensure the development environment is properly set up.
 ///
-/// This function checks for the presence and correct installation of essential tools:
+/// This function checks for the presence and correct installation of the tools
+/// listed in `tools` of the root's `khadim.json`, by default:
 /// - Volta (JavaScript toolchain manager)
 /// - npm (Node.js package manager)
 /// - Node.js (JavaScript runtime)
 ///
+/// Outside a monorepo, the default tools are checked.
 /// # Returns
 ///
 /// Returns `Ok(())` if all checks pass or if some checks fail but the function completes.
@@ -15,30 +22,34 @@ use std::process::Command;
 ///
 /// # Errors
 ///
-/// This function will return an `Err` if there's an unexpected error during the execution
-/// of any check. However, missing tools or version check failures are not considered errors
+/// This function will return an `Err` if the root's `khadim.json` is not valid, or
+/// if there's an unexpected error during the execution of any check. However, missing tools or version check failures are not considered errors
 /// at this level and are instead reported through the console output.
 ///
 /// # Example
 ///
-/// ```
-/// use khadim-rs::doctor::run_doctor_checks;
+/// ```no_run
+/// use khadim_rs::doctor::run_doctor_checks;
 ///
 /// let result = run_doctor_checks();
 /// assert!(result.is_ok());
 /// ```
-pub fn run_doctor_checks() -> Result<(), BoxError> {
-    println!("🩺 Running doctor checks...");
-    let mut all_checks_passed = true;
+pub fn run_doctor_checks() -> Result<(), KhadimError> {
+    info!("🩺 Running doctor checks...");
+    let config = match find_root_dir() {
+        Ok(root_dir) => Config::load(&root_dir)?,
+        Err(_) => Config::default(),
+    };
 
-    all_checks_passed &= check_command("volta", &["--version"])?;
-    all_checks_passed &= check_command("npm", &["--version"])?;
-    all_checks_passed &= check_command("node", &["--version"])?;
+    let mut all_checks_passed = true;
+    for tool in &config.tools {
+        all_checks_passed &= check_command(tool, &["--version"])?;
+    }
 
     if all_checks_passed {
-        println!("\n✅ All checks passed successfully! 🎉");
+        info!("\n✅ All checks passed successfully! 🎉");
     } else {
-        println!("\n❌ Some checks failed. Please install the missing tools. 🛠️");
+        info!("\n❌ Some checks failed. Please install the missing tools. 🛠️");
     }
 
     Ok(())
@@ -57,33 +68,43 @@ pub fn run_doctor_checks() -> Result<(), BoxError> {
 ///
 /// # Returns
 ///
-/// Returns a `Result<bool, Box<dyn std::error::Error>>`:
+/// Returns a `Result<bool, KhadimError>`:
 /// - `Ok(true)` if the command is installed and executed successfully.
 /// - `Ok(false)` if the command failed to execute or is not installed.
 /// - `Err(...)` if there was an unexpected error during the process.
 ///
 /// # Examples
 ///
-/// ```
-/// use khadim-rs::doctor::check_command;
+/// ```no_run
+/// use khadim_rs::doctor::check_command;
 ///
 /// let result = check_command("node", &["--version"]);
 /// assert!(result.is_ok());
 /// ```
-pub fn check_command(command: &str, args: &[&str]) -> Result<bool, BoxError> {
+pub fn check_command(command: &str, args: &[&str]) -> Result<bool, KhadimError> {
+    let step = Step::start(&format!("check {}", command), None, None);
     match Command::new(command).args(args).output() {
         Ok(output) => {
             if output.status.success() {
                 let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                println!("✅ {} is installed. Version: {} 🚀", command, version);
+                info!("✅ {} is installed. Version: {} 🚀", command, version);
+                step.finish_with("success", Some(0), None);
                 Ok(true)
             } else {
-                println!("❌ {} check failed 😕", command);
+                info!("❌ {} check failed 😕", command);
+                let error = KhadimError::subprocess(
+                    &format!("{} {}", command, args.join(" ")),
+                    &env::current_dir().unwrap_or_default(),
+                    output.status.code(),
+                    &output.stderr,
+                );
+                step.finish_with("failed", output.status.code(), Some(&error));
                 Ok(false)
             }
         }
-        Err(_) => {
-            println!("❌ {} is not installed or not in PATH 😢", command);
+        Err(e) => {
+            info!("❌ {} is not installed or not in PATH 😢", command);
+            step.finish_with("failed", None, Some(&KhadimError::spawn(command, e)));
             Ok(false)
         }
     }
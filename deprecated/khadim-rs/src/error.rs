@@ -0,0 +1,302 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The errors returned by khadim-rs.
+///
+/// Each category maps to its own process exit code, see [`KhadimError::exit_code`].
+#[derive(Debug)]
+pub enum KhadimError {
+    /// No workspace root was found from the given directory.
+    RootNotFound {
+        start: PathBuf,
+        /// The files or directories that were looked for.
+        markers: Vec<String>,
+    },
+    /// The root directory given with `--root` or `KHADIM_ROOT` does not exist.
+    RootDirMissing {
+        path: PathBuf,
+        /// Where the directory was given, `--root` or `KHADIM_ROOT`.
+        given_with: String,
+    },
+    /// A package.json, package-tmpl.json or other JSON file is not valid.
+    ManifestParse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The khadim.json configuration file is not valid, or the package-tmpl.json
+    /// template is missing.
+    InvalidConfig {
+        path: PathBuf,
+        /// Every problem found, e.g. `libsDir must be a relative path`.
+        problems: Vec<String>,
+    },
+    /// A required tool such as npm, node or git is not installed.
+    MissingTool { tool: String },
+    /// A command ran but failed.
+    Subprocess {
+        command: String,
+        cwd: PathBuf,
+        /// `None` when the command was killed by a signal.
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    /// The npm registry could not resolve a package.
+    Registry { package: String, message: String },
+    /// A file system operation failed.
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// A project name or glob given as an argument is not a valid glob.
+    InvalidPattern { pattern: String, message: String },
+    /// No app or lib matches a name or glob.
+    ProjectNotFound {
+        pattern: String,
+        /// The names of every app and lib of the workspace.
+        available: Vec<String>,
+    },
+    /// The apps and libs depend on each other in a cycle.
+    DependencyCycle {
+        /// The projects of the cycle, starting and ending with the same one.
+        projects: Vec<String>,
+    },
+    /// Any other failure, such as a failed script or a version conflict.
+    Other(String),
+}
+
+impl KhadimError {
+    /// Returns the process exit code for the error's category:
+    ///
+    /// * `1` - other failures
+    /// * `2` - invalid project pattern
+    /// * `3` - workspace root not found
+    /// * `4` - invalid manifest
+    /// * `5` - missing tool
+    /// * `6` - failed subprocess
+    /// * `7` - registry failure
+    /// * `8` - file system failure
+    /// * `9` - invalid configuration or missing template
+    /// * `10` - dependency cycle
+    /// * `11` - no matching project
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::error::KhadimError;
+    ///
+    /// let error = KhadimError::MissingTool { tool: "npm".to_string() };
+    /// assert_eq!(error.exit_code(), 5);
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            KhadimError::Other(_) => 1,
+            KhadimError::InvalidPattern { .. } => 2,
+            KhadimError::RootNotFound { .. } | KhadimError::RootDirMissing { .. } => 3,
+            KhadimError::ManifestParse { .. } => 4,
+            KhadimError::MissingTool { .. } => 5,
+            KhadimError::Subprocess { .. } => 6,
+            KhadimError::Registry { .. } => 7,
+            KhadimError::Io { .. } => 8,
+            KhadimError::InvalidConfig { .. } => 9,
+            KhadimError::DependencyCycle { .. } => 10,
+            KhadimError::ProjectNotFound { .. } => 11,
+        }
+    }
+
+    /// Returns the name of the error's category in snake case, e.g. `missing_tool`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            KhadimError::RootNotFound { .. } | KhadimError::RootDirMissing { .. } => {
+                "root_not_found"
+            }
+            KhadimError::ManifestParse { .. } => "manifest_parse",
+            KhadimError::InvalidConfig { .. } => "invalid_config",
+            KhadimError::MissingTool { .. } => "missing_tool",
+            KhadimError::Subprocess { .. } => "subprocess",
+            KhadimError::Registry { .. } => "registry",
+            KhadimError::Io { .. } => "io",
+            KhadimError::InvalidPattern { .. } => "invalid_pattern",
+            KhadimError::ProjectNotFound { .. } => "project_not_found",
+            KhadimError::DependencyCycle { .. } => "dependency_cycle",
+            KhadimError::Other(_) => "other",
+        }
+    }
+
+    /// Creates a parse error for a JSON file.
+    pub fn manifest_parse(path: &Path, error: &serde_json::Error) -> KhadimError {
+        // The position is kept in its own fields rather than repeated in the message
+        let position = format!(" at line {} column {}", error.line(), error.column());
+        let message = error.to_string();
+        KhadimError::ManifestParse {
+            path: path.to_path_buf(),
+            line: error.line(),
+            column: error.column(),
+            message: message
+                .strip_suffix(&position)
+                .unwrap_or(&message)
+                .to_string(),
+        }
+    }
+
+    /// Creates the error for a command that could not be started, reporting a
+    /// missing tool when the program does not exist.
+    pub fn spawn(tool: &str, error: io::Error) -> KhadimError {
+        if error.kind() == io::ErrorKind::NotFound {
+            KhadimError::MissingTool {
+                tool: tool.to_string(),
+            }
+        } else {
+            KhadimError::Io {
+                path: None,
+                source: error,
+            }
+        }
+    }
+
+    /// Creates the error for a command that exited unsuccessfully.
+    pub fn subprocess(
+        command: &str,
+        cwd: &Path,
+        exit_code: Option<i32>,
+        stderr: &[u8],
+    ) -> KhadimError {
+        KhadimError::Subprocess {
+            command: command.to_string(),
+            cwd: cwd.to_path_buf(),
+            exit_code,
+            stderr: String::from_utf8_lossy(stderr).trim().to_string(),
+        }
+    }
+
+    /// Creates a file system error for the given path.
+    pub fn io(path: &Path, source: io::Error) -> KhadimError {
+        KhadimError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for KhadimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KhadimError::RootNotFound { start, markers } => write!(
+                f,
+                "❌ Cannot find root directory from {}, no {} found in it or its parents 😢",
+                start.display(),
+                markers.join(", ")
+            ),
+            KhadimError::RootDirMissing { path, given_with } => write!(
+                f,
+                "❌ Root directory {} given with {} does not exist 😢",
+                path.display(),
+                given_with
+            ),
+            KhadimError::ManifestParse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "❌ Invalid {}:{}:{}: {} 😢",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            KhadimError::InvalidConfig { path, problems } => write!(
+                f,
+                "❌ Invalid configuration in {}: {} 😢",
+                path.display(),
+                problems.join("; ")
+            ),
+            KhadimError::MissingTool { tool } => {
+                write!(f, "❌ {} is not installed or not on PATH 😢", tool)
+            }
+            KhadimError::Subprocess {
+                command,
+                cwd,
+                exit_code,
+                stderr,
+            } => {
+                let status = match exit_code {
+                    Some(code) => format!("exit code {}", code),
+                    None => "a signal".to_string(),
+                };
+                write!(
+                    f,
+                    "❌ {} failed in {} with {}",
+                    command,
+                    cwd.display(),
+                    status
+                )?;
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                write!(f, " 😢")
+            }
+            KhadimError::Registry { message, .. } => write!(f, "❌ {} 😢", message),
+            KhadimError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "❌ {}: {} 😢", path.display(), source),
+            KhadimError::Io { path: None, source } => write!(f, "❌ {} 😢", source),
+            KhadimError::InvalidPattern { pattern, message } => {
+                write!(
+                    f,
+                    "❌ Invalid project pattern '{}': {} 😢",
+                    pattern, message
+                )
+            }
+            KhadimError::ProjectNotFound { pattern, available } => write!(
+                f,
+                "❌ No app or lib matches '{}'. Available projects: {} 😢",
+                pattern,
+                available.join(", ")
+            ),
+            KhadimError::DependencyCycle { projects } => write!(
+                f,
+                "❌ Dependency cycle detected: {} 😢",
+                projects.join(" -> ")
+            ),
+            KhadimError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for KhadimError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KhadimError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KhadimError {
+    fn from(source: io::Error) -> KhadimError {
+        KhadimError::Io { path: None, source }
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for KhadimError {
+    fn from(error: rayon::ThreadPoolBuildError) -> KhadimError {
+        KhadimError::Other(format!("❌ Cannot start worker threads: {} 😢", error))
+    }
+}
+
+impl From<String> for KhadimError {
+    fn from(message: String) -> KhadimError {
+        KhadimError::Other(message)
+    }
+}
+
+impl From<&str> for KhadimError {
+    fn from(message: &str) -> KhadimError {
+        KhadimError::Other(message.to_string())
+    }
+}
@@ -0,0 +1,348 @@
+use crate::error::KhadimError;
+use crate::workspace::{Project, Workspace};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Internal dependencies between the projects of a workspace.
+///
+/// An edge exists from a project to a sibling project when the former lists
+/// the latter in its `dependencies` or `devDependencies`, either by package
+/// name (e.g. `"@libs/hello": "^1.0.0"`) or by a `file:` path
+/// (e.g. `"@libs/hello": "file:../../libs/hello"`). External packages are ignored.
+///
+/// The graph is guaranteed to be acyclic once built.
+#[derive(Debug)]
+pub struct DependencyGraph<'a> {
+    workspace: &'a Workspace,
+    /// For each project index, the indices of the projects it depends on.
+    dependencies: Vec<BTreeSet<usize>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    /// Builds the dependency graph for a workspace.
+    ///
+    /// # Arguments
+    ///
+    /// * `workspace` - The workspace whose projects form the nodes of the graph.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the projects depend on each other in a cycle.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::graph::DependencyGraph;
+    /// use khadim_rs::workspace::Workspace;
+    /// use std::path::Path;
+    ///
+    /// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+    /// let graph = DependencyGraph::build(&workspace).unwrap();
+    /// for project in graph.topological_order() {
+    ///     println!("{}", project.name);
+    /// }
+    /// ```
+    pub fn build(workspace: &'a Workspace) -> Result<DependencyGraph<'a>, KhadimError> {
+        let project_dirs: Vec<PathBuf> = workspace
+            .projects
+            .iter()
+            .map(|project| normalize_path(&project.path))
+            .collect();
+
+        let dependencies = workspace
+            .projects
+            .iter()
+            .enumerate()
+            .map(|(index, project)| {
+                project
+                    .manifest
+                    .dependencies
+                    .iter()
+                    .chain(project.manifest.dev_dependencies.iter())
+                    .filter_map(|(name, spec)| {
+                        resolve_dependency(workspace, &project_dirs, project, name, spec)
+                    })
+                    .filter(|&dependency| dependency != index)
+                    .collect()
+            })
+            .collect();
+
+        let graph = DependencyGraph {
+            workspace,
+            dependencies,
+        };
+        graph.check_acyclic()?;
+        Ok(graph)
+    }
+
+    /// Returns the workspace projects that the given project depends on directly.
+    pub fn dependencies_of(&self, project: &Project) -> Vec<&'a Project> {
+        match self.index_of(project) {
+            Some(index) => self.dependencies[index]
+                .iter()
+                .map(|&dependency| &self.workspace.projects[dependency])
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns every workspace project that the given project depends on,
+    /// directly or through other projects, in workspace order.
+    pub fn transitive_dependencies_of(&self, project: &Project) -> Vec<&'a Project> {
+        let mut found = BTreeSet::new();
+        let mut pending: Vec<usize> = self.index_of(project).into_iter().collect();
+        while let Some(index) = pending.pop() {
+            for &dependency in &self.dependencies[index] {
+                if found.insert(dependency) {
+                    pending.push(dependency);
+                }
+            }
+        }
+        found
+            .into_iter()
+            .map(|index| &self.workspace.projects[index])
+            .collect()
+    }
+
+    /// Returns the workspace projects that depend on the given project directly.
+    pub fn dependents_of(&self, project: &Project) -> Vec<&'a Project> {
+        match self.index_of(project) {
+            Some(index) => self
+                .dependencies
+                .iter()
+                .enumerate()
+                .filter(|(_, dependencies)| dependencies.contains(&index))
+                .map(|(dependent, _)| &self.workspace.projects[dependent])
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns all projects ordered so that every project comes after its dependencies.
+    pub fn topological_order(&self) -> Vec<&'a Project> {
+        self.levels().into_iter().flatten().collect()
+    }
+
+    /// Groups the projects into levels that can be processed one after another.
+    ///
+    /// Every project only depends on projects from earlier levels, so the
+    /// projects within a single level are independent of each other and can be
+    /// processed in parallel. Within a level, projects keep the workspace order.
+    pub fn levels(&self) -> Vec<Vec<&'a Project>> {
+        let mut remaining: Vec<usize> = (0..self.dependencies.len()).collect();
+        let mut done = vec![false; self.dependencies.len()];
+        let mut levels = Vec::new();
+
+        while !remaining.is_empty() {
+            let (ready, blocked): (Vec<usize>, Vec<usize>) =
+                remaining.into_iter().partition(|&index| {
+                    self.dependencies[index]
+                        .iter()
+                        .all(|&dependency| done[dependency])
+                });
+            if ready.is_empty() {
+                // Unreachable for graphs created by `build`, which rejects cycles
+                break;
+            }
+
+            for &index in &ready {
+                done[index] = true;
+            }
+            levels.push(
+                ready
+                    .into_iter()
+                    .map(|index| &self.workspace.projects[index])
+                    .collect(),
+            );
+            remaining = blocked;
+        }
+
+        levels
+    }
+
+    fn index_of(&self, project: &Project) -> Option<usize> {
+        self.workspace
+            .projects
+            .iter()
+            .position(|candidate| candidate.path == project.path)
+    }
+
+    /// Returns an error describing the first dependency cycle found, if any.
+    fn check_acyclic(&self) -> Result<(), KhadimError> {
+        // 0 = unvisited, 1 = on the current path, 2 = finished
+        let mut state = vec![0u8; self.dependencies.len()];
+        let mut path = Vec::new();
+
+        for start in 0..self.dependencies.len() {
+            if let Some(cycle) = self.find_cycle(start, &mut state, &mut path) {
+                return Err(KhadimError::DependencyCycle {
+                    projects: cycle
+                        .iter()
+                        .map(|&index| self.workspace.projects[index].name.clone())
+                        .collect(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn find_cycle(
+        &self,
+        index: usize,
+        state: &mut [u8],
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        match state[index] {
+            1 => {
+                let start = path.iter().position(|&node| node == index).unwrap_or(0);
+                let mut cycle = path[start..].to_vec();
+                cycle.push(index);
+                return Some(cycle);
+            }
+            2 => return None,
+            _ => {}
+        }
+
+        state[index] = 1;
+        path.push(index);
+        for &dependency in &self.dependencies[index] {
+            if let Some(cycle) = self.find_cycle(dependency, state, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        state[index] = 2;
+        None
+    }
+}
+
+/// Resolves a single dependency entry to the index of a sibling project.
+fn resolve_dependency(
+    workspace: &Workspace,
+    project_dirs: &[PathBuf],
+    project: &Project,
+    name: &str,
+    spec: &str,
+) -> Option<usize> {
+    if let Some(relative_path) = spec.strip_prefix("file:") {
+        let target = normalize_path(&project.path.join(relative_path));
+        return project_dirs.iter().position(|dir| *dir == target);
+    }
+
+    workspace
+        .projects
+        .iter()
+        .position(|candidate| candidate.package_name() == name)
+}
+
+/// Lexically resolves `.` and `..` components so that paths can be compared.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Creates a workspace with the given `(dir, package name, dependencies)` projects.
+    fn workspace(projects: &[(&str, &str, &[&str])]) -> (tempfile::TempDir, Workspace) {
+        let root = tempfile::tempdir().unwrap();
+        for (dir, name, dependencies) in projects {
+            let dependencies: serde_json::Map<String, serde_json::Value> = dependencies
+                .iter()
+                .map(|dependency| (dependency.to_string(), "^1.0.0".into()))
+                .collect();
+            let manifest = serde_json::json!({ "name": name, "dependencies": dependencies });
+            let project_dir = root.path().join(dir);
+            fs::create_dir_all(&project_dir).unwrap();
+            fs::write(project_dir.join("package.json"), manifest.to_string()).unwrap();
+        }
+        let workspace = Workspace::discover(root.path()).unwrap();
+        (root, workspace)
+    }
+
+    fn names(projects: &[&Project]) -> Vec<String> {
+        projects
+            .iter()
+            .map(|project| project.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn levels_follow_dependencies() {
+        let (_root, workspace) = workspace(&[
+            ("libs/core", "@libs/core", &[]),
+            ("libs/ui", "@libs/ui", &["@libs/core", "react"]),
+            ("libs/utils", "@libs/utils", &[]),
+            ("apps/web", "web", &["@libs/ui", "@libs/utils"]),
+            ("apps/api", "api", &["@libs/core"]),
+        ]);
+        let graph = DependencyGraph::build(&workspace).unwrap();
+
+        let levels: Vec<Vec<String>> = graph.levels().iter().map(|level| names(level)).collect();
+        assert_eq!(
+            levels,
+            [vec!["core", "utils"], vec!["ui", "api"], vec!["web"]]
+        );
+
+        let web = workspace.find("web").unwrap();
+        assert_eq!(names(&graph.dependencies_of(web)), ["ui", "utils"]);
+        assert_eq!(
+            names(&graph.transitive_dependencies_of(web)),
+            ["core", "ui", "utils"]
+        );
+        let core = workspace.find("core").unwrap();
+        assert_eq!(names(&graph.dependents_of(core)), ["ui", "api"]);
+    }
+
+    #[test]
+    fn resolves_file_dependencies() {
+        let root = tempfile::tempdir().unwrap();
+        for (dir, manifest) in [
+            ("libs/hello", r#"{ "name": "hello" }"#),
+            (
+                "apps/web",
+                r#"{ "dependencies": { "greeting": "file:../../libs/hello/" } }"#,
+            ),
+        ] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+            fs::write(root.path().join(dir).join("package.json"), manifest).unwrap();
+        }
+        let workspace = Workspace::discover(root.path()).unwrap();
+        let graph = DependencyGraph::build(&workspace).unwrap();
+
+        let web = workspace.find("web").unwrap();
+        assert_eq!(names(&graph.dependencies_of(web)), ["hello"]);
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let (_root, workspace) = workspace(&[
+            ("libs/a", "a", &["b"]),
+            ("libs/b", "b", &["c"]),
+            ("libs/c", "c", &["a"]),
+            ("apps/web", "web", &["a"]),
+        ]);
+        let error = DependencyGraph::build(&workspace).unwrap_err();
+        assert!(matches!(
+            &error,
+            KhadimError::DependencyCycle { projects } if projects == &["a", "b", "c", "a"]
+        ));
+        assert_eq!(error.exit_code(), 10);
+        assert_eq!(
+            error.to_string(),
+            "❌ Dependency cycle detected: a -> b -> c -> a 😢"
+        );
+    }
+}
@@ -1,15 +1,21 @@
 use crate::dependencies::add_dev_dependency;
 use rayon::prelude::*;
 use serde_json::{json, Value};
-use std::env;
-use std::fs;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use crate::config::{APPS_DIR, LIBS_DIR, PACKAGE_JSON, PACKAGE_TMPL_JSON};
-use crate::BoxError;
+use crate::config::{PACKAGE_JSON, PACKAGE_TMPL_JSON};
+use crate::error::KhadimError;
+use crate::graph::DependencyGraph;
+use crate::json_file::JsonFile;
+use crate::logger;
+use crate::output::{self, Step};
+use crate::plan::{self, Action};
+use crate::root::find_root_dir;
+use crate::sync::{check_drift, print_drift};
+use crate::workspace::{Project, Workspace};
+use log::{info, trace};
 
 /// Initializes the project and installs all dependencies.
 ///
@@ -21,7 +27,7 @@ use crate::BoxError;
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if all operations are successful,
+/// * `Result<(), KhadimError>` - Ok(()) if all operations are successful,
 ///   or an error if any step fails.
 ///
 /// # Errors
@@ -31,420 +37,419 @@ use crate::BoxError;
 /// * Adding the 'concurrently' dev dependency fails
 /// * The npm install process fails
 /// * Installing project dependencies fails
-pub fn initialize_and_install_all() -> Result<(), BoxError> {
-    println!("🚀 Initializing and installing all dependencies...");
-    let root_dir = initialize_package_json()?;
+pub fn initialize_and_install_all() -> Result<(), KhadimError> {
+    info!("🚀 Initializing and installing all dependencies...");
+    let workspace = initialize_and_install_root()?;
+    install_project_dependencies(&workspace)?;
+    info!("✅ All dependencies installed successfully! 🎉");
+    Ok(())
+}
+
+/// Initializes the root package.json, adds the 'concurrently' dev dependency
+/// and runs npm install in the root directory, leaving the apps and libs alone.
+///
+/// # Returns
+///
+/// * `Result<Workspace, KhadimError>` - The workspace whose root was initialized.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The package.json initialization fails
+/// * Adding the 'concurrently' dev dependency fails
+/// * The npm install process fails
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::init::initialize_and_install_root;
+///
+/// let workspace = initialize_and_install_root().unwrap();
+/// println!("Installed the root dependencies of {}", workspace.root_dir.display());
+/// ```
+pub fn initialize_and_install_root() -> Result<Workspace, KhadimError> {
+    let workspace = initialize_package_json()?;
 
     // we use concurrently to run multiple npm scripts concurrently
-    add_dev_dependency("concurrently").map_err(BoxError::from)?;
+    add_dev_dependency("concurrently")?;
 
-    run_npm_install(&root_dir)?;
-    install_project_dependencies(&root_dir)?;
-    println!("✅ All dependencies installed successfully! 🎉");
-    Ok(())
+    run_npm_install(&workspace.root_dir)?;
+    Ok(workspace)
 }
 
 /// Example usage of initialize_package_json
 ///
-/// ```
-/// use khadim-rs::init::initialize_package_json;
+/// ```no_run
+/// use khadim_rs::init::initialize_package_json;
 ///
 /// match initialize_package_json() {
-///     Ok(_) => println!("Package.json initialized successfully."),
+///     Ok(workspace) => println!("Package.json initialized in {}", workspace.root_dir.display()),
 ///     Err(e) => eprintln!("Error initializing package.json: {}", e),
 /// }
 /// ```
-pub fn initialize_package_json() -> Result<PathBuf, BoxError> {
-    println!("📦 Initializing package.json...");
-    let current_dir = env::current_dir().map_err(BoxError::from)?;
-    let root_dir = current_dir
-        .ancestors()
-        .nth(1)
-        .ok_or_else(|| BoxError::from("❌ Cannot find root directory 😢"))?
-        .to_path_buf();
+pub fn initialize_package_json() -> Result<Workspace, KhadimError> {
+    info!("📦 Initializing package.json...");
+    let root_dir = find_root_dir()?;
+
+    let step = Step::start("generate package.json", None, Some(&root_dir));
+    let result = write_package_json(&root_dir);
+    step.finish(&result);
+    let workspace = result?;
+
+    info!("✅ Successfully created package.json in the root directory 📄");
+    Ok(workspace)
+}
+
+/// Discovers the workspace and writes its generated package.json, warning
+/// about hand edits that are overwritten.
+fn write_package_json(root_dir: &Path) -> Result<Workspace, KhadimError> {
+    let workspace = Workspace::discover(root_dir)?;
+    let package_json = generate_package_json(&workspace)?;
+
+    // Hand edits to package.json are overwritten, so point them out first
+    let drift = check_drift(&workspace)?;
+    if !drift.is_empty() {
+        print_drift(&drift);
+        output::warn(&format!(
+            "Overwriting these changes, run `khadim-rs sync` first to keep them in {}",
+            PACKAGE_TMPL_JSON
+        ));
+    }
+
+    package_json.write()?;
+
+    Ok(workspace)
+}
 
+/// Generates the root package.json from package-tmpl.json and the scripts of
+/// every app and lib, without writing it. The file keeps the key order,
+/// indentation and final newline of the template.
+///
+/// # Arguments
+///
+/// * `workspace` - The workspace whose root holds package-tmpl.json.
+///
+/// # Returns
+///
+/// * `Result<JsonFile, KhadimError>` - The root package.json: the template with its
+///   `scripts` replaced by the merged project scripts and the `dev` script.
+///
+/// # Errors
+///
+/// This function will return an error if package-tmpl.json is missing or is not valid JSON.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::init::generate_package_json;
+/// use khadim_rs::workspace::Workspace;
+/// use std::path::Path;
+///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+/// let package_json = generate_package_json(&workspace).unwrap();
+/// println!("{}", package_json.value["scripts"]["dev"]);
+/// ```
+pub fn generate_package_json(workspace: &Workspace) -> Result<JsonFile, KhadimError> {
+    let root_dir = &workspace.root_dir;
     let template_path = root_dir.join(PACKAGE_TMPL_JSON);
-    let output_path = root_dir.join(PACKAGE_JSON);
 
     if !template_path.exists() {
-        return Err(format!(
-            "❌ {} not found in {}",
-            PACKAGE_TMPL_JSON,
-            root_dir.display()
-        )
-        .into());
+        return Err(KhadimError::InvalidConfig {
+            path: template_path,
+            problems: vec![format!(
+                "{} not found, it is needed to generate package.json",
+                PACKAGE_TMPL_JSON
+            )],
+        });
     }
 
-    let template_content = fs::read_to_string(&template_path).map_err(BoxError::from)?;
-    let mut template: Value = serde_json::from_str(&template_content).map_err(BoxError::from)?;
+    let template = JsonFile::read(&template_path)?;
+    Ok(package_json_from_template(workspace, &template))
+}
 
-    let mut scripts = json!({});
+/// Builds the root package.json from an already loaded package-tmpl.json.
+pub(crate) fn package_json_from_template(workspace: &Workspace, template: &JsonFile) -> JsonFile {
+    let mut package_json = JsonFile::new(
+        &workspace.root_dir.join(PACKAGE_JSON),
+        template.value.clone(),
+        template.style.clone(),
+    );
 
-    // Dynamically find and merge scripts from all apps
-    let apps_dir = root_dir.join(APPS_DIR);
-    if apps_dir.is_dir() {
-        for entry in fs::read_dir(apps_dir).map_err(BoxError::from)? {
-            let entry = entry.map_err(BoxError::from)?;
-            let path = entry.path();
-            if path.is_dir() {
-                let package_json_path = path.join(PACKAGE_JSON);
-                if package_json_path.exists() {
-                    let app_name = match path.file_name() {
-                        Some(name) => match name.to_str() {
-                            Some(s) => s,
-                            None => continue,
-                        },
-                        None => continue,
-                    };
-                    merge_scripts(
-                        &mut scripts,
-                        &root_dir,
-                        &format!("{}/{}/{}", APPS_DIR, app_name, PACKAGE_JSON),
-                        app_name,
-                    )?;
-                }
-            }
-        }
-    }
+    let mut scripts = json!({});
 
-    // Dynamically find and merge scripts from all libs
-    let libs_dir = root_dir.join(LIBS_DIR);
-    if libs_dir.is_dir() {
-        for entry in fs::read_dir(libs_dir).map_err(BoxError::from)? {
-            let entry = entry.map_err(BoxError::from)?;
-            let path = entry.path();
-            if path.is_dir() {
-                let package_json_path = path.join(PACKAGE_JSON);
-                if package_json_path.exists() {
-                    let lib_name = match path.file_name() {
-                        Some(name) => match name.to_str() {
-                            Some(s) => s,
-                            None => continue,
-                        },
-                        None => continue,
-                    };
-                    merge_scripts(
-                        &mut scripts,
-                        &root_dir,
-                        &format!("{}/{}/{}", LIBS_DIR, lib_name, PACKAGE_JSON),
-                        lib_name,
-                    )?;
-                }
-            }
-        }
+    // Merge scripts from all apps and libs
+    for project in &workspace.projects {
+        merge_scripts(&mut scripts, project);
     }
 
     // Add the new dev script
-    let dev_scripts = create_dev_scripts(&root_dir)?;
-    scripts["dev"] = json!(dev_scripts);
-
-    template["scripts"] = scripts;
+    scripts["dev"] = json!(create_dev_scripts(workspace));
 
-    let output_content = serde_json::to_string_pretty(&template).map_err(BoxError::from)?;
-    fs::write(output_path, output_content).map_err(BoxError::from)?;
-
-    println!("✅ Successfully created package.json in the root directory 📄");
-    Ok(root_dir)
+    package_json.value["scripts"] = scripts;
+    package_json
 }
 
-/// Merges scripts from a package.json file into the main scripts object.
+/// Merges a project's scripts into the main scripts object.
+///
+/// Each script is prefixed with the project's directory name and runs
+/// from the project's directory relative to the workspace root.
 ///
 /// # Arguments
 ///
 /// * `scripts` - A mutable reference to the Value object containing all scripts.
-/// * `root_dir` - The root directory of the project.
-/// * `relative_path` - The relative path to the package.json file.
-/// * `prefix` - The prefix to be added to each script name.
-///
-/// # Returns
-///
-/// Returns `Ok(())` if successful, or an error if any operation fails.
+/// * `project` - The workspace project whose scripts should be merged.
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use serde_json::json;
 /// use std::path::Path;
-/// use khadim-rs::init::merge_scripts;
+/// use khadim_rs::init::merge_scripts;
+/// use khadim_rs::workspace::Workspace;
 ///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
 /// let mut scripts = json!({});
-/// let root_dir = Path::new("/path/to/project");
-/// let relative_path = "apps/my-app/package.json";
-/// let prefix = "my-app";
 ///
-/// merge_scripts(&mut scripts, root_dir, relative_path, prefix).unwrap();
+/// for project in &workspace.projects {
+///     merge_scripts(&mut scripts, project);
+/// }
 ///
-/// // If the original package.json had a "start" script,
+/// // If apps/my-app/package.json had a "start" script,
 /// // the merged scripts might now include:
 /// // {
 /// //     "my-app:start": "cd apps/my-app && npm run start"
 /// // }
 /// ```
-pub fn merge_scripts(
-    scripts: &mut Value,
-    root_dir: &Path,
-    relative_path: &str,
-    prefix: &str,
-) -> Result<(), BoxError> {
-    let file_path = root_dir.join(relative_path);
-    if !file_path.exists() {
-        println!("⚠️ {} not found, skipping", relative_path);
-        return Ok(());
-    }
-
-    let content = fs::read_to_string(&file_path).map_err(BoxError::from)?;
-    let package: Value = serde_json::from_str(&content).map_err(BoxError::from)?;
+pub fn merge_scripts(scripts: &mut Value, project: &Project) {
+    for (key, value) in &project.manifest.scripts {
+        let new_key = format!("{}:{}", project.name, key);
+        let script_value = format!("cd {} && {}", project.relative_dir().display(), value);
 
-    if let Some(package_scripts) = package["scripts"].as_object() {
-        for (key, value) in package_scripts {
-            let new_key = format!("{}:{}", prefix, key);
-            let parent_path = match Path::new(relative_path).parent() {
-                Some(path) => path,
-                None => return Err(BoxError::from("❌ Invalid file path 😢")),
-            };
-
-            let script_value = format!(
-                "cd {} && {}",
-                parent_path.display(),
-                value.as_str().unwrap_or("")
-            );
-
-            scripts[new_key] = json!(script_value);
-        }
+        scripts[new_key] = json!(script_value);
     }
-
-    Ok(())
 }
 
 /// Example usage of create_dev_scripts
 ///
-/// ```
+/// ```no_run
 /// use std::path::Path;
-/// use khadim-rs::init::create_dev_scripts;
-/// use khadim-rs::BoxError;
+/// use khadim_rs::init::create_dev_scripts;
+/// use khadim_rs::workspace::Workspace;
+/// use khadim_rs::error::KhadimError;
 ///
-/// fn example() -> Result<(), BoxError> {
-///     let root_dir = Path::new("/path/to/project");
-///     let dev_script = create_dev_scripts(root_dir)?;
+/// fn example() -> Result<(), KhadimError> {
+///     let workspace = Workspace::discover(Path::new("/path/to/project"))?;
+///     let dev_script = create_dev_scripts(&workspace);
 ///     println!("Dev script created: {}", dev_script);
 ///     Ok(())
 /// }
 /// ```
-pub fn create_dev_scripts(root_dir: &Path) -> Result<String, BoxError> {
+pub fn create_dev_scripts(workspace: &Workspace) -> String {
     let mut scripts = Vec::new();
 
     // Add libs dev script
     scripts.push("npm run libs:dev".to_string());
 
-    // Collect app scripts
-    if let Ok(entries) = fs::read_dir(root_dir.join(APPS_DIR)) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_dir() && path.join(PACKAGE_JSON).exists() {
-                    if let Some(name) = path.file_name() {
-                        if let Some(name_str) = name.to_str() {
-                            if name_str != "organic-lever-web-e2e" {
-                                // Exclude e2e from dev script
-                                scripts.push(format!("npm run {}:dev", name_str));
-                            }
-                        }
-                    }
-                }
-            }
+    // Collect app scripts, leaving out the apps excluded by the configuration
+    for app in workspace.apps() {
+        if workspace.config.in_dev_script(app) {
+            scripts.push(format!("npm run {}:dev", app.name));
         }
     }
 
-    Ok(format!(
+    format!(
         "concurrently {}",
         scripts
             .iter()
             .map(|s| format!("\"{}\"", s))
             .collect::<Vec<_>>()
             .join(" ")
-    ))
+    )
 }
 
 /// Example usage of install_project_dependencies
 ///
-/// ```
+/// Projects are installed in dependency order: a lib is always installed
+/// before the apps that depend on it, while independent projects are
+/// installed in parallel.
+///
+/// ```no_run
 /// use std::path::Path;
-/// use khadim-rs::init::install_project_dependencies;
+/// use khadim_rs::init::install_project_dependencies;
+/// use khadim_rs::workspace::Workspace;
 ///
-/// let root_dir = Path::new("/path/to/project");
-/// match install_project_dependencies(root_dir) {
+/// let workspace = Workspace::discover(Path::new("/path/to/project"))?;
+/// match install_project_dependencies(&workspace) {
 ///     Ok(_) => println!("Project dependencies installed successfully."),
 ///     Err(e) => eprintln!("Error installing project dependencies: {}", e),
 /// }
-/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// # Ok::<(), khadim_rs::error::KhadimError>(())
 /// ```
-pub fn install_project_dependencies(root_dir: &Path) -> Result<(), BoxError> {
-    println!("📚 Installing project dependencies...");
-    let libs_dir = root_dir.join(LIBS_DIR);
-    let apps_dir = root_dir.join(APPS_DIR);
-
-    // Install dependencies for libs sequentially
-    install_dependencies_in_dir(&libs_dir)?;
-
-    // Install dependencies for apps in parallel
-    install_dependencies_in_apps_parallel(&apps_dir)?;
-
-    println!("✅ All project dependencies installed successfully! 🎉");
+pub fn install_project_dependencies(workspace: &Workspace) -> Result<(), KhadimError> {
+    let projects: Vec<&Project> = workspace.projects.iter().collect();
+    install_dependencies_of(workspace, &projects)?;
+    info!("✅ All project dependencies installed successfully! 🎉");
     Ok(())
 }
 
-/// Installs dependencies for all npm projects in a given directory.
-///
-/// This function traverses the given directory, identifying npm projects
-/// and installing their dependencies.
+/// Installs the dependencies of some of the workspace projects, in dependency
+/// order like `install_project_dependencies`.
 ///
 /// # Arguments
 ///
-/// * `dir` - A `&Path` representing the directory to search for npm projects.
+/// * `workspace` - The workspace the projects belong to.
+/// * `projects` - The projects to install, e.g. from `Workspace::select`.
 ///
-/// # Returns
+/// # Errors
 ///
-/// * `Result<(), BoxError>` - Ok(()) if all dependencies are installed successfully,
-///   or an error if something goes wrong during the installation process.
+/// This function will return an error if the dependency graph cannot be built
+/// or npm install fails in one of the projects.
 ///
-/// # Errors
+/// # Examples
 ///
-/// This function will return an error if:
-/// * The directory cannot be read
-/// * There's an issue accessing a subdirectory
-/// * The `install_project_if_npm` function returns an error
-fn install_dependencies_in_dir(dir: &Path) -> Result<(), BoxError> {
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir).map_err(BoxError::from)? {
-            let entry = entry.map_err(BoxError::from)?;
-            let path = entry.path();
-            if path.is_dir() {
-                if is_npm_project(&path) {
-                    run_npm_install(&path)?;
-                }
-                // TODO: Add checks and installations for other project types
-            }
-        }
+/// ```no_run
+/// use khadim_rs::init::install_dependencies_of;
+/// use khadim_rs::workspace::Workspace;
+/// use std::path::Path;
+///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+/// let libs: Vec<_> = workspace.libs().collect();
+/// install_dependencies_of(&workspace, &libs).unwrap();
+/// ```
+pub fn install_dependencies_of(
+    workspace: &Workspace,
+    projects: &[&Project],
+) -> Result<(), KhadimError> {
+    info!("📚 Installing project dependencies...");
+    let graph = DependencyGraph::build(workspace)?;
+
+    // Install each level in parallel once all of its dependencies are installed
+    for level in graph.levels() {
+        let level: Vec<&Project> = level
+            .into_iter()
+            .filter(|project| {
+                projects
+                    .iter()
+                    .any(|selected| selected.path == project.path)
+            })
+            .collect();
+        install_dependencies_in_parallel(level, workspace.config.max_workers())?;
     }
     Ok(())
 }
 
-/// Installs dependencies for all npm projects in the "apps" directory in parallel.
+/// Installs dependencies for the given projects in parallel.
 ///
-/// This function uses the `rayon` crate to install dependencies for all npm projects
-/// in the "apps" directory in parallel, with a maximum number of workers based
-/// on the available CPU cores.
+/// This function uses the `rayon` crate to install dependencies for all
+/// given projects in parallel, with at most `max_workers` at once.
 ///
 /// # Arguments
 ///
-/// * `dir` - A `&Path` representing the directory to search for npm projects.
+/// * `projects` - The workspace projects to install. They must not depend on each other.
+/// * `max_workers` - The most projects installed at once, from `maxParallelism`.
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if all dependencies are installed successfully,
+/// * `Result<(), KhadimError>` - Ok(()) if all dependencies are installed successfully,
 ///   or an error if something goes wrong during the installation process.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
-/// * The directory cannot be read
-/// * There's an issue accessing a subdirectory
+/// * The thread pool cannot be created
 /// * The `run_npm_install` function returns an error
-fn install_dependencies_in_apps_parallel(dir: &Path) -> Result<(), BoxError> {
-    if !dir.is_dir() {
+fn install_dependencies_in_parallel(
+    projects: Vec<&Project>,
+    max_workers: usize,
+) -> Result<(), KhadimError> {
+    if projects.is_empty() {
         return Ok(());
     }
 
-    let cpu_count = num_cpus::get();
-    let max_workers = std::cmp::max(1, cpu_count - 1); // Use all cores except one
-    println!(
-        "🚀 Installing app dependencies in parallel (max {} workers)",
+    info!(
+        "🚀 Installing dependencies of {} projects in parallel (max {} workers)",
+        projects.len(),
         max_workers
     );
 
     let pool = rayon::ThreadPoolBuilder::new()
         .num_threads(max_workers)
         .build()
-        .map_err(BoxError::from)?;
+        .map_err(KhadimError::from)?;
 
     let completed_count = Arc::new(AtomicUsize::new(0));
-    let total_count = Arc::new(AtomicUsize::new(0));
+    let total = projects.len();
 
-    let result: Result<(), BoxError> = pool.install(|| {
-        fs::read_dir(dir)
-            .map_err(BoxError::from)?
-            .filter_map(Result::ok)
-            .filter(|entry| entry.path().is_dir())
-            .collect::<Vec<_>>()
+    pool.install(|| {
+        projects
             .par_iter()
-            .try_for_each(|entry| -> Result<(), BoxError> {
-                let path = entry.path();
-                total_count.fetch_add(1, Ordering::SeqCst);
-                if is_npm_project(&path) {
-                    run_npm_install(&path)?;
-                    let completed = completed_count.fetch_add(1, Ordering::SeqCst) + 1;
-                    let total = total_count.load(Ordering::SeqCst);
-                    println!("Progress: {}/{} apps completed", completed, total);
-                }
+            .try_for_each(|project| -> Result<(), KhadimError> {
+                logger::in_project(&project.name, || {
+                    npm_install(&project.path, Some(&project.name))
+                })?;
+                // TODO: Add checks and installations for other project types
+                let completed = completed_count.fetch_add(1, Ordering::SeqCst) + 1;
+                info!("Progress: {}/{} projects completed", completed, total);
                 Ok(())
             })
-    });
-
-    result
-}
-
-/// Determines if a given directory is an npm project.
-///
-/// This function checks for the existence of a `package.json` file
-/// in the provided directory, which is a standard indicator of an npm project.
-///
-/// # Arguments
-///
-/// * `project_dir` - A `&Path` representing the directory to check.
-///
-/// # Returns
-///
-/// * `bool` - Returns `true` if a `package.json` file exists in the directory,
-///            indicating it's likely an npm project. Returns `false` otherwise.
-fn is_npm_project(project_dir: &Path) -> bool {
-    let package_json_path = project_dir.join(PACKAGE_JSON);
-    package_json_path.exists()
+    })
 }
 
 /// Example usage of run_npm_install
 ///
-/// ```
+/// ```no_run
 /// use std::path::Path;
-/// use khadim-rs::init::run_npm_install;
+/// use khadim_rs::init::run_npm_install;
 ///
 /// let project_dir = Path::new("/path/to/project");
 /// match run_npm_install(project_dir) {
 ///     Ok(_) => println!("npm install completed successfully."),
 ///     Err(e) => eprintln!("Error running npm install: {}", e),
 /// }
-/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// # Ok::<(), khadim_rs::error::KhadimError>(())
 /// ```
-pub fn run_npm_install(dir: &Path) -> Result<(), BoxError> {
-    println!("🛠️ Running npm install in {}...", dir.display());
-    let output = Command::new("npm")
-        .arg("install")
-        .current_dir(dir)
-        .output()
-        .map_err(BoxError::from)?;
+pub fn run_npm_install(dir: &Path) -> Result<(), KhadimError> {
+    npm_install(dir, None)
+}
+
+/// Runs `npm install`, reporting it as a step of the given project. In
+/// dry-run mode, the command is only planned.
+fn npm_install(dir: &Path, project: Option<&str>) -> Result<(), KhadimError> {
+    if plan::is_dry_run() {
+        plan::perform(Action::run_command("npm", &["install"], dir))?;
+        return Ok(());
+    }
+
+    let step = Step::start("npm install", project, Some(dir));
+    let result = run_install_command(dir);
+    step.finish(&result);
+    result
+}
+
+/// Runs `npm install` in the directory, capturing its output.
+fn run_install_command(dir: &Path) -> Result<(), KhadimError> {
+    info!("🛠️ Running npm install in {}...", dir.display());
+    let output = plan::execute(&Action::run_command("npm", &["install"], dir))?
+        .ok_or("❌ npm install did not run 😢")?;
+    for line in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+    {
+        trace!("{}", line);
+    }
 
     if output.status.success() {
-        println!(
+        info!(
             "✅ npm install completed successfully in {} 🎉",
             dir.display()
         );
         Ok(())
     } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        Err(format!(
-            "❌ npm install failed in {}: {} 😢",
-            dir.display(),
-            error_message
-        )
-        .into())
+        Err(KhadimError::subprocess(
+            "npm install",
+            dir,
+            output.status.code(),
+            &output.stderr,
+        ))
     }
 }
@@ -0,0 +1,467 @@
+use crate::error::KhadimError;
+use crate::plan::{self, Action};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The formatting of a JSON file that is kept when it is rewritten.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonStyle {
+    /// The indentation of one level, e.g. two spaces or a tab.
+    pub indent: String,
+    /// The line ending, `\n` or `\r\n`.
+    pub newline: String,
+    /// Whether the file ends with a newline.
+    pub final_newline: bool,
+    /// The JSON pointers of the non-empty arrays written on a single line,
+    /// e.g. `/files` for `"files": ["dist"]`.
+    pub inline_arrays: BTreeSet<String>,
+}
+
+impl Default for JsonStyle {
+    /// Two spaces and a final newline, as written by npm and prettier.
+    fn default() -> JsonStyle {
+        JsonStyle {
+            indent: "  ".to_string(),
+            newline: "\n".to_string(),
+            final_newline: true,
+            inline_arrays: BTreeSet::new(),
+        }
+    }
+}
+
+impl JsonStyle {
+    /// Detects the style of JSON content from its first indented line, its
+    /// line endings and its arrays written on a single line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::json_file::JsonStyle;
+    ///
+    /// let style = JsonStyle::detect("{\n\t\"name\": \"root\"\n}");
+    /// assert_eq!(style.indent, "\t");
+    /// assert!(!style.final_newline);
+    /// ```
+    pub fn detect(content: &str) -> JsonStyle {
+        let indent = content
+            .lines()
+            .skip(1)
+            .find(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .collect::<String>()
+            })
+            .filter(|indent| !indent.is_empty())
+            .unwrap_or_else(|| JsonStyle::default().indent);
+
+        let newline = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+
+        JsonStyle {
+            indent,
+            newline: newline.to_string(),
+            final_newline: content.ends_with('\n'),
+            inline_arrays: find_inline_arrays(content),
+        }
+    }
+}
+
+/// An array or object that is open while scanning JSON content.
+struct OpenContainer {
+    pointer: String,
+    is_array: bool,
+    /// The line of the opening bracket.
+    line: usize,
+    /// The number of values read so far.
+    length: usize,
+    /// The last key read, in an object.
+    key: String,
+    expects_key: bool,
+}
+
+/// Returns the JSON pointers of the non-empty arrays that open and close on
+/// the same line of valid JSON content.
+fn find_inline_arrays(content: &str) -> BTreeSet<String> {
+    let mut inline_arrays = BTreeSet::new();
+    let mut open: Vec<OpenContainer> = Vec::new();
+    let mut line = 0;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            ',' => {
+                if let Some(container) = open.last_mut() {
+                    container.expects_key = !container.is_array;
+                }
+            }
+            ']' | '}' => {
+                if let Some(container) = open.pop() {
+                    if container.is_array && container.line == line && container.length > 0 {
+                        inline_arrays.insert(container.pointer);
+                    }
+                }
+            }
+            ':' => {}
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut end = start + 1;
+                let mut escaped = false;
+                for (index, c) in chars.by_ref() {
+                    end = index + 1;
+                    match c {
+                        '"' if !escaped => break,
+                        '\\' => escaped = !escaped,
+                        _ => escaped = false,
+                    }
+                }
+                match open.last_mut() {
+                    Some(container) if container.expects_key => {
+                        container.key =
+                            serde_json::from_str(&content[start..end]).unwrap_or_default();
+                        container.expects_key = false;
+                    }
+                    container => {
+                        count_value(container);
+                    }
+                }
+            }
+            '[' | '{' => {
+                let pointer = count_value(open.last_mut());
+                open.push(OpenContainer {
+                    pointer,
+                    is_array: c == '[',
+                    line,
+                    length: 0,
+                    key: String::new(),
+                    expects_key: c == '{',
+                });
+            }
+            _ => {
+                // The rest of a number, true, false or null
+                while chars
+                    .next_if(|&(_, c)| !matches!(c, ',' | ']' | '}') && !c.is_whitespace())
+                    .is_some()
+                {}
+                count_value(open.last_mut());
+            }
+        }
+    }
+    inline_arrays
+}
+
+/// Counts a value read in the open container, and returns its JSON pointer.
+fn count_value(container: Option<&mut OpenContainer>) -> String {
+    match container {
+        Some(container) if container.is_array => {
+            container.length += 1;
+            child_pointer(&container.pointer, &(container.length - 1).to_string())
+        }
+        Some(container) => child_pointer(&container.pointer, &container.key),
+        None => String::new(),
+    }
+}
+
+/// Returns the JSON pointer of a key or index inside the given pointer.
+fn child_pointer(pointer: &str, key: &str) -> String {
+    format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"))
+}
+
+/// A JSON file, such as a package.json, that is edited without reformatting it.
+///
+/// Keys keep their original order, and the file is written back with its
+/// original indentation, line endings, final newline and single-line arrays,
+/// so that edits only show up as the lines that actually changed.
+#[derive(Debug, Clone)]
+pub struct JsonFile {
+    pub path: PathBuf,
+    pub value: Value,
+    pub style: JsonStyle,
+}
+
+impl JsonFile {
+    /// Reads and parses a JSON file, remembering its style.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * The file cannot be read
+    /// * The file is not valid JSON
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::json_file::{insert_sorted, JsonFile};
+    /// use serde_json::json;
+    /// use std::path::Path;
+    ///
+    /// let mut package_json = JsonFile::read(Path::new("package.json")).unwrap();
+    /// insert_sorted(&mut package_json.value, "dependencies", "zod", json!("^3.23.8"));
+    /// package_json.write().unwrap();
+    /// ```
+    pub fn read(path: &Path) -> Result<JsonFile, KhadimError> {
+        let content = plan::read_to_string(path).map_err(|e| KhadimError::io(path, e))?;
+        let value =
+            serde_json::from_str(&content).map_err(|e| KhadimError::manifest_parse(path, &e))?;
+        Ok(JsonFile {
+            path: path.to_path_buf(),
+            value,
+            style: JsonStyle::detect(&content),
+        })
+    }
+
+    /// Creates a file that has not been read, to be written with the given style.
+    pub fn new(path: &Path, value: Value, style: JsonStyle) -> JsonFile {
+        JsonFile {
+            path: path.to_path_buf(),
+            value,
+            style,
+        }
+    }
+
+    /// Serializes the value with the file's style.
+    ///
+    /// Objects and arrays are written one entry per line, except for the
+    /// arrays that were on a single line, such as `"files": ["dist"]`.
+    pub fn render(&self) -> Result<String, KhadimError> {
+        let mut output = String::new();
+        self.render_value(&self.value, "", 0, &mut output)
+            .map_err(|e| KhadimError::io(&self.path, e.into()))?;
+        if self.style.final_newline {
+            output.push_str(&self.style.newline);
+        }
+        Ok(output)
+    }
+
+    fn render_value(
+        &self,
+        value: &Value,
+        pointer: &str,
+        depth: usize,
+        output: &mut String,
+    ) -> Result<(), serde_json::Error> {
+        let indent = |depth| self.style.indent.repeat(depth);
+        match value {
+            Value::Object(object) if !object.is_empty() => {
+                output.push('{');
+                for (index, (key, value)) in object.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+                    output.push_str(&self.style.newline);
+                    output.push_str(&indent(depth + 1));
+                    output.push_str(&serde_json::to_string(key)?);
+                    output.push_str(": ");
+                    self.render_value(value, &child_pointer(pointer, key), depth + 1, output)?;
+                }
+                output.push_str(&self.style.newline);
+                output.push_str(&indent(depth));
+                output.push('}');
+            }
+            Value::Array(array) if self.style.inline_arrays.contains(pointer) => {
+                let items = array
+                    .iter()
+                    .map(serde_json::to_string)
+                    .collect::<Result<Vec<_>, _>>()?;
+                output.push('[');
+                output.push_str(&items.join(", "));
+                output.push(']');
+            }
+            Value::Array(array) if !array.is_empty() => {
+                output.push('[');
+                for (index, value) in array.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+                    output.push_str(&self.style.newline);
+                    output.push_str(&indent(depth + 1));
+                    let pointer = child_pointer(pointer, &index.to_string());
+                    self.render_value(value, &pointer, depth + 1, output)?;
+                }
+                output.push_str(&self.style.newline);
+                output.push_str(&indent(depth));
+                output.push(']');
+            }
+            // Scalars, `{}` and `[]`
+            value => output.push_str(&serde_json::to_string(value)?),
+        }
+        Ok(())
+    }
+
+    /// Writes the value back to the file with its style, through a temporary
+    /// file so that an interrupted write never leaves a truncated file behind.
+    pub fn write(&self) -> Result<(), KhadimError> {
+        plan::perform(Action::WriteFile {
+            path: self.path.clone(),
+            content: self.render()?,
+        })?;
+        Ok(())
+    }
+}
+
+/// Sets a key of an object inside a JSON value, e.g. a package in `dependencies`.
+///
+/// An existing key keeps its position. A new key is inserted before the first
+/// key that sorts after it, so sorted objects stay sorted. The object is
+/// created at the end of the value if it does not exist.
+///
+/// # Examples
+///
+/// ```
+/// use khadim_rs::json_file::insert_sorted;
+/// use serde_json::json;
+///
+/// let mut manifest = json!({ "dependencies": { "next": "14.2.8", "react": "^18" } });
+/// insert_sorted(&mut manifest, "dependencies", "perfect-freehand", json!("^1.2.2"));
+/// let keys: Vec<&String> = manifest["dependencies"].as_object().unwrap().keys().collect();
+/// assert_eq!(keys, ["next", "perfect-freehand", "react"]);
+/// ```
+pub fn insert_sorted(value: &mut Value, section: &str, key: &str, entry: Value) {
+    if !value[section].is_object() {
+        value[section] = Value::Object(Map::new());
+    }
+    let object = match value[section].as_object_mut() {
+        Some(object) => object,
+        None => return,
+    };
+
+    if let Some(existing) = object.get_mut(key) {
+        *existing = entry;
+        return;
+    }
+
+    let mut entry = Some(entry);
+    let mut sorted = Map::new();
+    for (existing_key, existing_value) in std::mem::take(object) {
+        if existing_key.as_str() > key {
+            if let Some(entry) = entry.take() {
+                sorted.insert(key.to_string(), entry);
+            }
+        }
+        sorted.insert(existing_key, existing_value);
+    }
+    if let Some(entry) = entry {
+        sorted.insert(key.to_string(), entry);
+    }
+    *object = sorted;
+}
+
+/// Removes a key from an object inside a JSON value, keeping the order of the
+/// remaining keys. Returns whether the key was present.
+pub fn remove_entry(value: &mut Value, section: &str, key: &str) -> bool {
+    value
+        .get_mut(section)
+        .and_then(Value::as_object_mut)
+        .is_some_and(|object| object.shift_remove(key).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Parses and re-renders content without changing the value.
+    fn round_trip(content: &str) -> String {
+        let value = serde_json::from_str(content).unwrap();
+        JsonFile::new(Path::new("package.json"), value, JsonStyle::detect(content))
+            .render()
+            .unwrap()
+    }
+
+    #[test]
+    fn detects_indentation_and_newlines() {
+        let style = JsonStyle::detect("{\r\n    \"name\": \"a\"\r\n}\r\n");
+        assert_eq!(style.indent, "    ");
+        assert_eq!(style.newline, "\r\n");
+        assert!(style.final_newline);
+
+        let style = JsonStyle::detect("{}");
+        assert_eq!(style.indent, "  ");
+        assert_eq!(style.newline, "\n");
+        assert!(!style.final_newline);
+    }
+
+    #[test]
+    fn round_trips_formatting() {
+        for content in [
+            "{\n  \"name\": \"a\",\n  \"dependencies\": {\n    \"zod\": \"^3\"\n  }\n}\n",
+            "{\n\t\"name\": \"a\",\n\t\"private\": true\n}\n",
+            "{\r\n  \"name\": \"a\",\r\n  \"description\": \"one\\ntwo\"\r\n}\r\n",
+            "{\n    \"zeta\": 1,\n    \"alpha\": [\n        2\n    ]\n}",
+        ] {
+            assert_eq!(round_trip(content), content);
+        }
+    }
+
+    #[test]
+    fn keeps_arrays_on_a_single_line() {
+        for content in [
+            "{\n  \"files\": [\"dist\"],\n  \"workspaces\": [\"apps/*\", \"libs/*\"]\n}\n",
+            "{\r\n  \"files\": [\"dist\", \"README.md\"],\r\n  \"keywords\": []\r\n}\r\n",
+            "{\n  \"browserslist\": {\n    \"production\": [\">0.2%\", \"not dead\"],\n    \"development\": [\n      \"last 1 chrome version\"\n    ]\n  }\n}\n",
+            "{\n  \"exports\": {\n    \"./utils\": [\"./dist/utils.js\"],\n    \"~\": [1, true, null]\n  }\n}\n",
+            "{\n  \"a\": \"] \\\" [\",\n  \"b\": [\n    [\"x\"],\n    [\n      \"y\"\n    ]\n  ]\n}\n",
+        ] {
+            assert_eq!(round_trip(content), content);
+        }
+    }
+
+    #[test]
+    fn finds_inline_arrays_by_pointer() {
+        let style = JsonStyle::detect(
+            "{\"files\": [\"dist\"], \"a/b\": {\"c~d\": [[1], [\n2]]}, \"empty\": [], \"n\": -1.5e3}",
+        );
+        let pointers: Vec<&str> = style.inline_arrays.iter().map(String::as_str).collect();
+        assert_eq!(pointers, ["/a~1b/c~0d/0", "/files"]);
+    }
+
+    #[test]
+    fn edits_keep_inline_arrays_inline() {
+        let content = "{\n  \"name\": \"a\",\n  \"files\": [\"dist\"]\n}\n";
+        let mut file = JsonFile::new(
+            Path::new("package.json"),
+            serde_json::from_str(content).unwrap(),
+            JsonStyle::detect(content),
+        );
+        file.value["files"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!("types"));
+        file.value["keywords"] = json!(["cli"]);
+
+        assert_eq!(
+            file.render().unwrap(),
+            "{\n  \"name\": \"a\",\n  \"files\": [\"dist\", \"types\"],\n  \"keywords\": [\n    \"cli\"\n  ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn insert_sorted_keeps_order() {
+        let mut value = json!({ "name": "a", "dependencies": { "next": "14", "react": "18" } });
+        insert_sorted(&mut value, "dependencies", "axios", json!("^1"));
+        insert_sorted(&mut value, "dependencies", "zod", json!("^3"));
+        insert_sorted(&mut value, "dependencies", "next", json!("15"));
+        insert_sorted(&mut value, "devDependencies", "vitest", json!("^2"));
+
+        let keys: Vec<&String> = value["dependencies"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["axios", "next", "react", "zod"]);
+        assert_eq!(value["dependencies"]["next"], "15");
+        let sections: Vec<&String> = value.as_object().unwrap().keys().collect();
+        assert_eq!(sections, ["name", "dependencies", "devDependencies"]);
+    }
+
+    #[test]
+    fn remove_entry_keeps_order() {
+        let mut value = json!({ "dependencies": { "a": "1", "b": "2", "c": "3" } });
+        assert!(remove_entry(&mut value, "dependencies", "b"));
+        assert!(!remove_entry(&mut value, "dependencies", "b"));
+        assert!(!remove_entry(&mut value, "devDependencies", "a"));
+        let keys: Vec<&String> = value["dependencies"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["a", "c"]);
+    }
+}
@@ -1,9 +1,27 @@
 // Add this to expose the modules for testing
+pub mod affected;
+pub mod cache;
+pub mod check_versions;
+pub mod completions;
 pub mod config;
 pub mod dependencies;
 pub mod doctor;
+pub mod error;
+pub mod graph;
 pub mod init;
+pub mod json_file;
+pub mod list;
+pub mod logger;
+pub mod man_page;
+pub mod outdated;
+pub mod output;
+pub mod package_spec;
+pub mod plan;
+pub mod registry;
 pub mod reset;
-
-// Add this line to define BoxError at the crate level
-pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+pub mod root;
+pub mod run;
+pub mod sync;
+pub mod transaction;
+pub mod version_range;
+pub mod workspace;
@@ -0,0 +1,73 @@
+use crate::error::KhadimError;
+use crate::output;
+use crate::root::find_root_dir;
+use crate::workspace::{ProjectKind, Workspace};
+use log::info;
+use serde_json::json;
+
+/// Prints every app and lib of the workspace, libs first, with its npm
+/// package name and scripts.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * The workspace cannot be discovered
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::list::print_projects;
+///
+/// print_projects().unwrap();
+/// ```
+pub fn print_projects() -> Result<(), KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+
+    let items: Vec<_> = workspace
+        .projects
+        .iter()
+        .map(|project| {
+            json!({
+                "project": project.name,
+                "package": project.package_name(),
+                "kind": match project.kind {
+                    ProjectKind::Lib => "lib",
+                    ProjectKind::App => "app",
+                },
+                "dir": project.relative_dir(),
+                "scripts": project.manifest.scripts.keys().collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    output::report("projects", &items);
+
+    if workspace.projects.is_empty() {
+        info!("No apps or libs found in {}", root_dir.display());
+        return Ok(());
+    }
+
+    let dir_width = workspace
+        .projects
+        .iter()
+        .map(|project| project.relative_dir().display().to_string().len())
+        .max()
+        .unwrap_or(0);
+    for project in &workspace.projects {
+        let scripts: Vec<&str> = project
+            .manifest
+            .scripts
+            .keys()
+            .map(String::as_str)
+            .collect();
+        info!(
+            "{:<width$}  {}  [{}]",
+            project.relative_dir().display().to_string(),
+            project.package_name(),
+            scripts.join(", "),
+            width = dir_width
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use crate::error::KhadimError;
+use crate::output;
+use crate::plan;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// The directory, relative to the root directory, that holds the log files of past runs.
+pub const LOG_DIR: &str = ".khadim-logs";
+
+/// The log file of this run, if one was requested.
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+thread_local! {
+    /// The project the current thread is working on, used as a message prefix.
+    static PROJECT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// How much is logged to the console, and where else to log.
+#[derive(Debug, Clone, Default)]
+pub struct LoggerOptions {
+    /// The number of `-v` flags: 1 adds debug messages such as the commands
+    /// being run, 2 also adds the captured output of those commands.
+    pub verbosity: u8,
+    /// Only log warnings and errors to the console.
+    pub quiet: bool,
+    /// Prefix every console message with an RFC 3339 timestamp.
+    pub timestamps: bool,
+    /// Also log everything, at every level and with timestamps, to this file.
+    pub log_file: Option<PathBuf>,
+}
+
+impl LoggerOptions {
+    /// Returns the most verbose level logged to the console.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::logger::LoggerOptions;
+    /// use log::LevelFilter;
+    ///
+    /// let options = LoggerOptions { verbosity: 2, ..LoggerOptions::default() };
+    /// assert_eq!(options.console_level(), LevelFilter::Trace);
+    /// let options = LoggerOptions { quiet: true, ..LoggerOptions::default() };
+    /// assert_eq!(options.console_level(), LevelFilter::Warn);
+    /// ```
+    pub fn console_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbosity) {
+            (true, _) => LevelFilter::Warn,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Logs to the console, and to the log file of the run if there is one.
+///
+/// Info and lower levels go to stdout, warnings and errors to stderr. In JSON
+/// mode nothing is logged to the console, since stdout carries the events.
+struct Logger {
+    console_level: LevelFilter,
+    timestamps: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Libraries such as ureq only get to log their warnings and errors
+        let is_own = metadata.target().starts_with(env!("CARGO_CRATE_NAME"));
+        if !is_own && metadata.level() > Level::Warn {
+            return false;
+        }
+        metadata.level() <= self.console_level || LOG_FILE.get().is_some()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Everything logged in a dry run describes what would happen
+        let dry_run = if plan::is_dry_run() { "[dry run] " } else { "" };
+        let prefix = format!("{}{}", dry_run, project_prefix());
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now());
+        // Blank lines that separate sections stay in front of the timestamp
+        let message = record.args().to_string();
+        let text = message.trim_start_matches('\n');
+        let blank_lines = &message[..message.len() - text.len()];
+
+        if record.level() <= self.console_level && !output::is_json() {
+            let line = if self.timestamps {
+                format!("{}{} {}{}", blank_lines, timestamp, prefix, text)
+            } else {
+                format!("{}{}{}", blank_lines, prefix, text)
+            };
+            // Write errors, such as a closed pipe, are ignored like a lost message
+            let _ = match record.level() {
+                Level::Error | Level::Warn => writeln!(io::stderr().lock(), "{}", line),
+                _ => writeln!(io::stdout().lock(), "{}", line),
+            };
+        }
+
+        write_to_file(&format!(
+            "{} {:<5} {}{}",
+            timestamp,
+            record.level(),
+            prefix,
+            text
+        ));
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+        if let Some(file) = LOG_FILE.get() {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Installs the logger for the rest of the process.
+///
+/// Without it, as when khadim-rs is used as a library, messages go to
+/// whatever `log` implementation the caller installed, if any.
+///
+/// # Arguments
+///
+/// * `options` - The console verbosity and the optional log file.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The log file or its directory cannot be created
+/// * A logger is already installed
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::logger::{self, LoggerOptions};
+///
+/// logger::init(LoggerOptions { verbosity: 1, ..LoggerOptions::default() }).unwrap();
+/// log::debug!("Only shown with -v");
+/// ```
+pub fn init(options: LoggerOptions) -> Result<(), KhadimError> {
+    if let Some(path) = &options.log_file {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| KhadimError::io(dir, e))?;
+        }
+        let file = File::create(path).map_err(|e| KhadimError::io(path, e))?;
+        let _ = LOG_FILE.set(Mutex::new(file));
+    }
+
+    let console_level = options.console_level();
+    let max_level = if LOG_FILE.get().is_some() {
+        LevelFilter::Trace
+    } else {
+        console_level
+    };
+    log::set_boxed_logger(Box::new(Logger {
+        console_level,
+        timestamps: options.timestamps,
+    }))
+    .map_err(|e| KhadimError::Other(format!("❌ Cannot install logger: {} 😢", e)))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Returns a new log file path under the root directory, named after the
+/// current time, e.g. `.khadim-logs/2024-09-04T08-15-02Z-reset.log`.
+pub fn log_file_path(root_dir: &Path, command: &str) -> PathBuf {
+    let timestamp = humantime::format_rfc3339_seconds(SystemTime::now())
+        .to_string()
+        .replace(':', "-");
+    root_dir
+        .join(LOG_DIR)
+        .join(format!("{}-{}.log", timestamp, command))
+}
+
+/// Runs a closure with every message it logs from this thread prefixed with
+/// `[project]`, so that the messages of projects handled in parallel can be
+/// told apart.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::logger::in_project;
+///
+/// // Logs "[organic-lever-web] Installing..."
+/// in_project("organic-lever-web", || log::info!("Installing..."));
+/// ```
+pub fn in_project<T>(project: &str, f: impl FnOnce() -> T) -> T {
+    let previous = PROJECT.with(|current| current.replace(Some(project.to_string())));
+    let result = f();
+    PROJECT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Returns the prefix of the project the current thread is working on, e.g.
+/// `[organic-lever-web] `, or an empty string.
+pub fn project_prefix() -> String {
+    PROJECT.with(|project| {
+        project
+            .borrow()
+            .as_ref()
+            .map(|project| format!("[{}] ", project))
+            .unwrap_or_default()
+    })
+}
+
+/// Writes a line to the log file of the run only, e.g. a line of output of
+/// an npm script that is already shown on the console.
+pub fn write_to_file(line: &str) {
+    if let Some(file) = LOG_FILE.get() {
+        if let Ok(mut file) = file.lock() {
+            let _ = writeln!(file, "{}", line.trim_end());
+        }
+    }
+}
@@ -1,104 +1,716 @@
-mod config;
-mod dependencies;
-mod doctor;
-mod init;
-mod reset;
-
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, ErrorKind};
+use khadim_rs::affected::{self, ChangeSource};
+use khadim_rs::check_versions::{self, print_conflicts};
+use khadim_rs::completions::{self, COMPLETE_COMMAND};
+use khadim_rs::config::CACHE_DIR;
+use khadim_rs::error::KhadimError;
+use khadim_rs::logger::{self, LoggerOptions};
+use khadim_rs::man_page::render_man_page;
+use khadim_rs::package_spec::SavePrefix;
+use khadim_rs::reset::{ResetOptions, ResetScope};
+use khadim_rs::{dependencies, doctor, init, list, outdated, output, plan, reset, root, run, sync};
+use log::{error, info};
+use std::path::{Path, PathBuf};
 use std::process;
-extern crate num_cpus;
-extern crate rayon;
+use std::time::Instant;
+
+/// The flags that used to select the command, with the command that replaces
+/// each of them, kept working during the migration to subcommands.
+const LEGACY_FLAGS: [(&str, &str, &str); 5] = [
+    ("init", "init", "init"),
+    ("doctor", "doctor", "doctor"),
+    ("reset", "reset", "reset"),
+    ("deps", "add", "add"),
+    ("deps-dev", "add", "add --dev"),
+];
+
+/// The name of the binary, as completed by the shell and named in the man page.
+const BIN_NAME: &str = "khadim-rs";
+
+fn main() {
+    let matches = parser().get_matches();
 
-use crate::config::PACKAGE_TMPL_JSON;
+    // Run by the completion scripts on every tab, so it skips logging entirely
+    if let Some(complete_matches) = matches.subcommand_matches(COMPLETE_COMMAND) {
+        let words: Vec<String> = complete_matches
+            .values_of("words")
+            .map(|values| values.map(str::to_string).collect())
+            .unwrap_or_default();
+        match completions::complete(&words) {
+            Some(candidates) => {
+                for candidate in candidates {
+                    println!("{}", candidate);
+                }
+                process::exit(0);
+            }
+            None => process::exit(1),
+        }
+    }
 
-type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+    if let Some(format) = matches.value_of("output") {
+        if let Ok(format) = format.parse() {
+            output::set_format(format);
+        }
+    }
+    if let Some(root_dir) = matches.value_of("root") {
+        root::set_root_dir(Path::new(root_dir));
+    }
+    let invocation = Invocation::new(&matches);
+
+    // The log file needs the root, so it is skipped with a warning outside a workspace
+    let root_dir = matches.is_present("log-file").then(root::find_root_dir);
+    let log_file = match &root_dir {
+        Some(Ok(root_dir)) => Some(logger::log_file_path(root_dir, &invocation.command)),
+        _ => None,
+    };
+    let options = LoggerOptions {
+        verbosity: matches.occurrences_of("verbose").min(u8::MAX as u64) as u8,
+        quiet: matches.is_present("quiet"),
+        timestamps: matches.is_present("timestamps"),
+        log_file: log_file.clone(),
+    };
+    if let Err(e) = logger::init(options) {
+        eprintln!("Error starting the log: {}", e);
+        invocation.exit(e.exit_code());
+    }
+    match (root_dir, log_file) {
+        (Some(Err(e)), _) => output::warn(&format!("Not writing a log file: {}", e)),
+        (_, Some(log_file)) => info!("📝 Logging to {}", log_file.display()),
+        _ => {}
+    }
+    if let Some((flag, _, replacement)) = LEGACY_FLAGS
+        .iter()
+        .find(|(flag, _, _)| matches.is_present(flag))
+    {
+        output::warn(&format!(
+            "--{} is deprecated, use `khadim-rs {}` instead",
+            flag, replacement
+        ));
+    }
 
-fn main() -> Result<(), BoxError> {
-    let reset_help = format!(
-        "Reset the project: delete package.json and node_modules, then reinitialize using {}",
-        PACKAGE_TMPL_JSON
-    );
+    if matches.is_present("dry-run") {
+        if matches!(invocation.command.as_str(), "run" | "run-all") {
+            // Scripts cannot be planned, so this is a usage error like a conflict
+            parser()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!(
+                        "{} does not support --dry-run, scripts cannot run without their side effects",
+                        invocation.command
+                    ),
+                )
+                .exit();
+        }
+        plan::set_dry_run(true);
+    }
 
-    let matches = App::new("Repo Manager")
+    match matches.subcommand() {
+        Some(("check-versions", check_matches)) => {
+            let conflicts = match check_versions::check_versions() {
+                Ok(conflicts) => conflicts,
+                Err(e) => {
+                    invocation.fail("Error checking dependency versions", e);
+                }
+            };
+            print_conflicts(&conflicts);
+            if check_matches.is_present("fix") {
+                if !conflicts.is_empty() {
+                    if let Err(e) = check_versions::fix_versions(&conflicts) {
+                        invocation.fail("Error unifying dependency versions", e);
+                    }
+                }
+            } else if !conflicts.is_empty() {
+                invocation.exit(1);
+            }
+        }
+        Some(("sync", sync_matches)) => {
+            match sync::sync_package_json(
+                sync_matches.is_present("check"),
+                sync_matches.is_present("yes"),
+            ) {
+                Ok(true) => {}
+                Ok(false) => invocation.exit(1),
+                Err(e) => {
+                    invocation.fail("Error syncing package.json", e);
+                }
+            }
+        }
+        Some(("outdated", _)) => match outdated::outdated() {
+            Ok(dependencies) => outdated::print_outdated(&dependencies),
+            Err(e) => {
+                invocation.fail("Error checking outdated dependencies", e);
+            }
+        },
+        Some(("upgrade", upgrade_matches)) => {
+            let packages: Vec<&str> = upgrade_matches
+                .values_of("packages")
+                .map(|values| values.collect())
+                .unwrap_or_default();
+            if let Err(e) = outdated::upgrade(packages, upgrade_matches.is_present("major")) {
+                invocation.fail("Error upgrading dependencies", e);
+            }
+        }
+        Some(("remove", remove_matches)) => {
+            let packages: Vec<&str> = remove_matches
+                .values_of("packages")
+                .map(|values| values.collect())
+                .unwrap_or_default();
+            if let Err(e) = dependencies::remove_dependencies(packages) {
+                invocation.fail("Error removing dependencies", e);
+            }
+        }
+        Some(("run", run_matches)) => {
+            let project = run_matches.value_of("project").unwrap_or_default();
+            let script = run_matches.value_of("script").unwrap_or_default();
+            let args: Vec<&str> = run_matches
+                .values_of("args")
+                .map(|values| values.collect())
+                .unwrap_or_default();
+            match run::run_script(project, script, &args) {
+                Ok(0) => {}
+                Ok(exit_code) => invocation.exit(exit_code),
+                Err(e) => {
+                    invocation.fail("Error running script", e);
+                }
+            }
+        }
+        Some(("run-all", run_all_matches)) => {
+            let script = run_all_matches.value_of("script").unwrap_or_default();
+            let options = run::RunAllOptions {
+                changes: if run_all_matches.is_present("affected") {
+                    Some(change_source(run_all_matches.value_of("base")))
+                } else {
+                    None
+                },
+                cache_dir: if run_all_matches.is_present("no-cache") {
+                    None
+                } else {
+                    run_all_matches.value_of("cache-dir").map(PathBuf::from)
+                },
+            };
+            match run::run_all(script, &options) {
+                Ok(results) => {
+                    let exit_code = run::exit_code(&results);
+                    if exit_code != 0 {
+                        invocation.exit(exit_code);
+                    }
+                }
+                Err(e) => {
+                    invocation.fail("Error running script", e);
+                }
+            }
+        }
+        Some(("affected", affected_matches)) => {
+            let source = change_source(affected_matches.value_of("base"));
+            if let Err(e) = affected::print_affected(&source) {
+                invocation.fail("Error detecting affected projects", e);
+            }
+        }
+        Some(("completions", completions_matches)) => {
+            let shell = completions_matches
+                .value_of("shell")
+                .unwrap_or_default()
+                .parse()
+                .map_err(KhadimError::from);
+            match shell
+                .and_then(|shell| completions::generate_completions(&mut cli(), shell, BIN_NAME))
+            {
+                Ok(script) => print!("{}", script),
+                Err(e) => invocation.fail("Error generating completions", e),
+            }
+        }
+        Some(("man", _)) => {
+            print!("{}", render_man_page(&cli(), BIN_NAME));
+        }
+        Some(("list", _)) => {
+            if let Err(e) = list::print_projects() {
+                invocation.fail("Error listing projects", e);
+            }
+        }
+        Some(("init", _)) => init(&invocation),
+        Some(("doctor", _)) => doctor(&invocation),
+        Some(("reset", reset_matches)) => reset(&invocation, Some(reset_matches)),
+        Some(("add", add_matches)) => add_dependencies(
+            &invocation,
+            add_matches,
+            "packages",
+            add_matches.is_present("dev"),
+        ),
+        Some((command, _)) => unreachable!("unhandled subcommand {}", command),
+        // The deprecated flags, named after the subcommand that replaces them
+        None => match invocation.command.as_str() {
+            "init" => init(&invocation),
+            "doctor" => doctor(&invocation),
+            "reset" => reset(&invocation, None),
+            "add" if matches.is_present("deps") => {
+                add_dependencies(&invocation, &matches, "deps", false)
+            }
+            "add" => add_dependencies(&invocation, &matches, "deps-dev", true),
+            _ => {
+                info!("No command provided. Use --help for usage information.");
+                invocation.exit(1);
+            }
+        },
+    }
+
+    if plan::is_dry_run() {
+        plan::report_dry_run();
+    }
+    invocation.exit(0);
+}
+
+/// The command-line interface, also used to generate the shell completions and the man page.
+fn cli() -> App<'static> {
+    App::new("Repo Manager")
         .version("1.0")
         .author("Your Name")
         .about("Manages repositories")
+        .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .help("Output format: human, or json for one JSON event per line")
+                .takes_value(true)
+                .possible_values(["human", "json"])
+                .default_value("human")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("root")
+                .long("root")
+                .help("Root directory of the monorepo, instead of searching up from the current directory")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Show the files that would be written or deleted, and the commands that would run, without changing anything")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Log the commands being run (-v), and their output (-vv)")
+                .multiple_occurrences(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Only log warnings and errors")
+                .conflicts_with("verbose")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("timestamps")
+                .long("timestamps")
+                .help("Prefix every message with a timestamp")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .help("Write a full log of the run to .khadim-logs at the root")
+                .global(true),
+        )
+        .subcommand(
+            App::new("init")
+                .about("Initialize package.json, run npm install, and install project dependencies"),
+        )
+        .subcommand(
+            App::new("doctor")
+                .about("Check that the tools listed in khadim.json, by default volta, npm and node, are installed"),
+        )
+        .subcommand(
+            App::new("reset")
+                .about("Reset the project: delete package.json and node_modules, then reinitialize using package-tmpl.json")
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short('y')
+                        .help("Delete without asking for confirmation"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Reset even if package.json or package-tmpl.json files have uncommitted changes"),
+                )
+                .arg(
+                    Arg::with_name("only")
+                        .long("only")
+                        .help("Only reset the root package.json and node_modules, the apps or the libs")
+                        .takes_value(true)
+                        .possible_values(["root", "apps", "libs"]),
+                )
+                .arg(
+                    Arg::with_name("project")
+                        .long("project")
+                        .help("Only reset the apps or libs matching a name or glob, e.g. organic-lever-web or 'libs/*'")
+                        .takes_value(true)
+                        .conflicts_with("only"),
+                )
+                .arg(
+                    Arg::with_name("build-outputs")
+                        .long("build-outputs")
+                        .help("Also delete the build outputs of the projects: .next, dist, out and their project.outputs"),
+                )
+                .arg(
+                    Arg::with_name("venvs")
+                        .long("venvs")
+                        .help("Also delete the Python virtual environments of the projects, e.g. venv"),
+                )
+                .arg(
+                    Arg::with_name("npm-cache")
+                        .long("npm-cache")
+                        .help("Also clear the npm cache"),
+                )
+                .arg(
+                    Arg::with_name("no-install")
+                        .long("no-install")
+                        .help("Only delete, without regenerating package.json and reinstalling"),
+                ),
+        )
+        .subcommand(
+            App::new("add")
+                .about("Add dependencies to root package.json and package-tmpl.json, or to apps and libs")
+                .arg(
+                    Arg::with_name("packages")
+                        .help("npm-style package specifiers, e.g. react, next@~14.2 or @scope/pkg@1")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("dev")
+                        .long("dev")
+                        .short('D')
+                        .help("Add to devDependencies instead of dependencies"),
+                )
+                .arg(save_prefix_arg())
+                .arg(project_arg()),
+        )
+        .subcommand(
+            App::new("check-versions")
+                .about("Report packages declared with different ranges across package-tmpl.json and every app and lib")
+                .arg(
+                    Arg::with_name("fix")
+                        .long("fix")
+                        .help("Rewrite the manifests to use the suggested range"),
+                ),
+        )
+        .subcommand(
+            App::new("sync")
+                .about("Regenerate package.json from package-tmpl.json, pulling hand edits back into the template")
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Only report how package.json differs from package-tmpl.json, ignoring scripts"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short('y')
+                        .help("Pull changes into package-tmpl.json without asking"),
+                ),
+        )
+        .subcommand(
+            App::new("outdated").about(
+                "List dependencies of package-tmpl.json and every app and lib that are not on their latest version",
+            ),
+        )
+        .subcommand(
+            App::new("upgrade")
+                .about("Upgrade dependencies of the root and every app and lib, within their major version")
+                .arg(
+                    Arg::with_name("packages")
+                        .help("Names of the packages to upgrade; all dependencies if omitted")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("major")
+                        .long("major")
+                        .help("Upgrade to the latest version even across major versions"),
+                ),
+        )
+        .subcommand(
+            App::new("remove")
+                .about("Remove dependencies from root package.json and package-tmpl.json")
+                .arg(
+                    Arg::with_name("packages")
+                        .help("Names of the packages to remove")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            App::new("run")
+                .about("Run an npm script of a single app or lib, streaming its output")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name("project")
+                        .help("Name of the project under apps or libs")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("script")
+                        .help("Name of the script in the project's package.json")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .help("Extra arguments passed to the script")
+                        .multiple(true)
+                        .last(true),
+                ),
+        )
+        .subcommand(
+            App::new("run-all")
+                .about(
+                    "Run an npm script in every app and lib that defines it, in dependency order",
+                )
+                .arg(
+                    Arg::with_name("script")
+                        .help("Name of the script, e.g. build, test or lint")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("affected")
+                        .long("affected")
+                        .help("Only run projects affected by changes according to git"),
+                )
+                .arg(
+                    Arg::with_name("base")
+                        .long("base")
+                        .help("Base ref to compare HEAD against instead of the working tree")
+                        .takes_value(true)
+                        .requires("affected"),
+                )
+                .arg(
+                    Arg::with_name("cache-dir")
+                        .long("cache-dir")
+                        .help("Directory of the task cache, relative to the root directory")
+                        .takes_value(true)
+                        .default_value(CACHE_DIR),
+                )
+                .arg(
+                    Arg::with_name("no-cache")
+                        .long("no-cache")
+                        .help("Always run the script instead of replaying cached results"),
+                ),
+        )
+        .subcommand(
+            App::new("list").about("List the apps and libs of the workspace, with their package names and scripts"),
+        )
+        .subcommand(
+            App::new("completions")
+                .about("Print the completion script of a shell, which also completes project and script names")
+                .arg(
+                    Arg::with_name("shell")
+                        .help("The shell to complete in")
+                        .required(true)
+                        .possible_values(["bash", "zsh", "fish", "powershell"]),
+                ),
+        )
+        .subcommand(App::new("man").about("Print the man page, e.g. to save it as khadim-rs.1"))
+        .subcommand(
+            App::new("affected")
+                .about("List the apps and libs affected by changes according to git")
+                .arg(
+                    Arg::with_name("base")
+                        .long("base")
+                        .help("Base ref to compare HEAD against instead of the working tree")
+                        .takes_value(true),
+                ),
+        )
+}
+
+/// The command-line interface as parsed: `cli` with the deprecated flags,
+/// kept working during the migration, and the command run by the completion
+/// scripts, neither of which is completed or documented.
+fn parser() -> App<'static> {
+    cli()
         .arg(
             Arg::with_name("init")
                 .long("init")
-                .help("Initialize package.json, run npm install, and install project dependencies")
-                .takes_value(false),
+                .help("Deprecated, use the init command")
+                .hide(true),
         )
         .arg(
             Arg::with_name("doctor")
                 .long("doctor")
-                .help("Check if volta, npm, and node are installed")
-                .takes_value(false),
+                .help("Deprecated, use the doctor command")
+                .hide(true),
         )
         .arg(
             Arg::with_name("reset")
                 .long("reset")
-                .help(reset_help.as_str())
-                .takes_value(false),
+                .help("Deprecated, use the reset command")
+                .hide(true),
         )
         .arg(
             Arg::with_name("deps")
                 .long("deps")
-                .help("Add dependencies to root package.json and package-tmpl.json")
+                .help("Deprecated, use the add command")
                 .takes_value(true)
-                .multiple(true),
+                .multiple(true)
+                .hide(true),
         )
         .arg(
             Arg::with_name("deps-dev")
                 .long("deps-dev")
-                .help("Add dev dependencies to root package.json and package-tmpl.json")
+                .help("Deprecated, use the add command with --dev")
                 .takes_value(true)
-                .multiple(true),
+                .multiple(true)
+                .hide(true),
+        )
+        .arg(save_prefix_arg().hide(true))
+        .arg(project_arg().hide(true))
+        .group(ArgGroup::with_name("legacy").args(&["init", "doctor", "reset", "deps", "deps-dev"]))
+        .subcommand(
+            App::new(COMPLETE_COMMAND)
+                .about("Print the project or script names that complete the given words")
+                .hide(true)
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name("words")
+                        .multiple(true)
+                        .allow_hyphen_values(true),
+                ),
         )
-        .get_matches();
+}
 
-    if matches.is_present("init") {
-        match init::initialize_and_install_all() {
-            Ok(_) => println!("Initialization completed successfully."),
-            Err(e) => {
-                eprintln!("Error during initialization: {}", e);
-                process::exit(1);
-            }
+/// The command being run, reported with its duration and exit code when the process exits.
+struct Invocation {
+    command: String,
+    started: Instant,
+}
+
+impl Invocation {
+    /// Names the command after its subcommand, or after the subcommand that
+    /// replaces a deprecated flag such as `--init`.
+    fn new(matches: &ArgMatches) -> Invocation {
+        let command = matches
+            .subcommand_name()
+            .or_else(|| {
+                LEGACY_FLAGS
+                    .iter()
+                    .find(|(flag, _, _)| matches.is_present(flag))
+                    .map(|(_, command, _)| *command)
+            })
+            .unwrap_or("khadim-rs");
+        Invocation {
+            command: command.to_string(),
+            started: Instant::now(),
         }
-    } else if matches.is_present("doctor") {
-        match doctor::run_doctor_checks() {
-            Ok(_) => println!("Doctor checks passed successfully."),
-            Err(e) => {
-                eprintln!("Doctor checks failed: {}", e);
-                process::exit(1);
-            }
+    }
+
+    /// Exits with the given exit code.
+    fn exit(&self, exit_code: i32) -> ! {
+        output::command_finished(&self.command, self.started, exit_code, None);
+        process::exit(exit_code);
+    }
+
+    /// Logs an error, and reports it in JSON mode, and exits with the exit code of its category.
+    fn fail(&self, context: &str, error: KhadimError) -> ! {
+        error!("{}: {}", context, error);
+        output::command_finished(&self.command, self.started, error.exit_code(), Some(&error));
+        process::exit(error.exit_code());
+    }
+}
+
+/// The `--save-prefix` option of `add`, and of the deprecated `--deps` and `--deps-dev`.
+fn save_prefix_arg<'help>() -> Arg<'help> {
+    Arg::with_name("save-prefix")
+        .long("save-prefix")
+        .help("Prefix for versions resolved from a dist-tag or an exact version: ^, ~ or exact")
+        .takes_value(true)
+        .possible_values(["^", "~", "exact"])
+        .default_value("^")
+}
+
+/// The `--project` option of `add`, and of the deprecated `--deps` and `--deps-dev`.
+fn project_arg<'help>() -> Arg<'help> {
+    Arg::with_name("project")
+        .long("project")
+        .help("Add to the apps or libs matching a name or glob, e.g. organic-lever-web or 'libs/*', instead of the root")
+        .takes_value(true)
+}
+
+/// Initializes the root and every project, exiting on failure.
+fn init(invocation: &Invocation) {
+    match init::initialize_and_install_all() {
+        Ok(_) => info!("Initialization completed successfully."),
+        Err(e) => {
+            invocation.fail("Error during initialization", e);
         }
-    } else if matches.is_present("reset") {
-        match reset::reset_project() {
-            Ok(_) => println!("Reset completed successfully."),
-            Err(e) => {
-                eprintln!("Error during reset: {}", e);
-                process::exit(1);
-            }
+    }
+}
+
+/// Runs the doctor checks, exiting on failure.
+fn doctor(invocation: &Invocation) {
+    match doctor::run_doctor_checks() {
+        Ok(_) => info!("Doctor checks passed successfully."),
+        Err(e) => {
+            invocation.fail("Doctor checks failed", e);
         }
-    } else {
-        match matches.values_of("deps") {
-            Some(deps) => {
-                let deps: Vec<&str> = deps.collect();
-                dependencies::add_dependencies(deps, false).map_err(BoxError::from)?;
-            }
-            None => match matches.values_of("deps-dev") {
-                Some(deps_dev) => {
-                    let deps_dev: Vec<&str> = deps_dev.collect();
-                    dependencies::add_dependencies(deps_dev, true).map_err(BoxError::from)?;
-                }
-                None => {
-                    println!("No valid option provided. Use --help for usage information.");
-                    process::exit(1);
-                }
-            },
+    }
+}
+
+/// Resets with the options of the `reset` subcommand, or with the defaults
+/// for the deprecated `--reset` flag, exiting on failure.
+fn reset(invocation: &Invocation, reset_matches: Option<&ArgMatches>) {
+    let is_present = |arg| reset_matches.is_some_and(|m| m.is_present(arg));
+    let scope = match (
+        reset_matches.and_then(|m| m.value_of("only")),
+        reset_matches.and_then(|m| m.value_of("project")),
+    ) {
+        (Some("root"), _) => ResetScope::Root,
+        (Some("apps"), _) => ResetScope::Apps,
+        (Some("libs"), _) => ResetScope::Libs,
+        (_, Some(pattern)) => ResetScope::Projects(pattern.to_string()),
+        _ => ResetScope::All,
+    };
+    let options = ResetOptions {
+        scope,
+        build_outputs: is_present("build-outputs"),
+        venvs: is_present("venvs"),
+        npm_cache: is_present("npm-cache"),
+        skip_install: is_present("no-install"),
+        assume_yes: is_present("yes"),
+        force: is_present("force"),
+    };
+    match reset::reset_project(&options) {
+        Ok(true) => info!("Reset completed successfully."),
+        Ok(false) => invocation.exit(1),
+        Err(e) => {
+            invocation.fail("Error during reset", e);
         }
     }
+}
 
-    Ok(())
+/// Adds the packages given in `packages_arg` with the `--save-prefix` and
+/// `--project` options of the same matches, exiting on failure.
+fn add_dependencies(invocation: &Invocation, matches: &ArgMatches, packages_arg: &str, dev: bool) {
+    let save_prefix: SavePrefix = match matches.value_of("save-prefix").unwrap_or("^").parse() {
+        Ok(save_prefix) => save_prefix,
+        Err(e) => invocation.fail("Invalid save prefix", e),
+    };
+    let packages: Vec<&str> = matches
+        .values_of(packages_arg)
+        .map(|values| values.collect())
+        .unwrap_or_default();
+    let project = matches.value_of("project");
+    if let Err(e) = dependencies::add_dependencies(packages, dev, save_prefix, project) {
+        invocation.fail("Error adding dependencies", e);
+    }
+}
+
+/// Compares against the given base ref, or the working tree if none is given.
+fn change_source(base: Option<&str>) -> ChangeSource {
+    match base {
+        Some(base) => ChangeSource::Base(base.to_string()),
+        None => ChangeSource::WorkingTree,
+    }
 }
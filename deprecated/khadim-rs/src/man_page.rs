@@ -0,0 +1,216 @@
+use crate::root::{ROOT_ENV, ROOT_MARKER_ENV};
+use clap::{App, Arg};
+use roff::{bold, italic, roman, Inline, Roff};
+
+/// The environment variables read by khadim-rs, with what they change.
+const ENVIRONMENT: [(&str, &str); 4] = [
+    (
+        ROOT_ENV,
+        "The root directory of the monorepo, when --root is not given.",
+    ),
+    (
+        ROOT_MARKER_ENV,
+        "A comma-separated list of the files or directories that mark the root directory, \
+         instead of khadim.json, package-tmpl.json and .git.",
+    ),
+    (
+        "KHADIM_REGISTRY",
+        "The npm registry to look package versions up from.",
+    ),
+    (
+        "npm_config_registry",
+        "The npm registry, when KHADIM_REGISTRY is not set.",
+    ),
+];
+
+/// The exit codes of khadim-rs, as returned by `KhadimError::exit_code`.
+const EXIT_STATUS: [(&str, &str); 12] = [
+    ("0", "Success."),
+    (
+        "1",
+        "Other failures, such as version conflicts, drift or a failed script.",
+    ),
+    (
+        "2",
+        "Invalid command-line arguments, such as an invalid project pattern.",
+    ),
+    (
+        "3",
+        "The root directory of the monorepo was not found, or the one given does not exist.",
+    ),
+    ("4", "A package.json or package-tmpl.json is not valid."),
+    ("5", "A required tool such as npm or git is not installed."),
+    ("6", "A command such as npm install failed."),
+    ("7", "The npm registry could not resolve a package."),
+    ("8", "A file could not be read or written."),
+    (
+        "9",
+        "khadim.json is not valid, or package-tmpl.json is missing.",
+    ),
+    ("10", "The apps and libs depend on each other in a cycle."),
+    ("11", "No app or lib matches the given name or glob."),
+];
+
+/// Renders the man page of the command-line interface, in roff, with a
+/// section for its options and one for each of its commands.
+///
+/// Hidden options and commands, such as the deprecated flags, are left out.
+///
+/// # Arguments
+///
+/// * `app` - The command-line definitions, as used to parse the arguments.
+/// * `bin_name` - The name of the binary, e.g. `khadim-rs`.
+///
+/// # Examples
+///
+/// ```
+/// use clap::App;
+/// use khadim_rs::man_page::render_man_page;
+///
+/// let app = App::new("khadim-rs")
+///     .about("Manages repositories")
+///     .subcommand(App::new("init").about("Initialize package.json"));
+/// let page = render_man_page(&app, "khadim-rs");
+/// assert!(page.contains(".SH COMMANDS"));
+/// assert!(page.contains("Initialize package.json"));
+/// ```
+pub fn render_man_page(app: &App, bin_name: &str) -> String {
+    let mut page = Roff::new();
+    let version = app.get_version().unwrap_or_default();
+    // roff drops empty arguments, so the version takes the place of the date
+    page.control(
+        "TH",
+        [
+            bin_name.to_uppercase().as_str(),
+            "1",
+            &format!("{} {}", bin_name, version),
+        ],
+    );
+
+    page.control("SH", ["NAME"]);
+    page.text([roman(format!(
+        "{} - {}",
+        bin_name,
+        app.get_about().unwrap_or_default()
+    ))]);
+
+    page.control("SH", ["SYNOPSIS"]);
+    page.text([
+        bold(bin_name),
+        roman(" ["),
+        italic("OPTIONS"),
+        roman("] "),
+        italic("COMMAND"),
+        roman(" ["),
+        italic("ARGS"),
+        roman("]"),
+    ]);
+
+    page.control("SH", ["OPTIONS"]);
+    page.text([roman(
+        "These options are accepted by every command, before or after it.",
+    )]);
+    render_args(&mut page, app, false);
+
+    page.control("SH", ["COMMANDS"]);
+    for command in app
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+    {
+        page.control(
+            "SS",
+            [format!("{} {}", bin_name, command.get_name()).as_str()],
+        );
+        let mut synopsis = vec![bold(format!("{} {}", bin_name, command.get_name()))];
+        for positional in command.get_positionals().filter(|arg| !arg.is_hide_set()) {
+            synopsis.push(roman(" "));
+            synopsis.extend(value_placeholder(positional));
+        }
+        page.text(synopsis);
+        if let Some(about) = command.get_about() {
+            page.control("PP", []);
+            page.text([roman(about)]);
+        }
+        render_args(&mut page, command, true);
+    }
+
+    page.control("SH", ["ENVIRONMENT"]);
+    for (name, description) in ENVIRONMENT {
+        page.control("TP", []);
+        page.text([bold(name)]);
+        page.text([roman(description)]);
+    }
+
+    page.control("SH", ["EXIT STATUS"]);
+    for (code, description) in EXIT_STATUS {
+        page.control("TP", []);
+        page.text([bold(code)]);
+        page.text([roman(description)]);
+    }
+
+    page.render()
+}
+
+/// Renders the visible arguments of a command as a tagged paragraph each,
+/// leaving out `--help` and `--version` for subcommands.
+fn render_args(page: &mut Roff, app: &App, is_subcommand: bool) {
+    let args = app.get_arguments().filter(|arg| {
+        let is_builtin = is_subcommand && matches!(arg.get_id(), "help" | "version");
+        !arg.is_hide_set() && !is_builtin
+    });
+    for arg in args {
+        page.control("TP", []);
+        page.text(arg_heading(arg));
+
+        let mut description = arg.get_help().unwrap_or_default().to_string();
+        if let Some(values) = arg.get_possible_values() {
+            let values: Vec<&str> = values.iter().map(|value| value.get_name()).collect();
+            description.push_str(&format!(" [possible values: {}]", values.join(", ")));
+        }
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+        if !defaults.is_empty() {
+            description.push_str(&format!(" [default: {}]", defaults.join(", ")));
+        }
+        page.text([roman(description)]);
+    }
+}
+
+/// Returns the heading of an argument, e.g. `-D, --dev` or `--root <root>`.
+fn arg_heading(arg: &Arg) -> Vec<Inline> {
+    if arg.is_positional() {
+        return value_placeholder(arg);
+    }
+
+    let mut heading = Vec::new();
+    if let Some(short) = arg.get_short() {
+        heading.push(bold(format!("-{}", short)));
+    }
+    if let Some(long) = arg.get_long() {
+        if !heading.is_empty() {
+            heading.push(roman(", "));
+        }
+        heading.push(bold(format!("--{}", long)));
+    }
+    if arg.is_takes_value_set() {
+        heading.push(roman(" "));
+        heading.extend(value_placeholder(arg));
+    }
+    heading
+}
+
+/// Returns the placeholder of an argument's value, e.g. `<packages>...`.
+fn value_placeholder(arg: &Arg) -> Vec<Inline> {
+    let name = arg
+        .get_value_names()
+        .and_then(|names| names.first().copied())
+        .unwrap_or_else(|| arg.get_id());
+    let mut placeholder = vec![roman("<"), italic(name), roman(">")];
+    if arg.is_multiple_values_set() || arg.is_multiple_occurrences_set() {
+        placeholder.push(roman("..."));
+    }
+    placeholder
+}
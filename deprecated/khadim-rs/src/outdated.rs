@@ -0,0 +1,513 @@
+use crate::config::{PACKAGE_JSON, PACKAGE_LOCK_JSON, PACKAGE_TMPL_JSON};
+use crate::error::KhadimError;
+use crate::init::run_npm_install;
+use crate::json_file::{insert_sorted, JsonFile};
+use crate::output;
+use crate::package_spec::{PackageSpec, SpecSource};
+use crate::registry::{Packument, RegistryClient};
+use crate::root::find_root_dir;
+use crate::transaction::ManifestTransaction;
+use crate::version_range::VersionRange;
+use crate::workspace::{Manifest, Workspace};
+use log::info;
+use rayon::prelude::*;
+use semver::Version;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// The location label used for dependencies declared at the root.
+const ROOT_LOCATION: &str = "root";
+
+/// A registry dependency that is not on its latest version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutdatedDependency {
+    pub package: String,
+    /// `root` for package-tmpl.json, or a project directory such as `apps/organic-lever-web`.
+    pub location: String,
+    /// `dependencies` or `devDependencies`.
+    pub dep_key: String,
+    /// The range declared in the manifest, e.g. `^18.2.0`.
+    pub range: String,
+    /// The version installed according to the lockfile next to the manifest.
+    pub installed: Option<String>,
+    /// The highest version satisfying the declared range.
+    pub wanted: Option<String>,
+    /// The version of the `latest` dist-tag.
+    pub latest: String,
+}
+
+/// A registry dependency declared by the root template or a workspace project.
+pub(crate) struct Declaration {
+    pub(crate) package: String,
+    pub(crate) location: String,
+    /// The directory holding the manifest and its lockfile.
+    pub(crate) dir: PathBuf,
+    pub(crate) dep_key: &'static str,
+    pub(crate) range: String,
+}
+
+/// Lists the dependencies of package-tmpl.json and every app and lib that are
+/// not on their latest version.
+///
+/// Only registry dependencies are checked; `file:`, git and `npm:` alias
+/// dependencies are skipped. Packages whose metadata cannot be fetched are
+/// reported as warnings and skipped.
+///
+/// # Returns
+///
+/// * `Result<Vec<OutdatedDependency>, KhadimError>` - The outdated dependencies,
+///   sorted by package name and location.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * package-tmpl.json or a project's package.json cannot be read or parsed
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::outdated::{outdated, print_outdated};
+///
+/// let dependencies = outdated().unwrap();
+/// print_outdated(&dependencies);
+/// ```
+pub fn outdated() -> Result<Vec<OutdatedDependency>, KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let registry = RegistryClient::for_root(&root_dir);
+
+    let declarations = collect_declarations(&workspace)?;
+    let packuments = fetch_packuments(&registry, &declarations);
+
+    let mut dependencies = Vec::new();
+    for declaration in &declarations {
+        let packument = match packuments.get(&declaration.package) {
+            Some(packument) => packument,
+            None => continue,
+        };
+        let latest = match packument.dist_tags.get("latest") {
+            Some(latest) => latest.clone(),
+            None => continue,
+        };
+        let installed = installed_version(&registry, declaration);
+        let wanted = VersionRange::parse(&declaration.range)
+            .ok()
+            .and_then(|range| range.max_satisfying(&packument.versions));
+
+        if installed.as_ref() != Some(&latest) || wanted != installed {
+            dependencies.push(OutdatedDependency {
+                package: declaration.package.clone(),
+                location: declaration.location.clone(),
+                dep_key: declaration.dep_key.to_string(),
+                range: declaration.range.clone(),
+                installed,
+                wanted,
+                latest,
+            });
+        }
+    }
+
+    dependencies.sort_by(|a, b| (&a.package, &a.location).cmp(&(&b.package, &b.location)));
+    Ok(dependencies)
+}
+
+/// Prints the outdated dependencies as a table.
+pub fn print_outdated(dependencies: &[OutdatedDependency]) {
+    output::report("outdated", &dependencies);
+    if dependencies.is_empty() {
+        info!("✅ All dependencies are up to date 🎉");
+        return;
+    }
+
+    let package_width = column_width("Package", dependencies.iter().map(|d| &d.package));
+    let location_width = column_width("Location", dependencies.iter().map(|d| &d.location));
+    let range_width = column_width("Current", dependencies.iter().map(|d| &d.range));
+
+    info!(
+        "{:<package_width$}  {:<location_width$}  {:<range_width$}  {:<12}  {:<12}  {:<12}",
+        "Package", "Location", "Current", "Installed", "Wanted", "Latest",
+    );
+    for dependency in dependencies {
+        info!(
+            "{:<package_width$}  {:<location_width$}  {:<range_width$}  {:<12}  {:<12}  {:<12}",
+            dependency.package,
+            dependency.location,
+            dependency.range,
+            dependency.installed.as_deref().unwrap_or("-"),
+            dependency.wanted.as_deref().unwrap_or("-"),
+            dependency.latest,
+        );
+    }
+}
+
+/// Upgrades dependencies of package-tmpl.json, package.json and every app and lib.
+///
+/// Each declaration is bumped to the highest version with the same major as
+/// the installed (or wanted) version, or to the `latest` dist-tag when `major`
+/// is set. The `^`, `~` or exact style of the declared range is kept, and
+/// ranges that already allow the target version are left as they are. Root
+/// upgrades are written to both package-tmpl.json and package.json. Afterwards
+/// `npm install` runs only at the root and in the projects that changed.
+///
+/// # Arguments
+///
+/// * `packages` - The packages to upgrade. An empty vector upgrades every dependency.
+/// * `major` - Whether to allow upgrades across major versions.
+///
+/// # Returns
+///
+/// * `Result<(), KhadimError>` - Ok(()) if the upgrade succeeds,
+///   or an error if any part of the process fails.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * None of the given packages is a registry dependency of the workspace
+/// * There are issues reading or writing a package.json or package-tmpl.json
+/// * The npm install process fails
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::outdated::upgrade;
+///
+/// // Bump react and next within their current majors
+/// let result = upgrade(vec!["react", "next"], false);
+/// ```
+pub fn upgrade(packages: Vec<&str>, major: bool) -> Result<(), KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let registry = RegistryClient::for_root(&root_dir);
+
+    let mut declarations = collect_declarations(&workspace)?;
+    if !packages.is_empty() {
+        declarations.retain(|declaration| packages.contains(&declaration.package.as_str()));
+        for package in &packages {
+            if !declarations.iter().any(|d| d.package == *package) {
+                output::warn(&format!(
+                    "{} is not a registry dependency, skipping",
+                    package
+                ));
+            }
+        }
+        if declarations.is_empty() {
+            return Err(format!(
+                "❌ None of {} is a registry dependency of the workspace 😢",
+                packages.join(", ")
+            )
+            .into());
+        }
+    }
+
+    let packuments = fetch_packuments(&registry, &declarations);
+
+    // New ranges, grouped by the directory of the manifest they belong to
+    let mut upgrades: BTreeMap<PathBuf, Vec<(&Declaration, String)>> = BTreeMap::new();
+    for declaration in &declarations {
+        let packument = match packuments.get(&declaration.package) {
+            Some(packument) => packument,
+            None => continue,
+        };
+        let installed = installed_version(&registry, declaration);
+        let target = match target_version(packument, &declaration.range, installed, major) {
+            Some(target) => target,
+            None => continue,
+        };
+        if let Some(range) = upgraded_range(&declaration.range, &target) {
+            upgrades
+                .entry(declaration.dir.clone())
+                .or_default()
+                .push((declaration, range));
+        }
+    }
+
+    if upgrades.is_empty() {
+        info!("✅ All dependencies are already up to date 🎉");
+        return Ok(());
+    }
+
+    let mut transaction = ManifestTransaction::new();
+    for (dir, changes) in &upgrades {
+        stage_ranges(&mut transaction, &root_dir, dir, changes)?;
+        for (declaration, range) in changes {
+            info!(
+                "⬆️ Upgraded {} {} → {} in {}",
+                declaration.package, declaration.range, range, declaration.location
+            );
+            output::dependency_changed(
+                &declaration.package,
+                "upgraded",
+                &declaration.location,
+                declaration.dep_key,
+                Some(&declaration.range),
+                Some(range),
+            );
+        }
+    }
+
+    // Install only where manifests changed, restoring every manifest and
+    // lockfile if any install fails
+    transaction.commit_and_then(|| {
+        for dir in upgrades.keys() {
+            run_npm_install(dir)?;
+        }
+        Ok(())
+    })?;
+
+    info!("✅ Dependencies upgraded and installed successfully! 🎉");
+    Ok(())
+}
+
+/// Stages new ranges for the manifests in a directory, protecting its lockfile.
+/// Ranges declared at the root are written to both package-tmpl.json and package.json.
+pub(crate) fn stage_ranges(
+    transaction: &mut ManifestTransaction,
+    root_dir: &Path,
+    dir: &Path,
+    changes: &[(&Declaration, String)],
+) -> Result<(), KhadimError> {
+    let manifest_files = if dir == root_dir {
+        vec![PACKAGE_TMPL_JSON, PACKAGE_JSON]
+    } else {
+        vec![PACKAGE_JSON]
+    };
+
+    for manifest_file in manifest_files {
+        let path = dir.join(manifest_file);
+        if !path.exists() {
+            continue;
+        }
+        let mut manifest = JsonFile::read(&path)?;
+        for (declaration, range) in changes {
+            insert_sorted(
+                &mut manifest.value,
+                declaration.dep_key,
+                &declaration.package,
+                json!(range),
+            );
+        }
+        transaction.stage(&manifest)?;
+    }
+    transaction.protect(&dir.join(PACKAGE_LOCK_JSON));
+    Ok(())
+}
+
+/// Collects the registry dependencies of package-tmpl.json and every workspace project.
+pub(crate) fn collect_declarations(workspace: &Workspace) -> Result<Vec<Declaration>, KhadimError> {
+    let root_manifest = Manifest::from_path(&workspace.root_dir.join(PACKAGE_TMPL_JSON))?;
+
+    let mut manifests = vec![(
+        ROOT_LOCATION.to_string(),
+        workspace.root_dir.clone(),
+        &root_manifest,
+    )];
+    for project in &workspace.projects {
+        manifests.push((
+            project.relative_dir().display().to_string(),
+            project.path.clone(),
+            &project.manifest,
+        ));
+    }
+
+    let mut declarations = Vec::new();
+    for (location, dir, manifest) in manifests {
+        for (dep_key, deps) in [
+            ("dependencies", &manifest.dependencies),
+            ("devDependencies", &manifest.dev_dependencies),
+        ] {
+            for (package, range) in deps {
+                if is_registry_range(package, range) {
+                    declarations.push(Declaration {
+                        package: package.clone(),
+                        location: location.clone(),
+                        dir: dir.clone(),
+                        dep_key,
+                        range: range.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(declarations)
+}
+
+/// Returns `true` for dependencies declared with a registry version or range.
+fn is_registry_range(package: &str, range: &str) -> bool {
+    match PackageSpec::parse(&format!("{}@{}", package, range)) {
+        Ok(spec) => {
+            matches!(spec.source, SpecSource::Registry(_)) && VersionRange::parse(range).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Fetches the packument of every declared package concurrently, warning about failures.
+fn fetch_packuments(
+    registry: &RegistryClient,
+    declarations: &[Declaration],
+) -> HashMap<String, Packument> {
+    let packages: BTreeSet<&str> = declarations
+        .iter()
+        .map(|declaration| declaration.package.as_str())
+        .collect();
+
+    packages
+        .into_par_iter()
+        .filter_map(|package| match registry.packument(package) {
+            Ok(packument) => Some((package.to_string(), packument)),
+            Err(e) => {
+                output::warn(&format!("Skipping {}: {}", package, e));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns the version installed according to the lockfile next to the declaration's manifest.
+fn installed_version(registry: &RegistryClient, declaration: &Declaration) -> Option<String> {
+    registry
+        .clone()
+        .with_lockfile(&declaration.dir.join(PACKAGE_LOCK_JSON))
+        .locked_version(&declaration.package)
+}
+
+/// Picks the version to upgrade to: the `latest` dist-tag when crossing majors
+/// is allowed, otherwise the highest stable version that is caret-compatible
+/// with the current one, see `is_compatible`.
+fn target_version(
+    packument: &Packument,
+    range: &str,
+    installed: Option<String>,
+    major: bool,
+) -> Option<Version> {
+    let latest = packument
+        .dist_tags
+        .get("latest")
+        .and_then(|latest| Version::parse(latest).ok());
+    if major {
+        return latest;
+    }
+
+    // The installed version only counts when it still satisfies the declared range
+    let range = VersionRange::parse(range).ok()?;
+    let current = installed
+        .and_then(|installed| Version::parse(&installed).ok())
+        .filter(|installed| range.matches(installed))
+        .or_else(|| Version::parse(&range.max_satisfying(&packument.versions)?).ok())?;
+    packument
+        .versions
+        .iter()
+        .filter_map(|version| Version::parse(version).ok())
+        .filter(|version| {
+            is_compatible(version, &current) && version.pre.is_empty() && *version >= current
+        })
+        .filter(|version| latest.as_ref().is_none_or(|latest| version <= latest))
+        .max()
+}
+
+/// Returns `true` if a version is on the same major as the current one, where
+/// below 1.0.0 the minor, and below 0.1.0 the patch, counts as the major, as
+/// for npm's `^`.
+fn is_compatible(version: &Version, current: &Version) -> bool {
+    match (current.major, current.minor) {
+        (0, 0) => version.major == 0 && version.minor == 0 && version.patch == current.patch,
+        (0, minor) => version.major == 0 && version.minor == minor,
+        (major, _) => version.major == major,
+    }
+}
+
+/// Rewrites a range to the target version, keeping its `^`, `~` or exact style.
+/// Returns `None` when the range does not need to change.
+fn upgraded_range(range: &str, target: &Version) -> Option<String> {
+    let upgraded = if Version::parse(range.trim_start_matches(['v', '='])).is_ok() {
+        target.to_string()
+    } else if range.starts_with('~') {
+        format!("~{}", target)
+    } else if range.starts_with('^') {
+        format!("^{}", target)
+    } else if VersionRange::parse(range).is_ok_and(|range| range.matches(target)) {
+        // Ranges such as `*`, `1.x` or `>=1 <3` that already allow the target
+        return None;
+    } else {
+        format!("^{}", target)
+    };
+
+    if upgraded == range {
+        None
+    } else {
+        Some(upgraded)
+    }
+}
+
+/// Returns the width of a table column holding the given header and values.
+fn column_width<'a>(header: &str, values: impl Iterator<Item = &'a String>) -> usize {
+    values
+        .map(|value| value.len())
+        .max()
+        .unwrap_or(0)
+        .max(header.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packument(versions: &[&str], latest: &str) -> Packument {
+        Packument {
+            name: "pkg".to_string(),
+            dist_tags: [("latest".to_string(), latest.to_string())].into(),
+            versions: versions.iter().map(|version| version.to_string()).collect(),
+        }
+    }
+
+    fn target(packument: &Packument, range: &str, installed: Option<&str>) -> Option<String> {
+        target_version(packument, range, installed.map(str::to_string), false)
+            .map(|version| version.to_string())
+    }
+
+    #[test]
+    fn stays_on_the_current_major() {
+        let packument = packument(&["1.0.0", "1.4.2", "2.0.0-rc.1", "2.1.0"], "2.1.0");
+        assert_eq!(
+            target(&packument, "^1.0.0", Some("1.0.0")).as_deref(),
+            Some("1.4.2")
+        );
+        assert_eq!(target(&packument, "^1.0.0", None).as_deref(), Some("1.4.2"));
+        assert_eq!(
+            target_version(&packument, "^1.0.0", None, true).map(|v| v.to_string()),
+            Some("2.1.0".to_string())
+        );
+    }
+
+    #[test]
+    fn stays_on_the_current_minor_below_one() {
+        let packument = packument(&["0.2.1", "0.2.9", "0.3.0", "0.4.0"], "0.4.0");
+        assert_eq!(
+            target(&packument, "^0.2.1", Some("0.2.1")).as_deref(),
+            Some("0.2.9")
+        );
+        assert_eq!(target(&packument, "0.2.1", None).as_deref(), Some("0.2.9"));
+    }
+
+    #[test]
+    fn stays_on_the_current_patch_below_one_tenth() {
+        let packument = packument(&["0.0.3", "0.0.4", "0.1.0"], "0.1.0");
+        assert_eq!(
+            target(&packument, "^0.0.3", Some("0.0.3")).as_deref(),
+            Some("0.0.3")
+        );
+    }
+
+    #[test]
+    fn never_passes_latest() {
+        let packument = packument(&["1.0.0", "1.1.0", "1.2.0"], "1.1.0");
+        assert_eq!(
+            target(&packument, "^1.0.0", Some("1.0.0")).as_deref(),
+            Some("1.1.0")
+        );
+    }
+}
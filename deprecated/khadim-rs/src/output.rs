@@ -0,0 +1,388 @@
+use crate::error::KhadimError;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Whether events are written as JSON lines instead of human output.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// How the result of a command is reported on stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Emoji progress messages and tables, the default.
+    #[default]
+    Human,
+    /// One JSON event per line (NDJSON), for CI scripts.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = KhadimError;
+
+    fn from_str(format: &str) -> Result<OutputFormat, KhadimError> {
+        match format {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!(
+                "❌ Invalid output format '{}', expected human or json 😢",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+/// Sets the output format for the rest of the process.
+pub fn set_format(format: OutputFormat) {
+    JSON_OUTPUT.store(format == OutputFormat::Json, Ordering::SeqCst);
+}
+
+/// Returns `true` if events are written as JSON lines.
+pub fn is_json() -> bool {
+    JSON_OUTPUT.load(Ordering::SeqCst)
+}
+
+/// A structured event, written as one JSON line in JSON mode.
+///
+/// Every line has an `event` field naming the variant in snake case, e.g.
+/// `{"event":"step_started","step":"npm install","dir":"/path/to/project"}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A step such as `npm install` or `run build` started.
+    StepStarted {
+        step: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        project: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        dir: Option<PathBuf>,
+    },
+    /// A step finished, or was skipped or replayed from the cache.
+    StepFinished {
+        step: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        project: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        dir: Option<PathBuf>,
+        /// `success`, `failed`, `skipped` or `cached`.
+        status: String,
+        duration_ms: u128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Value>,
+    },
+    /// A dependency was added, removed or changed in a manifest.
+    DependencyChanged {
+        package: String,
+        /// `added`, `removed`, `upgraded` or `unified`.
+        action: String,
+        /// `root`, or a project directory such as `apps/organic-lever-web`.
+        location: String,
+        dep_key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<String>,
+    },
+    /// Something worth attention that does not stop the command.
+    Warning { message: String },
+    /// The findings of a reporting command, such as `outdated` or `check-versions`.
+    Report { report: String, items: Value },
+    /// The command finished; always the last event.
+    CommandFinished {
+        command: String,
+        status: String,
+        duration_ms: u128,
+        exit_code: i32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Value>,
+    },
+}
+
+/// Writes an event as a JSON line on stdout. Does nothing in human mode.
+///
+/// # Examples
+///
+/// ```
+/// use khadim_rs::output::{emit, set_format, Event, OutputFormat};
+///
+/// set_format(OutputFormat::Json);
+/// // Prints {"event":"warning","message":"react is not a dependency, skipping"}
+/// emit(&Event::Warning {
+///     message: "react is not a dependency, skipping".to_string(),
+/// });
+/// ```
+pub fn emit(event: &Event) {
+    if !is_json() {
+        return;
+    }
+    if let Ok(line) = serde_json::to_string(event) {
+        // Lock stdout so that events from parallel steps never interleave
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+/// Logs a warning, and emits it as a `warning` event in JSON mode.
+pub fn warn(message: &str) {
+    log::warn!("⚠️ {}", message);
+    emit(&Event::Warning {
+        message: message.to_string(),
+    });
+}
+
+/// Asks a yes/no question on stdin, defaulting to no.
+///
+/// # Errors
+///
+/// This function will return an error if stdin is not a terminal, e.g. on CI
+/// or when input is piped, since nobody can answer; the caller's `--yes`
+/// skips the question instead.
+pub fn confirm(question: &str) -> Result<bool, KhadimError> {
+    ask(question, io::stdin().is_terminal(), &mut io::stdin().lock())
+}
+
+/// Asks a yes/no question, reading the answer from `input` unless there is
+/// nobody to answer it.
+fn ask(question: &str, interactive: bool, input: &mut impl BufRead) -> Result<bool, KhadimError> {
+    if !interactive {
+        return Err(format!(
+            "❌ Cannot ask \"{}\" without a terminal, pass --yes to go on 😢",
+            question
+        )
+        .into());
+    }
+
+    // stdout only carries events in JSON mode
+    if is_json() {
+        eprint!("❓ {} [y/N] ", question);
+    } else {
+        print!("❓ {} [y/N] ", question);
+        io::stdout().flush()?;
+    }
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Emits the findings of a reporting command as a `report` event.
+pub fn report<T: Serialize>(report: &str, items: &T) {
+    if is_json() {
+        emit(&Event::Report {
+            report: report.to_string(),
+            items: serde_json::to_value(items).unwrap_or(Value::Null),
+        });
+    }
+}
+
+/// Emits a `dependency_changed` event.
+pub fn dependency_changed(
+    package: &str,
+    action: &str,
+    location: &str,
+    dep_key: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) {
+    emit(&Event::DependencyChanged {
+        package: package.to_string(),
+        action: action.to_string(),
+        location: location.to_string(),
+        dep_key: dep_key.to_string(),
+        from: from.map(str::to_string),
+        to: to.map(str::to_string),
+    });
+}
+
+/// Emits the `command_finished` event with the exit code of the process.
+pub fn command_finished(
+    command: &str,
+    started: Instant,
+    exit_code: i32,
+    error: Option<&KhadimError>,
+) {
+    emit(&Event::CommandFinished {
+        command: command.to_string(),
+        status: if exit_code == 0 { "success" } else { "failed" }.to_string(),
+        duration_ms: started.elapsed().as_millis(),
+        exit_code,
+        error: error.map(error_details),
+    });
+}
+
+/// A timed step, reported with a `step_started` event when it starts and a
+/// `step_finished` event when it finishes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::init::run_npm_install;
+/// use khadim_rs::output::Step;
+/// use std::path::Path;
+///
+/// let dir = Path::new("/path/to/project");
+/// let step = Step::start("npm install", None, Some(dir));
+/// let result = run_npm_install(dir);
+/// step.finish(&result);
+/// ```
+#[derive(Debug)]
+pub struct Step {
+    step: String,
+    project: Option<String>,
+    dir: Option<PathBuf>,
+    started: Instant,
+}
+
+impl Step {
+    /// Starts timing a step and emits its `step_started` event.
+    pub fn start(step: &str, project: Option<&str>, dir: Option<&Path>) -> Step {
+        let step = Step {
+            step: step.to_string(),
+            project: project.map(str::to_string),
+            dir: dir.map(Path::to_path_buf),
+            started: Instant::now(),
+        };
+        emit(&Event::StepStarted {
+            step: step.step.clone(),
+            project: step.project.clone(),
+            dir: step.dir.clone(),
+        });
+        step
+    }
+
+    /// Finishes the step as a success or a failure, depending on the result.
+    pub fn finish<T>(self, result: &Result<T, KhadimError>) {
+        match result {
+            Ok(_) => self.finish_with("success", None, None),
+            Err(e) => {
+                let exit_code = match e {
+                    KhadimError::Subprocess { exit_code, .. } => *exit_code,
+                    _ => None,
+                };
+                self.finish_with("failed", exit_code, Some(e))
+            }
+        }
+    }
+
+    /// Finishes the step with the exit code of the process it ran.
+    pub fn finish_with_exit_code(self, exit_code: i32) {
+        let status = if exit_code == 0 { "success" } else { "failed" };
+        self.finish_with(status, Some(exit_code), None);
+    }
+
+    /// Finishes the step with an explicit status, e.g. `cached`.
+    pub fn finish_with(self, status: &str, exit_code: Option<i32>, error: Option<&KhadimError>) {
+        emit(&Event::StepFinished {
+            step: self.step,
+            project: self.project,
+            dir: self.dir,
+            status: status.to_string(),
+            duration_ms: self.started.elapsed().as_millis(),
+            exit_code,
+            error: error.map(error_details),
+        });
+    }
+}
+
+/// Describes an error as JSON, with its kind, exit code, message and the
+/// fields of its variant.
+///
+/// # Examples
+///
+/// ```
+/// use khadim_rs::error::KhadimError;
+/// use khadim_rs::output::error_details;
+///
+/// let details = error_details(&KhadimError::MissingTool { tool: "npm".to_string() });
+/// assert_eq!(details["kind"], "missing_tool");
+/// assert_eq!(details["exit_code"], 5);
+/// assert_eq!(details["tool"], "npm");
+/// ```
+pub fn error_details(error: &KhadimError) -> Value {
+    let mut details = json!({
+        "kind": error.kind(),
+        "exit_code": error.exit_code(),
+        "message": error.to_string(),
+    });
+    let fields = match error {
+        KhadimError::RootNotFound { start, markers } => {
+            json!({ "start": start, "markers": markers })
+        }
+        KhadimError::RootDirMissing { path, given_with } => {
+            json!({ "path": path, "given_with": given_with })
+        }
+        KhadimError::ManifestParse {
+            path, line, column, ..
+        } => json!({ "path": path, "line": line, "column": column }),
+        KhadimError::InvalidConfig { path, problems } => {
+            json!({ "path": path, "problems": problems })
+        }
+        KhadimError::MissingTool { tool } => json!({ "tool": tool }),
+        KhadimError::Subprocess {
+            command,
+            cwd,
+            exit_code,
+            stderr,
+        } => json!({
+            "command": command,
+            "cwd": cwd,
+            "process_exit_code": exit_code,
+            "stderr": stderr,
+        }),
+        KhadimError::Registry { package, .. } => json!({ "package": package }),
+        KhadimError::Io { path, .. } => json!({ "path": path }),
+        KhadimError::InvalidPattern { pattern, .. } => json!({ "pattern": pattern }),
+        KhadimError::ProjectNotFound { pattern, available } => {
+            json!({ "pattern": pattern, "available": available })
+        }
+        KhadimError::DependencyCycle { projects } => json!({ "projects": projects }),
+        KhadimError::Other(_) => json!({}),
+    };
+    if let (Some(details), Value::Object(fields)) = (details.as_object_mut(), fields) {
+        details.extend(fields);
+    }
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_to_ask_without_a_terminal() {
+        let mut input = "y\n".as_bytes();
+        let error = ask("Go on?", false, &mut input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "❌ Cannot ask \"Go on?\" without a terminal, pass --yes to go on 😢"
+        );
+        // The answer was not read
+        assert_eq!(input, b"y\n");
+    }
+
+    #[test]
+    fn defaults_to_no() {
+        for (answer, expected) in [
+            ("y\n", true),
+            (" YES \n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+        ] {
+            let mut input = answer.as_bytes();
+            assert_eq!(
+                ask("Go on?", true, &mut input).unwrap(),
+                expected,
+                "{:?}",
+                answer
+            );
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::error::KhadimError;
+use crate::registry::RegistryClient;
+use crate::version_range::VersionRange;
+use std::str::FromStr;
+
+/// How a resolved registry version is written to package.json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SavePrefix {
+    /// `^1.2.3`, allowing minor and patch updates.
+    #[default]
+    Caret,
+    /// `~1.2.3`, allowing patch updates.
+    Tilde,
+    /// `1.2.3`, pinning the version.
+    Exact,
+}
+
+impl SavePrefix {
+    /// Applies the prefix to a concrete version.
+    pub fn apply(&self, version: &str) -> String {
+        match self {
+            SavePrefix::Caret => format!("^{}", version),
+            SavePrefix::Tilde => format!("~{}", version),
+            SavePrefix::Exact => version.to_string(),
+        }
+    }
+}
+
+impl FromStr for SavePrefix {
+    type Err = KhadimError;
+
+    fn from_str(prefix: &str) -> Result<SavePrefix, KhadimError> {
+        match prefix {
+            "^" | "caret" => Ok(SavePrefix::Caret),
+            "~" | "tilde" => Ok(SavePrefix::Tilde),
+            "" | "exact" => Ok(SavePrefix::Exact),
+            other => Err(format!(
+                "❌ Invalid save prefix '{}', expected ^, ~ or exact 😢",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+/// Where a dependency comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecSource {
+    /// A registry version: empty for `latest`, a dist-tag, an exact version or a range.
+    Registry(String),
+    /// A local path, e.g. `file:../../libs/hello`.
+    File(String),
+    /// A git URL, e.g. `git+https://github.com/user/repo.git#main`.
+    Git(String),
+    /// A registry package installed under another name, e.g. `npm:react@18`.
+    Alias { package: String, spec: String },
+}
+
+/// A parsed npm-style package specifier such as `react`, `react@18.2.0`,
+/// `next@~14.2`, `@scope/pkg@1`, `hello@file:../hello`,
+/// `tool@git+https://github.com/user/tool.git` or `react18@npm:react@18`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    /// The name the dependency is saved under.
+    pub name: String,
+    pub source: SpecSource,
+}
+
+impl PackageSpec {
+    /// Parses a package specifier.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the name is missing or invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::package_spec::{PackageSpec, SpecSource};
+    ///
+    /// let spec = PackageSpec::parse("@scope/pkg@1").unwrap();
+    /// assert_eq!(spec.name, "@scope/pkg");
+    /// assert_eq!(spec.source, SpecSource::Registry("1".to_string()));
+    /// ```
+    pub fn parse(spec: &str) -> Result<PackageSpec, KhadimError> {
+        // The version separator is the first `@` that does not start a scope
+        let (name, version) = match spec.char_indices().skip(1).find(|&(_, c)| c == '@') {
+            Some((index, _)) => (&spec[..index], &spec[index + 1..]),
+            None => (spec, ""),
+        };
+        validate_name(name, spec)?;
+
+        let source = if let Some(path) = version.strip_prefix("file:") {
+            SpecSource::File(path.to_string())
+        } else if version.starts_with("git+") || version.starts_with("git://") {
+            SpecSource::Git(version.to_string())
+        } else if let Some(aliased) = version.strip_prefix("npm:") {
+            let target = PackageSpec::parse(aliased)?;
+            match target.source {
+                SpecSource::Registry(spec) => SpecSource::Alias {
+                    package: target.name,
+                    spec,
+                },
+                _ => {
+                    return Err(format!(
+                        "❌ npm: aliases must point to a registry package: {} 😢",
+                        spec
+                    )
+                    .into())
+                }
+            }
+        } else {
+            SpecSource::Registry(version.to_string())
+        };
+
+        Ok(PackageSpec {
+            name: name.to_string(),
+            source,
+        })
+    }
+
+    /// Resolves the specifier to the value written to package.json.
+    ///
+    /// * Ranges such as `~14.2` or `>=1 <2` are saved as written, once the
+    ///   registry confirms that a version satisfies them
+    /// * Dist-tags, exact versions and empty specs are resolved to a version
+    ///   and saved with the given prefix
+    /// * `file:` and `git+` specs are saved as written without contacting the registry
+    /// * `npm:` aliases are resolved like registry specs, e.g. `npm:react@^18.3.1`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the registry cannot resolve the spec.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::package_spec::{PackageSpec, SavePrefix};
+    /// use khadim_rs::registry::RegistryClient;
+    /// use std::path::Path;
+    ///
+    /// let registry = RegistryClient::for_root(Path::new("/path/to/project"));
+    /// let spec = PackageSpec::parse("next@14.2.8").unwrap();
+    /// assert_eq!(spec.resolve(&registry, SavePrefix::Exact).unwrap(), "14.2.8");
+    /// ```
+    pub fn resolve(
+        &self,
+        registry: &RegistryClient,
+        prefix: SavePrefix,
+    ) -> Result<String, KhadimError> {
+        match &self.source {
+            SpecSource::Registry(spec) => resolve_registry(registry, &self.name, spec, prefix),
+            SpecSource::File(path) => Ok(format!("file:{}", path)),
+            SpecSource::Git(url) => Ok(url.clone()),
+            SpecSource::Alias { package, spec } => Ok(format!(
+                "npm:{}@{}",
+                package,
+                resolve_registry(registry, package, spec, prefix)?
+            )),
+        }
+    }
+}
+
+fn resolve_registry(
+    registry: &RegistryClient,
+    package: &str,
+    spec: &str,
+    prefix: SavePrefix,
+) -> Result<String, KhadimError> {
+    let version = registry.resolve(package, spec)?;
+    if is_range(spec) {
+        Ok(spec.to_string())
+    } else {
+        Ok(prefix.apply(&version))
+    }
+}
+
+/// Returns `true` for specs that are ranges rather than a single version or a dist-tag.
+fn is_range(spec: &str) -> bool {
+    !spec.is_empty()
+        && semver::Version::parse(spec.trim_start_matches(['v', '='])).is_err()
+        && VersionRange::parse(spec).is_ok()
+}
+
+/// Checks a package name against npm's naming rules.
+fn validate_name(name: &str, spec: &str) -> Result<(), KhadimError> {
+    let bare = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, package)) if !scope.is_empty() => package,
+            _ => "",
+        },
+        None => name,
+    };
+
+    let is_valid = !bare.is_empty()
+        && name.len() <= 214
+        && !bare.starts_with(['.', '_'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "@/-._~".contains(c));
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format!("❌ Invalid package name in '{}' 😢", spec).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &str) -> (String, SpecSource) {
+        let parsed = PackageSpec::parse(spec).unwrap();
+        (parsed.name, parsed.source)
+    }
+
+    #[test]
+    fn parses_registry_specs() {
+        assert_eq!(
+            parse("react"),
+            ("react".to_string(), SpecSource::Registry(String::new()))
+        );
+        assert_eq!(
+            parse("next@~14.2"),
+            (
+                "next".to_string(),
+                SpecSource::Registry("~14.2".to_string())
+            )
+        );
+        assert_eq!(
+            parse("@scope/pkg@>=1 <2"),
+            (
+                "@scope/pkg".to_string(),
+                SpecSource::Registry(">=1 <2".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn parses_file_specs() {
+        assert_eq!(
+            parse("@libs/hello@file:../../libs/hello"),
+            (
+                "@libs/hello".to_string(),
+                SpecSource::File("../../libs/hello".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn parses_git_specs() {
+        for url in [
+            "git+https://github.com/user/tool.git#main",
+            "git+ssh://git@github.com/user/tool.git",
+            "git://github.com/user/tool.git",
+        ] {
+            assert_eq!(
+                parse(&format!("tool@{}", url)),
+                ("tool".to_string(), SpecSource::Git(url.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn parses_npm_aliases() {
+        assert_eq!(
+            parse("react18@npm:react@^18.3"),
+            (
+                "react18".to_string(),
+                SpecSource::Alias {
+                    package: "react".to_string(),
+                    spec: "^18.3".to_string()
+                }
+            )
+        );
+        assert_eq!(
+            parse("types@npm:@types/node"),
+            (
+                "types".to_string(),
+                SpecSource::Alias {
+                    package: "@types/node".to_string(),
+                    spec: String::new()
+                }
+            )
+        );
+        assert!(PackageSpec::parse("hello@npm:hello@file:../hello").is_err());
+    }
+
+    #[test]
+    fn rejects_missing_names() {
+        assert!(PackageSpec::parse("").is_err());
+        assert!(PackageSpec::parse("@1.2.3").is_err());
+    }
+
+    #[test]
+    fn applies_save_prefixes() {
+        let prefixes: Vec<SavePrefix> = ["^", "tilde", "exact"]
+            .iter()
+            .map(|prefix| prefix.parse().unwrap())
+            .collect();
+        let saved: Vec<String> = prefixes
+            .iter()
+            .map(|prefix| prefix.apply("1.2.3"))
+            .collect();
+        assert_eq!(saved, ["^1.2.3", "~1.2.3", "1.2.3"]);
+        assert!("latest".parse::<SavePrefix>().is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use crate::error::KhadimError;
+use crate::output;
+use crate::transaction::write_atomic;
+use diffy::DiffOptions;
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Whether actions are only planned instead of performed.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// The actions planned so far in dry-run mode.
+static DRY_RUN_PLAN: Mutex<Plan> = Mutex::new(Plan::new());
+
+/// Sets whether actions are only planned, for the rest of the process.
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::SeqCst);
+}
+
+/// Returns `true` if actions are only planned instead of performed.
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+/// A change to the file system, or a command, that a command needs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Create or replace a file, through a temporary file renamed over it.
+    WriteFile {
+        path: PathBuf,
+        #[serde(skip)]
+        content: String,
+    },
+    /// Delete a file.
+    DeleteFile { path: PathBuf },
+    /// Delete a directory and everything in it.
+    DeleteDir { path: PathBuf },
+    /// Run a command and capture its output.
+    RunCommand {
+        program: String,
+        args: Vec<String>,
+        cwd: PathBuf,
+    },
+}
+
+impl Action {
+    /// Creates the action of running a program with arguments in a directory.
+    pub fn run_command(program: &str, args: &[&str], cwd: &Path) -> Action {
+        Action::RunCommand {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            cwd: cwd.to_path_buf(),
+        }
+    }
+}
+
+/// An action of a plan, with what it would change.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedAction {
+    #[serde(flatten)]
+    pub action: Action,
+    /// For a file write, the unified diff from the file's planned content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// For a directory deletion, the size of its files in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+}
+
+impl PlannedAction {
+    /// Describes the action in one line, e.g. `🗑️ Delete node_modules (12.5 MB)`.
+    pub fn describe(&self) -> String {
+        match &self.action {
+            Action::WriteFile { path, .. } if self.diff.as_deref() == Some("") => {
+                format!("📝 Write {} (unchanged)", path.display())
+            }
+            Action::WriteFile { path, .. } => format!("📝 Write {}", path.display()),
+            Action::DeleteFile { path } => format!("🗑️ Delete {}", path.display()),
+            Action::DeleteDir { path } => format!(
+                "🗑️ Delete {} ({})",
+                path.display(),
+                format_size(self.size_bytes.unwrap_or(0))
+            ),
+            Action::RunCommand { program, args, cwd } => format!(
+                "▶️ Run `{} {}` in {}",
+                program,
+                args.join(" "),
+                cwd.display()
+            ),
+        }
+    }
+}
+
+/// The actions of a command, in order, with the diffs of the files they
+/// write and the sizes of the directories they delete.
+///
+/// Diffs are computed against the content that earlier actions of the plan
+/// give a file, so a file deleted then written again shows as created.
+///
+/// # Examples
+///
+/// ```
+/// use khadim_rs::plan::{Action, Plan};
+/// use std::path::PathBuf;
+///
+/// let mut plan = Plan::new();
+/// plan.add(Action::WriteFile {
+///     path: PathBuf::from("/nonexistent/package.json"),
+///     content: "{}\n".to_string(),
+/// });
+/// assert_eq!(plan.actions().len(), 1);
+/// assert!(plan.actions()[0].diff.as_deref().unwrap().contains("+{}"));
+/// ```
+#[derive(Debug, Default)]
+pub struct Plan {
+    actions: Vec<PlannedAction>,
+    /// The content of each file after the actions so far, or `None` once deleted.
+    files: BTreeMap<PathBuf, Option<String>>,
+}
+
+impl Plan {
+    /// Creates an empty plan.
+    pub const fn new() -> Plan {
+        Plan {
+            actions: Vec::new(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an action to the plan, describing what it would change.
+    pub fn add(&mut self, action: Action) -> &PlannedAction {
+        let mut planned = PlannedAction {
+            action,
+            diff: None,
+            size_bytes: None,
+        };
+        match &planned.action {
+            Action::WriteFile { path, content } => {
+                let before = match self.files.get(path) {
+                    Some(before) => before.clone(),
+                    None => fs::read_to_string(path).ok(),
+                };
+                planned.diff = Some(unified_diff(path, before.as_deref(), content));
+                self.files.insert(path.clone(), Some(content.clone()));
+            }
+            Action::DeleteFile { path } => {
+                self.files.insert(path.clone(), None);
+            }
+            Action::DeleteDir { path } => {
+                planned.size_bytes = Some(dir_size(path));
+                for (file, content) in self.files.iter_mut() {
+                    if file.starts_with(path) {
+                        *content = None;
+                    }
+                }
+            }
+            Action::RunCommand { .. } => {}
+        }
+        self.actions.push(planned);
+        self.actions.last().expect("an action was just added")
+    }
+
+    /// Returns the actions of the plan, in order.
+    pub fn actions(&self) -> &[PlannedAction] {
+        &self.actions
+    }
+
+    /// Returns `true` if the plan has no action.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Summarizes the plan in one line, e.g. `2 files to write, 3 directories
+    /// to delete (1.2 GB), 1 command to run`.
+    pub fn summary(&self) -> String {
+        let count = |f: fn(&Action) -> bool| self.actions.iter().filter(|a| f(&a.action)).count();
+        // Writes that leave a file as it is change nothing
+        let writes = self
+            .actions
+            .iter()
+            .filter(|a| a.diff.as_deref().is_some_and(|diff| !diff.is_empty()))
+            .count();
+        let files = count(|action| matches!(action, Action::DeleteFile { .. }));
+        let dirs = count(|action| matches!(action, Action::DeleteDir { .. }));
+        let commands = count(|action| matches!(action, Action::RunCommand { .. }));
+        let size: u64 = self.actions.iter().filter_map(|a| a.size_bytes).sum();
+
+        let mut parts = Vec::new();
+        if writes > 0 {
+            parts.push(format!("{} to write", plural(writes, "file")));
+        }
+        if files > 0 {
+            parts.push(format!("{} to delete", plural(files, "file")));
+        }
+        if dirs > 0 {
+            parts.push(format!(
+                "{} to delete ({})",
+                plural(dirs, "directory"),
+                format_size(size)
+            ));
+        }
+        if commands > 0 {
+            parts.push(format!("{} to run", plural(commands, "command")));
+        }
+        if parts.is_empty() {
+            "nothing to do".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Performs an action, or in dry-run mode only adds it to the plan and logs it.
+///
+/// This is where commands that support `--dry-run` change the file system or
+/// run other programs, so that a dry run has no side effects.
+///
+/// # Returns
+///
+/// * `Result<Option<Output>, KhadimError>` - The output of a command that ran,
+///   or `None` for other actions and in dry-run mode.
+///
+/// # Errors
+///
+/// This function will return an error if the action fails, see `execute`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::plan::{perform, set_dry_run, Action};
+/// use std::path::PathBuf;
+///
+/// set_dry_run(true);
+/// // Logs "📝 Write package.json" and the diff, without writing anything
+/// perform(Action::WriteFile {
+///     path: PathBuf::from("package.json"),
+///     content: "{}\n".to_string(),
+/// })
+/// .unwrap();
+/// ```
+pub fn perform(action: Action) -> Result<Option<Output>, KhadimError> {
+    if !is_dry_run() {
+        return execute(&action);
+    }
+
+    let mut plan = DRY_RUN_PLAN.lock().unwrap_or_else(|e| e.into_inner());
+    let planned = plan.add(action);
+    info!("{}", planned.describe());
+    if let Some(diff) = planned.diff.as_deref().filter(|diff| !diff.is_empty()) {
+        for line in diff.lines() {
+            info!("    {}", line);
+        }
+    }
+    Ok(None)
+}
+
+/// Performs an action, even in dry-run mode.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The file cannot be written or deleted
+/// * The directory cannot be deleted
+/// * The program cannot be started
+pub fn execute(action: &Action) -> Result<Option<Output>, KhadimError> {
+    match action {
+        Action::WriteFile { path, content } => write_atomic(path, content.as_bytes())?,
+        Action::DeleteFile { path } => {
+            fs::remove_file(path).map_err(|e| KhadimError::io(path, e))?
+        }
+        Action::DeleteDir { path } => {
+            fs::remove_dir_all(path).map_err(|e| KhadimError::io(path, e))?
+        }
+        Action::RunCommand { program, args, cwd } => {
+            let output = Command::new(program)
+                .args(args)
+                .current_dir(cwd)
+                .output()
+                .map_err(|e| KhadimError::spawn(program, e))?;
+            return Ok(Some(output));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads a file with the content that the actions planned so far in dry-run
+/// mode give it, so that later steps build on the earlier planned writes.
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be read, or if a
+/// planned action deletes it.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    if is_dry_run() {
+        let plan = DRY_RUN_PLAN.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(content) = plan.files.get(path) {
+            return content
+                .clone()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "deleted by the dry run"));
+        }
+    }
+    fs::read_to_string(path)
+}
+
+/// Returns `true` if a file exists once the actions planned so far in dry-run
+/// mode are applied, e.g. `false` for a file that a planned reset deletes.
+pub fn exists(path: &Path) -> bool {
+    if is_dry_run() {
+        let plan = DRY_RUN_PLAN.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(content) = plan.files.get(path) {
+            return content.is_some();
+        }
+    }
+    path.exists()
+}
+
+/// Takes the actions planned so far in dry-run mode, leaving an empty plan.
+pub fn take_dry_run_plan() -> Plan {
+    let mut plan = DRY_RUN_PLAN.lock().unwrap_or_else(|e| e.into_inner());
+    std::mem::take(&mut *plan)
+}
+
+/// Logs the summary of the actions planned in dry-run mode, and emits them as
+/// a `plan` report in JSON mode.
+pub fn report_dry_run() {
+    let plan = take_dry_run_plan();
+    output::report("plan", &plan.actions);
+    info!("\n📋 Dry run: {}. Nothing was changed.", plan.summary());
+}
+
+/// Returns the total size of the files in a directory, without following
+/// symbolic links.
+pub fn dir_size(dir: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(dir) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| dir_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Formats a size in bytes for humans, e.g. `12.5 MB`.
+///
+/// # Examples
+///
+/// ```
+/// use khadim_rs::plan::format_size;
+///
+/// assert_eq!(format_size(512), "512 B");
+/// assert_eq!(format_size(13_107_200), "12.5 MB");
+/// ```
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Returns the unified diff between the content of a file before and after,
+/// or an empty string if they are the same.
+fn unified_diff(path: &Path, before: Option<&str>, after: &str) -> String {
+    if before == Some(after) {
+        return String::new();
+    }
+    let path = path.display().to_string();
+    let original = if before.is_some() {
+        path.clone()
+    } else {
+        "/dev/null".to_string()
+    };
+    DiffOptions::new()
+        .set_original_filename(original)
+        .set_modified_filename(path)
+        .create_patch(before.unwrap_or_default(), after)
+        .to_string()
+}
+
+/// Returns e.g. `1 file` or `2 files`.
+fn plural(count: usize, noun: &str) -> String {
+    match (count, noun) {
+        (1, noun) => format!("1 {}", noun),
+        (count, "directory") => format!("{} directories", count),
+        (count, noun) => format!("{} {}s", count, noun),
+    }
+}
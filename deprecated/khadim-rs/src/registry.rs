@@ -0,0 +1,386 @@
+use crate::config::{CACHE_DIR, DEFAULT_REGISTRY, PACKAGE_LOCK_JSON};
+use crate::error::KhadimError;
+use crate::output;
+use crate::plan;
+use crate::transaction::write_atomic;
+use crate::version_range::VersionRange;
+use log::debug;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Environment variables checked, in order, for the registry URL.
+const REGISTRY_ENV_VARS: [&str; 2] = ["KHADIM_REGISTRY", "npm_config_registry"];
+
+/// The abbreviated package metadata ("packument") of an npm package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Packument {
+    pub name: String,
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: BTreeMap<String, String>,
+    /// All published versions.
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+/// The packument as returned by the registry, where versions are an object.
+#[derive(Deserialize)]
+struct RegistryPackument {
+    name: String,
+    #[serde(rename = "dist-tags", default)]
+    dist_tags: BTreeMap<String, String>,
+    #[serde(default)]
+    versions: BTreeMap<String, IgnoredAny>,
+}
+
+/// A minimal npm registry client.
+///
+/// Packuments are fetched over HTTP and written to an on-disk cache. When the
+/// registry cannot be reached, the client falls back to the cached packument,
+/// and then to the version installed according to the lockfile.
+#[derive(Debug, Clone)]
+pub struct RegistryClient {
+    registry_url: String,
+    cache_dir: Option<PathBuf>,
+    lockfile: Option<PathBuf>,
+    agent: ureq::Agent,
+}
+
+impl RegistryClient {
+    /// Creates a client for the given registry URL, without cache or lockfile fallback.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::registry::RegistryClient;
+    ///
+    /// // e.g. a local stand-in registry
+    /// let client = RegistryClient::new("http://127.0.0.1:4873");
+    /// let version = client.resolve("react", "^18").unwrap();
+    /// ```
+    pub fn new(registry_url: &str) -> RegistryClient {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(5))
+            .timeout(Duration::from_secs(30))
+            .build();
+        RegistryClient {
+            registry_url: registry_url.trim_end_matches('/').to_string(),
+            cache_dir: None,
+            lockfile: None,
+            agent,
+        }
+    }
+
+    /// Creates a client for a workspace root.
+    ///
+    /// The registry URL is read from `KHADIM_REGISTRY` or `npm_config_registry`,
+    /// defaulting to the public npm registry. Packuments are cached in the
+    /// `registry` directory of the task cache, and the root `package-lock.json`
+    /// is used as the last fallback.
+    pub fn for_root(root_dir: &Path) -> RegistryClient {
+        let registry_url = REGISTRY_ENV_VARS
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+            .unwrap_or_else(|| DEFAULT_REGISTRY.to_string());
+
+        RegistryClient::new(&registry_url)
+            .with_cache_dir(&root_dir.join(CACHE_DIR).join("registry"))
+            .with_lockfile(&root_dir.join(PACKAGE_LOCK_JSON))
+    }
+
+    /// Caches fetched packuments in the given directory.
+    pub fn with_cache_dir(mut self, cache_dir: &Path) -> RegistryClient {
+        self.cache_dir = Some(cache_dir.to_path_buf());
+        self
+    }
+
+    /// Falls back to the versions installed according to the given package-lock.json.
+    pub fn with_lockfile(mut self, lockfile: &Path) -> RegistryClient {
+        self.lockfile = Some(lockfile.to_path_buf());
+        self
+    }
+
+    /// Fetches the packument of a package, falling back to the cache when offline.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the registry cannot be reached
+    /// or rejects the request, and no cached packument exists.
+    pub fn packument(&self, package: &str) -> Result<Packument, KhadimError> {
+        match self.fetch_packument(package) {
+            Ok(packument) => {
+                if let Err(e) = self.write_cache(&packument) {
+                    output::warn(&format!(
+                        "Cannot cache registry metadata for {}: {}",
+                        package, e
+                    ));
+                }
+                Ok(packument)
+            }
+            Err(e) => match self.read_cache(package) {
+                Some(packument) => {
+                    output::warn(&format!(
+                        "Using cached registry metadata for {} ({})",
+                        package, e
+                    ));
+                    Ok(packument)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Resolves a dist-tag or semver range to a concrete version.
+    ///
+    /// An empty spec resolves the `latest` dist-tag. If neither the registry
+    /// nor the cache is available, the version installed according to the
+    /// lockfile is used when it satisfies the spec.
+    ///
+    /// # Arguments
+    ///
+    /// * `package` - The package name, e.g. `react` or `@types/node`.
+    /// * `spec` - A dist-tag such as `latest` or `next`, or a range such as `^18` or `14.2.8`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * No metadata is available from the registry, the cache or the lockfile
+    /// * The dist-tag does not exist, or no version satisfies the range
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::registry::RegistryClient;
+    /// use std::path::Path;
+    ///
+    /// let client = RegistryClient::for_root(Path::new("/path/to/project"));
+    /// assert_eq!(client.resolve("next", "14.2.8").unwrap(), "14.2.8");
+    /// println!("latest react: {}", client.resolve("react", "latest").unwrap());
+    /// ```
+    pub fn resolve(&self, package: &str, spec: &str) -> Result<String, KhadimError> {
+        let spec = if spec.is_empty() { "latest" } else { spec };
+
+        let packument = match self.packument(package) {
+            Ok(packument) => packument,
+            Err(e) => {
+                return match self.locked_version(package) {
+                    Some(version) if spec_allows(spec, &version) => {
+                        output::warn(&format!(
+                            "Using {}@{} from the lockfile ({})",
+                            package, version, e
+                        ));
+                        Ok(version)
+                    }
+                    _ => Err(e),
+                }
+            }
+        };
+
+        if let Some(version) = packument.dist_tags.get(spec) {
+            return Ok(version.clone());
+        }
+
+        let range = VersionRange::parse(spec).map_err(|_| KhadimError::Registry {
+            package: package.to_string(),
+            message: format!("Unknown dist-tag '{}' for {}", spec, package),
+        })?;
+        range
+            .max_satisfying(&packument.versions)
+            .ok_or_else(|| KhadimError::Registry {
+                package: package.to_string(),
+                message: format!("No version of {} satisfies '{}'", package, spec),
+            })
+    }
+
+    /// Returns the version of a package installed according to the lockfile.
+    pub fn locked_version(&self, package: &str) -> Option<String> {
+        let content = fs::read_to_string(self.lockfile.as_ref()?).ok()?;
+        let lockfile: Value = serde_json::from_str(&content).ok()?;
+        lockfile["packages"][format!("node_modules/{}", package)]["version"]
+            .as_str()
+            .or_else(|| lockfile["dependencies"][package]["version"].as_str())
+            .map(str::to_string)
+    }
+
+    fn fetch_packument(&self, package: &str) -> Result<Packument, KhadimError> {
+        // Scoped packages are requested as `@scope%2fname`
+        let url = format!("{}/{}", self.registry_url, package.replace('/', "%2f"));
+        debug!("Fetching registry metadata from {}", url);
+        let response = self
+            .agent
+            .get(&url)
+            .set("Accept", "application/vnd.npm.install-v1+json")
+            .call()
+            .map_err(|e| KhadimError::Registry {
+                package: package.to_string(),
+                message: match e {
+                    ureq::Error::Status(404, _) => {
+                        format!("Package {} not found in {}", package, self.registry_url)
+                    }
+                    other => format!("Cannot reach registry {}: {}", self.registry_url, other),
+                },
+            })?;
+
+        let packument: RegistryPackument = serde_json::from_reader(response.into_reader())
+            .map_err(|e| KhadimError::Registry {
+                package: package.to_string(),
+                message: format!("Invalid registry response for {}: {}", package, e),
+            })?;
+        Ok(Packument {
+            name: packument.name,
+            dist_tags: packument.dist_tags,
+            versions: packument.versions.into_keys().collect(),
+        })
+    }
+
+    fn cache_path(&self, package: &str) -> Option<PathBuf> {
+        self.cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", package.replace('/', "__"))))
+    }
+
+    fn read_cache(&self, package: &str) -> Option<Packument> {
+        let content = fs::read_to_string(self.cache_path(package)?).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn write_cache(&self, packument: &Packument) -> Result<(), KhadimError> {
+        // A dry run leaves the cache as it is too
+        if plan::is_dry_run() {
+            return Ok(());
+        }
+        if let Some(path) = self.cache_path(&packument.name) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // An interrupted write must not leave a truncated packument behind
+            let content =
+                serde_json::to_string(packument).map_err(|e| KhadimError::io(&path, e.into()))?;
+            write_atomic(&path, content.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if a version satisfies a spec, treating every dist-tag as a match.
+fn spec_allows(spec: &str, version: &str) -> bool {
+    match (VersionRange::parse(spec), semver::Version::parse(version)) {
+        (Ok(range), Ok(version)) => range.matches(&version),
+        (Err(_), _) => true,
+        (Ok(_), Err(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Mutex;
+    use std::thread;
+
+    /// Serializes the tests that set the registry environment variable.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    const REACT: &str = r#"{
+        "name": "react",
+        "dist-tags": { "latest": "18.3.1", "next": "19.0.0-rc.1" },
+        "versions": { "17.0.2": {}, "18.2.0": {}, "18.3.1": {}, "19.0.0-rc.1": {} }
+    }"#;
+
+    /// Starts a stand-in registry on 127.0.0.1 that answers every request
+    /// with the packument, and returns its URL.
+    fn serve(packument: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, packument);
+            }
+        });
+        url
+    }
+
+    fn respond(mut stream: TcpStream, body: &str) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        // Skip the request line and headers
+        while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+            line.clear();
+        }
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+    }
+
+    /// Returns the URL of a port that nothing listens on.
+    fn unreachable_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    /// Creates a client for the root, as configured by `KHADIM_REGISTRY`.
+    fn client_for(root_dir: &Path, registry_url: &str) -> RegistryClient {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_var("KHADIM_REGISTRY", registry_url);
+        let client = RegistryClient::for_root(root_dir);
+        env::remove_var("KHADIM_REGISTRY");
+        client
+    }
+
+    #[test]
+    fn resolves_from_the_registry() {
+        let root = tempfile::tempdir().unwrap();
+        let client = client_for(root.path(), &serve(REACT));
+
+        assert_eq!(client.resolve("react", "").unwrap(), "18.3.1");
+        assert_eq!(client.resolve("react", "next").unwrap(), "19.0.0-rc.1");
+        assert_eq!(client.resolve("react", "^18.0.0").unwrap(), "18.3.1");
+        assert_eq!(client.resolve("react", "17").unwrap(), "17.0.2");
+        assert!(matches!(
+            client.resolve("react", "^20"),
+            Err(KhadimError::Registry { .. })
+        ));
+
+        let cached = root.path().join(".khadim-cache/registry/react.json");
+        let packument: Packument =
+            serde_json::from_str(&fs::read_to_string(cached).unwrap()).unwrap();
+        assert_eq!(packument.versions.len(), 4);
+    }
+
+    #[test]
+    fn resolves_offline_from_the_cache() {
+        let root = tempfile::tempdir().unwrap();
+        client_for(root.path(), &serve(REACT))
+            .resolve("react", "latest")
+            .unwrap();
+
+        let offline = client_for(root.path(), &unreachable_url());
+        assert_eq!(offline.resolve("react", "~18.2").unwrap(), "18.2.0");
+        assert_eq!(offline.resolve("react", "latest").unwrap(), "18.3.1");
+    }
+
+    #[test]
+    fn resolves_offline_from_the_lockfile() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(
+            root.path().join("package-lock.json"),
+            r#"{ "packages": { "node_modules/react": { "version": "18.2.0" } } }"#,
+        )
+        .unwrap();
+        let offline = client_for(root.path(), &unreachable_url());
+
+        assert_eq!(offline.resolve("react", "^18").unwrap(), "18.2.0");
+        assert_eq!(offline.resolve("react", "latest").unwrap(), "18.2.0");
+        assert!(offline.resolve("react", "^19").is_err());
+        assert!(offline.resolve("vue", "latest").is_err());
+    }
+}
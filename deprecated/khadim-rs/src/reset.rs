@@ -1,190 +1,934 @@
-use crate::config::{APPS_DIR, LIBS_DIR, PACKAGE_JSON};
+use crate::affected::run_git;
+use crate::config::{PACKAGE_JSON, PACKAGE_TMPL_JSON};
+use crate::error::KhadimError;
 use crate::init;
-use crate::BoxError;
+use crate::logger;
+use crate::output::{self, Step};
+use crate::plan::{self, Action, Plan};
+use crate::root::find_root_dir;
+use crate::workspace::{Project, Workspace};
+use log::{debug, error, info};
 use rayon::prelude::*;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-/// Resets the project by removing existing configuration and dependencies,
-/// then reinitializing the project.
+/// The build output directories that `--build-outputs` deletes in each project,
+/// besides the `project.outputs` of its package.json.
+pub const BUILD_OUTPUT_DIRS: [&str; 3] = [".next", "dist", "out"];
+
+/// The file that marks a Python virtual environment directory.
+const VENV_MARKER: &str = "pyvenv.cfg";
+
+/// What `reset` deletes and reinstalls.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ResetScope {
+    /// The root and every app and lib.
+    #[default]
+    All,
+    /// The root package.json and node_modules only.
+    Root,
+    /// Every app.
+    Apps,
+    /// Every lib.
+    Libs,
+    /// The apps and libs matching a name or glob, as with `Workspace::select`.
+    Projects(String),
+}
+
+/// What `reset` deletes besides node_modules, and how it makes sure that it
+/// only deletes what it should.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResetOptions {
+    /// The part of the workspace to reset.
+    pub scope: ResetScope,
+    /// Also delete the build outputs of the projects, e.g. `.next` and `dist`.
+    pub build_outputs: bool,
+    /// Also delete the Python virtual environments of the projects.
+    pub venvs: bool,
+    /// Also clear the npm cache, with `npm cache clean --force`.
+    pub npm_cache: bool,
+    /// Only delete, without regenerating package.json and reinstalling.
+    pub skip_install: bool,
+    /// Delete without asking for confirmation, e.g. on CI.
+    pub assume_yes: bool,
+    /// Reset even if package.json or package-tmpl.json files have uncommitted changes.
+    pub force: bool,
+}
+
+/// Resets the project, or part of it, by removing existing configuration and
+/// dependencies, then reinstalling them.
 ///
 /// This function performs the following steps:
 /// 1. Finds the root directory of the project.
-/// 2. Deletes the package.json file if it exists.
-/// 3. Removes node_modules directories from the root, apps (APPS_DIR), and libs (LIBS_DIR) directories in parallel.
-/// 4. Recreates the package.json file and reinstalls all dependencies.
+/// 2. Refuses to go on if a manifest has uncommitted changes, unless `force` is set.
+/// 3. Lists what the scope covers, with sizes, and asks for confirmation unless
+///    `assume_yes` is set: the root package.json and node_modules, every
+///    node_modules directory of the selected projects, and their build outputs
+///    and virtual environments when asked for.
+/// 4. Deletes the package.json, then the directories in parallel, then clears
+///    the npm cache if asked for.
+/// 5. Unless `skip_install` is set, recreates the package.json for the root
+///    and reinstalls the dependencies of the reset projects.
+///
+/// Nothing outside of the root directory is deleted: symbolic links are
+/// removed rather than followed, and projects linked from elsewhere are skipped.
+///
+/// # Arguments
+///
+/// * `options` - The scope, what to delete besides node_modules, and whether to
+///   skip the confirmation, the check for uncommitted changes and the reinstall.
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if the reset is successful,
-///   or an error if any step fails.
+/// * `Result<bool, KhadimError>` - `Ok(true)` if the project was reset, or
+///   `Ok(false)` if the user declined.
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 /// * The root directory cannot be found
-/// * Deleting files or directories fails
-/// * Reinitializing the project fails
+/// * No project matches the scope's pattern
+/// * A manifest has uncommitted changes and `force` is not set
+/// * Deleting package.json or a directory, or clearing the npm cache, fails
+/// * Reinstalling fails
 ///
 /// # Examples
 ///
-/// ```
-/// use khadim-rs::reset::reset_project;
+/// ```no_run
+/// use khadim_rs::reset::{reset_project, ResetOptions, ResetScope};
 ///
-/// let result = reset_project();
+/// // Rebuild a single app from scratch
+/// let options = ResetOptions {
+///     scope: ResetScope::Projects("organic-lever-web".to_string()),
+///     build_outputs: true,
+///     assume_yes: true,
+///     ..ResetOptions::default()
+/// };
+/// let result = reset_project(&options);
 /// assert!(result.is_ok());
 /// ```
-
-/// ```
-/// use khadim-rs::reset::delete_node_modules;
-/// use std::path::Path;
-///
-/// let project_dir = Path::new(".");
-/// let result = delete_node_modules(project_dir);
-/// assert!(result.is_ok());
-/// ```
-pub fn reset_project() -> Result<(), BoxError> {
-    println!("🔄 Resetting project...");
+pub fn reset_project(options: &ResetOptions) -> Result<bool, KhadimError> {
+    info!("🔄 Resetting project...");
     let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let projects = scoped_projects(&workspace, &options.scope)?;
 
-    // Delete package.json
-    if Path::new(PACKAGE_JSON).exists() {
-        fs::remove_file(PACKAGE_JSON)?;
-        println!("Removed {}", PACKAGE_JSON);
+    if !options.force {
+        check_manifests_committed(&workspace, &projects, &options.scope)?;
     }
 
-    // Delete node_modules in root, apps, and libs in parallel
-    let dirs_to_clean = vec![
-        root_dir.clone(),
-        root_dir.join(APPS_DIR),
-        root_dir.join(LIBS_DIR),
-    ];
-
-    let cpu_count = num_cpus::get();
-    let max_workers = std::cmp::max(1, cpu_count - 1); // Use all cores except one
-    println!(
-        "🚀 Deleting node_modules in parallel (max {} workers)",
-        max_workers
-    );
+    let plan = plan_reset(&workspace, &projects, options)?;
+    if !plan::is_dry_run() {
+        if plan.is_empty() {
+            info!("Nothing to delete");
+        } else {
+            info!("The reset will:");
+            for action in plan.actions() {
+                info!("  {}", action.describe());
+            }
+            info!("Total: {}", plan.summary());
+            output::report("reset", &plan.actions());
+        }
 
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(max_workers)
-        .build()
-        .map_err(BoxError::from)?;
+        let question = if options.skip_install {
+            "Go on?"
+        } else {
+            "Go on, then reinstall the dependencies?"
+        };
+        if !plan.is_empty() && !options.assume_yes && !output::confirm(question)? {
+            output::warn("Reset cancelled, nothing was deleted");
+            return Ok(false);
+        }
+    }
 
-    let completed_count = Arc::new(AtomicUsize::new(0));
-    let total_count = Arc::new(AtomicUsize::new(dirs_to_clean.len()));
+    perform_planned(&workspace, &plan)?;
 
-    pool.install(|| {
-        dirs_to_clean.par_iter().for_each(|dir| {
-            if let Err(e) = delete_node_modules(dir) {
-                eprintln!("Error deleting node_modules in {}: {}", dir.display(), e);
+    if options.skip_install {
+        info!("⏭️ Skipping the reinstall, run `khadim-rs init` to install the dependencies");
+    } else {
+        // Recreate package.json and install dependencies
+        match options.scope {
+            ResetScope::All => init::initialize_and_install_all()?,
+            ResetScope::Root => {
+                init::initialize_and_install_root()?;
             }
-            let completed = completed_count.fetch_add(1, Ordering::SeqCst) + 1;
-            let total = total_count.load(Ordering::SeqCst);
-            println!("Progress: {}/{} directories cleaned", completed, total);
-        });
-    });
+            _ => init::install_dependencies_of(&workspace, &projects)?,
+        }
+    }
 
-    // Recreate package.json and install dependencies
-    init::initialize_and_install_all()?;
+    info!("✅ Project reset completed successfully! 🎉");
+    Ok(true)
+}
 
-    println!("✅ Project reset completed successfully! 🎉");
-    Ok(())
+/// Returns the projects that a scope covers, in workspace order.
+fn scoped_projects<'a>(
+    workspace: &'a Workspace,
+    scope: &ResetScope,
+) -> Result<Vec<&'a Project>, KhadimError> {
+    Ok(match scope {
+        ResetScope::All => workspace.projects.iter().collect(),
+        ResetScope::Root => Vec::new(),
+        ResetScope::Apps => workspace.apps().collect(),
+        ResetScope::Libs => workspace.libs().collect(),
+        ResetScope::Projects(pattern) => workspace.select(pattern)?,
+    })
 }
 
-/// Finds the root directory of the project.
+/// Lists what a reset does: deleting the root package.json and node_modules
+/// when the scope covers the root, every node_modules directory inside the
+/// given projects, their build outputs and virtual environments when the
+/// options ask for them, and clearing the npm cache.
 ///
-/// This function searches for the root directory by looking for the 'package-tmpl.json' file
-/// in the current directory and its ancestors.
+/// # Arguments
+///
+/// * `workspace` - The workspace to reset.
+/// * `projects` - The apps and libs to reset.
+/// * `options` - The scope and what to delete besides node_modules.
 ///
 /// # Returns
 ///
-/// * `Result<PathBuf, BoxError>` - The path to the root directory if found,
-///   or an error if the root directory cannot be determined.
+/// * `Result<Plan, KhadimError>` - The actions, with the size of each directory.
 ///
 /// # Errors
 ///
-/// This function will return an error if:
-/// * The current directory cannot be determined
-/// * The root directory (containing 'package-tmpl.json') is not found in the current directory or its ancestors
+/// This function will return an error if the root directory cannot be resolved.
 ///
 /// # Examples
 ///
+/// ```no_run
+/// use khadim_rs::reset::{plan_reset, ResetOptions};
+/// use khadim_rs::workspace::Workspace;
+/// use std::path::Path;
+///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+/// let apps: Vec<_> = workspace.apps().collect();
+/// let options = ResetOptions {
+///     build_outputs: true,
+///     ..ResetOptions::default()
+/// };
+/// let plan = plan_reset(&workspace, &apps, &options).unwrap();
+/// println!("Resetting the apps would delete {}", plan.summary());
 /// ```
-/// use khadim-rs::reset::find_root_dir;
-///
-/// fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-///     match find_root_dir() {
-///         Ok(root_dir) => println!("Root directory found: {}", root_dir.display()),
-///         Err(e) => eprintln!("Failed to find root directory: {}", e),
-///     }
-///     Ok(())
+pub fn plan_reset(
+    workspace: &Workspace,
+    projects: &[&Project],
+    options: &ResetOptions,
+) -> Result<Plan, KhadimError> {
+    let root_dir = &workspace.root_dir;
+    let mut plan = Plan::new();
+
+    if matches!(options.scope, ResetScope::All | ResetScope::Root) {
+        let package_json = root_dir.join(PACKAGE_JSON);
+        if package_json.is_file() {
+            plan.add(Action::DeleteFile { path: package_json });
+        }
+
+        let root_node_modules = root_dir.join("node_modules");
+        if fs::symlink_metadata(&root_node_modules).is_ok() {
+            plan.add(Action::DeleteDir {
+                path: root_node_modules,
+            });
+        }
+    }
+
+    for project in projects {
+        if !is_inside(root_dir, &project.path)? {
+            output::warn(&format!(
+                "Skipping {}, it links to a directory outside of {}",
+                project.relative_dir().display(),
+                root_dir.display()
+            ));
+            continue;
+        }
+        for node_modules in find_node_modules(&project.path) {
+            plan.add(Action::DeleteDir { path: node_modules });
+        }
+        if options.build_outputs {
+            for output in build_outputs(project) {
+                plan.add(Action::DeleteDir { path: output });
+            }
+        }
+        if options.venvs {
+            for venv in find_venvs(&project.path) {
+                plan.add(Action::DeleteDir { path: venv });
+            }
+        }
+    }
+
+    if options.npm_cache {
+        plan.add(Action::run_command(
+            "npm",
+            &["cache", "clean", "--force"],
+            root_dir,
+        ));
+    }
+
+    Ok(plan)
+}
+
+/// Returns the build output directories of a project that exist: `.next`,
+/// `dist` and `out`, and the `project.outputs` of its package.json.
+///
+/// Outputs that are symbolic links, or that point outside of the project,
+/// are left out.
+fn build_outputs(project: &Project) -> Vec<PathBuf> {
+    let declared = project.manifest.project.outputs.iter().map(String::as_str);
+    let mut outputs: Vec<PathBuf> = BUILD_OUTPUT_DIRS
+        .into_iter()
+        .chain(declared)
+        .map(|output| project.path.join(output))
+        .filter(|output| {
+            output.starts_with(&project.path)
+                && !output
+                    .components()
+                    .any(|component| component == Component::ParentDir)
+                && fs::symlink_metadata(output).is_ok_and(|metadata| metadata.is_dir())
+        })
+        .collect();
+    outputs.sort();
+    outputs.dedup();
+    outputs
+}
+
+/// Recursively finds the Python virtual environments within the given
+/// directory, such as the `venv` of `sentinel-web`: the directories that hold
+/// a `pyvenv.cfg`.
+///
+/// Symbolic links and node_modules directories are not searched.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use khadim_rs::reset::find_venvs;
+///
+/// for venv in find_venvs(Path::new("/path/to/project/apps/sentinel-web")) {
+///     println!("{}", venv.display());
 /// }
 /// ```
-pub fn find_root_dir() -> Result<PathBuf, BoxError> {
-    let current_dir = std::env::current_dir()?;
-    current_dir
-        .ancestors()
-        .find(|p| p.join("package-tmpl.json").exists())
-        .map(|p| p.to_path_buf())
-        .ok_or_else(|| BoxError::from("❌ Cannot find root directory 😢"))
+pub fn find_venvs(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        // The file type of an entry does not follow symbolic links
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        if !is_dir || entry.file_name() == "node_modules" {
+            continue;
+        }
+        let path = entry.path();
+        if path.join(VENV_MARKER).is_file() {
+            found.push(path);
+        } else {
+            found.extend(find_venvs(&path));
+        }
+    }
+
+    found.sort();
+    found
 }
 
-/// Recursively deletes 'node_modules' directories within the given directory and its subdirectories.
+/// Recursively finds the 'node_modules' directories within the given directory
+/// and its subdirectories, without looking inside them.
 ///
-/// This function performs the following actions:
-/// 1. Deletes the 'node_modules' directory in the given directory, if it exists.
-/// 2. Recursively searches for and deletes 'node_modules' directories in all subdirectories.
+/// Symbolic links are not followed, so a linked directory is never searched.
+/// A 'node_modules' that is itself a symbolic link is returned, and deleting it
+/// removes the link only.
 ///
 /// # Arguments
 ///
-/// * `dir` - A reference to a `Path` representing the directory to start the deletion process from.
+/// * `dir` - A reference to a `Path` representing the directory to search.
 ///
 /// # Returns
 ///
-/// * `Result<(), BoxError>` - Ok(()) if all operations are successful,
-///   or an error if any deletion fails.
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// * There's a problem accessing or reading the directory
-/// * Deleting a 'node_modules' directory fails
-/// * Recursing into subdirectories encounters any issues
+/// * `Vec<PathBuf>` - The 'node_modules' directories, sorted.
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use std::path::Path;
-/// use khadim-rs::reset::delete_node_modules;
+/// use khadim_rs::reset::find_node_modules;
 ///
-/// let project_dir = Path::new("/path/to/project");
-/// let result = delete_node_modules(project_dir);
-/// assert!(result.is_ok());
+/// let project_dir = Path::new("/path/to/project/apps/my-app");
+/// for dir in find_node_modules(project_dir) {
+///     println!("{}", dir.display());
+/// }
 /// ```
-pub fn delete_node_modules(dir: &Path) -> Result<(), BoxError> {
-    let node_modules = dir.join("node_modules");
-    if node_modules.exists() {
-        fs::remove_dir_all(&node_modules)?;
-        println!("✅ Deleted node_modules in {} 🗑️", dir.display());
-    }
-
-    // Recursively delete node_modules in subdirectories
-    if dir.is_dir() {
-        dir.read_dir()?
-            .par_bridge()
-            .try_for_each(|entry| -> Result<(), BoxError> {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() && path.file_name() != Some(std::ffi::OsStr::new("node_modules")) {
-                    delete_node_modules(&path)?;
-                }
-                Ok(())
-            })?;
+pub fn find_node_modules(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Cannot read {}: {}", dir.display(), e);
+            return found;
+        }
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        // The file type of an entry does not follow symbolic links
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if entry.file_name() == "node_modules" && (file_type.is_dir() || file_type.is_symlink()) {
+            found.push(entry.path());
+        } else if file_type.is_dir() {
+            found.extend(find_node_modules(&entry.path()));
+        }
+    }
+
+    found.sort();
+    found
+}
+
+/// Performs the actions of a reset plan: deletes the package.json first, then
+/// the directories in parallel, then runs the commands. In dry-run mode, they
+/// are only planned.
+///
+/// A directory that cannot be deleted does not stop the others from being
+/// deleted, but the reset then fails before running the commands.
+fn perform_planned(workspace: &Workspace, plan: &Plan) -> Result<(), KhadimError> {
+    let actions: Vec<&Action> = plan
+        .actions()
+        .iter()
+        .map(|planned| &planned.action)
+        .collect();
+    let dirs: Vec<&Action> = actions
+        .iter()
+        .copied()
+        .filter(|action| matches!(action, Action::DeleteDir { .. }))
+        .collect();
+
+    for action in &actions {
+        if let Action::DeleteFile { path } = action {
+            delete(action, path)?;
+        }
+    }
+    delete_in_parallel(workspace, &dirs)?;
+    for action in &actions {
+        if let Action::RunCommand { program, args, cwd } = action {
+            run(action, &format!("{} {}", program, args.join(" ")), cwd)?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes directories in parallel, with the project of each as the log prefix.
+///
+/// Every directory is attempted; the ones that could not be deleted are
+/// listed in the returned error.
+fn delete_in_parallel(workspace: &Workspace, dirs: &[&Action]) -> Result<(), KhadimError> {
+    if dirs.is_empty() {
+        return Ok(());
     }
 
+    let max_workers = workspace.config.max_workers();
+    info!(
+        "🚀 Deleting {} directories in parallel (max {} workers)",
+        dirs.len(),
+        max_workers
+    );
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(max_workers)
+        .build()
+        .map_err(KhadimError::from)?;
+
+    let completed_count = Arc::new(AtomicUsize::new(0));
+    let total = dirs.len();
+    let failed = Mutex::new(Vec::new());
+
+    pool.install(|| {
+        dirs.par_iter().for_each(|action| {
+            let Action::DeleteDir { path } = action else {
+                return;
+            };
+            let project = workspace
+                .projects
+                .iter()
+                .find(|project| path.starts_with(&project.path));
+            let result = match project {
+                Some(project) => logger::in_project(&project.name, || delete(action, path)),
+                None => delete(action, path),
+            };
+            if let Err(e) = result {
+                error!("Error deleting {}: {}", path.display(), e);
+                failed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(path.display().to_string());
+            }
+            let completed = completed_count.fetch_add(1, Ordering::SeqCst) + 1;
+            info!("Progress: {}/{} directories deleted", completed, total);
+        });
+    });
+
+    let mut failed = failed.into_inner().unwrap_or_else(|e| e.into_inner());
+    if failed.is_empty() {
+        return Ok(());
+    }
+    failed.sort();
+    Err(format!("❌ Cannot delete {} 😢", failed.join(", ")).into())
+}
+
+/// Deletes a file or directory as a step named after it, e.g. `delete node_modules`.
+fn delete(action: &Action, path: &Path) -> Result<(), KhadimError> {
+    if plan::is_dry_run() {
+        plan::perform(action.clone())?;
+        return Ok(());
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path.parent().unwrap_or(path);
+    let step = Step::start(&format!("delete {}", name), None, Some(dir));
+    let result = plan::execute(action);
+    step.finish(&result);
+    result?;
+    info!("✅ Deleted {} in {} 🗑️", name, dir.display());
     Ok(())
 }
+
+/// Runs a command such as `npm cache clean --force` as a step.
+fn run(action: &Action, command: &str, cwd: &Path) -> Result<(), KhadimError> {
+    if plan::is_dry_run() {
+        plan::perform(action.clone())?;
+        return Ok(());
+    }
+
+    info!("🧹 Running {} in {}...", command, cwd.display());
+    let step = Step::start(command, None, Some(cwd));
+    let result = plan::execute(action).and_then(|output| match output {
+        Some(output) if !output.status.success() => Err(KhadimError::subprocess(
+            command,
+            cwd,
+            output.status.code(),
+            &output.stderr,
+        )),
+        _ => Ok(()),
+    });
+    step.finish(&result);
+    result?;
+    info!("✅ {} completed successfully 🎉", command);
+    Ok(())
+}
+
+/// Returns an error listing the package.json and package-tmpl.json files
+/// within the scope that have uncommitted changes, as a reset would lose them.
+///
+/// Outside of a git repository, or without git, the check is skipped with a warning.
+fn check_manifests_committed(
+    workspace: &Workspace,
+    projects: &[&Project],
+    scope: &ResetScope,
+) -> Result<(), KhadimError> {
+    let mut pathspecs = Vec::new();
+    if matches!(scope, ResetScope::All | ResetScope::Root) {
+        pathspecs.push(PACKAGE_JSON.to_string());
+        pathspecs.push(PACKAGE_TMPL_JSON.to_string());
+    }
+    pathspecs.extend(projects.iter().map(|project| {
+        project
+            .relative_dir()
+            .join(PACKAGE_JSON)
+            .display()
+            .to_string()
+    }));
+    // Without a pathspec, git would report the whole repository
+    if pathspecs.is_empty() {
+        return Ok(());
+    }
+
+    // An untracked manifest has no committed version that a reset could lose
+    let mut args = vec!["status", "--porcelain", "--untracked-files=no", "--"];
+    args.extend(pathspecs.iter().map(String::as_str));
+    let changed = match run_git(&workspace.root_dir, &args) {
+        Ok(lines) => lines,
+        Err(e) => {
+            output::warn(&format!(
+                "Cannot check manifests for uncommitted changes: {}",
+                e
+            ));
+            return Ok(());
+        }
+    };
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    // Lines look like ` M apps/web/package.json`
+    let files: Vec<&str> = changed
+        .iter()
+        .map(|line| line.get(3..).unwrap_or(line))
+        .collect();
+    Err(format!(
+        "❌ Uncommitted changes to {}, commit or stash them first, or reset with --force 😢",
+        files.join(", ")
+    )
+    .into())
+}
+
+/// Returns `true` if a path, with symbolic links resolved, is inside the root directory.
+fn is_inside(root_dir: &Path, path: &Path) -> Result<bool, KhadimError> {
+    let root_dir = root_dir
+        .canonicalize()
+        .map_err(|e| KhadimError::io(root_dir, e))?;
+    Ok(path
+        .canonicalize()
+        .map(|path| path.starts_with(&root_dir))
+        .unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    /// Creates a workspace with the `web` and `admin` apps and the `ui` lib.
+    fn workspace() -> (tempfile::TempDir, Workspace) {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(PACKAGE_TMPL_JSON), r#"{ "name": "root" }"#).unwrap();
+        for (dir, name) in [
+            ("apps/web", "web"),
+            ("apps/admin", "admin"),
+            ("libs/ui", "ui"),
+        ] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+            fs::write(
+                root.path().join(dir).join(PACKAGE_JSON),
+                format!(r#"{{ "name": "{}" }}"#, name),
+            )
+            .unwrap();
+        }
+        let workspace = Workspace::discover(root.path()).unwrap();
+        (root, workspace)
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {}", args.join(" "));
+    }
+
+    /// Checks the manifests of the projects in the scope.
+    fn check(workspace: &Workspace, scope: ResetScope) -> Result<(), KhadimError> {
+        let projects = scoped_projects(workspace, &scope).unwrap();
+        check_manifests_committed(workspace, &projects, &scope)
+    }
+
+    #[test]
+    fn only_checks_the_manifests_in_scope() {
+        let (root, workspace) = workspace();
+        git(root.path(), &["init", "-q"]);
+        git(root.path(), &["add", "."]);
+        git(root.path(), &["commit", "-q", "-m", "init"]);
+        assert!(check(&workspace, ResetScope::All).is_ok());
+
+        fs::write(
+            root.path().join("apps/admin/package.json"),
+            r#"{ "name": "admin", "version": "2.0.0" }"#,
+        )
+        .unwrap();
+        let error = check(&workspace, ResetScope::All).unwrap_err();
+        assert!(error.to_string().contains("apps/admin/package.json"));
+        assert!(check(&workspace, ResetScope::Apps).is_err());
+        assert!(check(&workspace, ResetScope::Projects("web".to_string())).is_ok());
+        assert!(check(&workspace, ResetScope::Libs).is_ok());
+        assert!(check(&workspace, ResetScope::Root).is_ok());
+
+        fs::write(
+            root.path().join(PACKAGE_TMPL_JSON),
+            r#"{ "name": "renamed" }"#,
+        )
+        .unwrap();
+        assert!(check(&workspace, ResetScope::Root).is_err());
+        assert!(check(&workspace, ResetScope::Libs).is_ok());
+    }
+
+    #[test]
+    fn ignores_untracked_manifests() {
+        let (root, workspace) = workspace();
+        git(root.path(), &["init", "-q"]);
+        git(root.path(), &["add", PACKAGE_TMPL_JSON]);
+        git(root.path(), &["commit", "-q", "-m", "init"]);
+        // The generated root package.json is usually untracked too
+        fs::write(root.path().join(PACKAGE_JSON), "{}").unwrap();
+
+        assert!(check(&workspace, ResetScope::All).is_ok());
+    }
+
+    #[test]
+    fn skips_the_check_outside_of_git() {
+        let (_root, workspace) = workspace();
+        assert!(check(&workspace, ResetScope::All).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_projects_linked_from_outside() {
+        let (root, _) = workspace();
+        let outside = tempfile::tempdir().unwrap();
+        fs::create_dir_all(outside.path().join("node_modules/react")).unwrap();
+        fs::write(outside.path().join(PACKAGE_JSON), r#"{ "name": "linked" }"#).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("apps/linked")).unwrap();
+        fs::create_dir_all(root.path().join("apps/web/node_modules")).unwrap();
+        let workspace = Workspace::discover(root.path()).unwrap();
+
+        let linked = workspace.select("linked").unwrap();
+        assert!(!is_inside(root.path(), &linked[0].path).unwrap());
+        assert!(is_inside(root.path(), &root.path().join("apps/web")).unwrap());
+        assert!(!is_inside(root.path(), &root.path().join("apps/missing")).unwrap());
+
+        let plan = plan_reset(&workspace, &linked, &ResetOptions::default()).unwrap();
+        assert!(plan.is_empty());
+        let plan = plan_reset(
+            &workspace,
+            &workspace.projects.iter().collect::<Vec<_>>(),
+            &ResetOptions::default(),
+        )
+        .unwrap();
+        let paths: Vec<&Path> = plan
+            .actions()
+            .iter()
+            .filter_map(|planned| match &planned.action {
+                Action::DeleteDir { path } => Some(path.as_path()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(paths, [root.path().join("apps/web/node_modules")]);
+    }
+
+    /// Creates directories relative to the root.
+    fn mkdirs(root: &Path, dirs: &[&str]) {
+        for dir in dirs {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+    }
+
+    /// Returns the directories that a plan deletes, relative to the root.
+    fn planned_dirs(root: &Path, plan: &Plan) -> Vec<String> {
+        let root = root.canonicalize().unwrap();
+        plan.actions()
+            .iter()
+            .filter_map(|planned| match &planned.action {
+                Action::DeleteDir { path } => Some(path),
+                _ => None,
+            })
+            .map(|path| {
+                let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                path.strip_prefix(&root).unwrap().display().to_string()
+            })
+            .collect()
+    }
+
+    fn plan_scope(workspace: &Workspace, options: &ResetOptions) -> Vec<String> {
+        let projects = scoped_projects(workspace, &options.scope).unwrap();
+        let plan = plan_reset(workspace, &projects, options).unwrap();
+        planned_dirs(&workspace.root_dir, &plan)
+    }
+
+    #[test]
+    fn plans_the_directories_of_each_scope() {
+        let (root, workspace) = workspace();
+        fs::write(root.path().join(PACKAGE_JSON), "{}").unwrap();
+        mkdirs(
+            root.path(),
+            &[
+                "node_modules",
+                "apps/web/node_modules",
+                "apps/web/packages/editor/node_modules",
+                "apps/admin/node_modules",
+                "libs/ui/node_modules",
+            ],
+        );
+        let scope = |scope| ResetOptions {
+            scope,
+            ..ResetOptions::default()
+        };
+
+        assert_eq!(
+            plan_scope(&workspace, &scope(ResetScope::All)),
+            [
+                "node_modules",
+                "libs/ui/node_modules",
+                "apps/admin/node_modules",
+                "apps/web/node_modules",
+                "apps/web/packages/editor/node_modules",
+            ]
+        );
+        assert_eq!(
+            plan_scope(&workspace, &scope(ResetScope::Root)),
+            ["node_modules"]
+        );
+        assert_eq!(
+            plan_scope(&workspace, &scope(ResetScope::Apps)),
+            [
+                "apps/admin/node_modules",
+                "apps/web/node_modules",
+                "apps/web/packages/editor/node_modules",
+            ]
+        );
+        assert_eq!(
+            plan_scope(&workspace, &scope(ResetScope::Libs)),
+            ["libs/ui/node_modules"]
+        );
+        assert_eq!(
+            plan_scope(&workspace, &scope(ResetScope::Projects("ad*".to_string()))),
+            ["apps/admin/node_modules"]
+        );
+
+        // Only the scopes that cover the root delete its package.json
+        let projects = scoped_projects(&workspace, &ResetScope::Root).unwrap();
+        let plan = plan_reset(&workspace, &projects, &scope(ResetScope::Root)).unwrap();
+        assert!(matches!(
+            &plan.actions()[0].action,
+            Action::DeleteFile { path } if path.ends_with(PACKAGE_JSON)
+        ));
+        let projects = scoped_projects(&workspace, &ResetScope::Libs).unwrap();
+        let plan = plan_reset(&workspace, &projects, &scope(ResetScope::Libs)).unwrap();
+        assert!(plan
+            .actions()
+            .iter()
+            .all(|planned| matches!(planned.action, Action::DeleteDir { .. })));
+    }
+
+    #[test]
+    fn plans_build_outputs_and_venvs_when_asked_for() {
+        let (root, _) = workspace();
+        fs::write(
+            root.path().join("apps/web/package.json"),
+            r#"{ "name": "web", "project": { "outputs": ["build/static", "dist"] } }"#,
+        )
+        .unwrap();
+        mkdirs(
+            root.path(),
+            &[
+                "apps/web/.next",
+                "apps/web/dist",
+                "apps/web/build/static",
+                "apps/web/src",
+                "apps/admin/venv",
+                "apps/admin/scripts/.venv",
+                "apps/admin/not-a-venv",
+                "libs/ui/out",
+            ],
+        );
+        fs::write(root.path().join("apps/admin/venv/pyvenv.cfg"), "").unwrap();
+        fs::write(root.path().join("apps/admin/scripts/.venv/pyvenv.cfg"), "").unwrap();
+        // A file named like an output is not a build output
+        fs::write(root.path().join("apps/admin/out"), "").unwrap();
+        let workspace = Workspace::discover(root.path()).unwrap();
+
+        let apps = ResetOptions {
+            scope: ResetScope::Apps,
+            ..ResetOptions::default()
+        };
+        assert!(plan_scope(&workspace, &apps).is_empty());
+        assert_eq!(
+            plan_scope(
+                &workspace,
+                &ResetOptions {
+                    build_outputs: true,
+                    ..apps.clone()
+                }
+            ),
+            ["apps/web/.next", "apps/web/build/static", "apps/web/dist"]
+        );
+        assert_eq!(
+            plan_scope(
+                &workspace,
+                &ResetOptions {
+                    venvs: true,
+                    ..apps.clone()
+                }
+            ),
+            ["apps/admin/scripts/.venv", "apps/admin/venv"]
+        );
+        assert_eq!(
+            plan_scope(
+                &workspace,
+                &ResetOptions {
+                    scope: ResetScope::Libs,
+                    build_outputs: true,
+                    venvs: true,
+                    ..ResetOptions::default()
+                }
+            ),
+            ["libs/ui/out"]
+        );
+    }
+
+    #[test]
+    fn finds_venvs_by_their_marker() {
+        let root = tempfile::tempdir().unwrap();
+        mkdirs(
+            root.path(),
+            &[
+                "env/lib",
+                "node_modules/pkg/venv",
+                "tools/py/.venv/lib",
+                "src",
+            ],
+        );
+        fs::write(root.path().join("env/pyvenv.cfg"), "").unwrap();
+        fs::write(root.path().join("node_modules/pkg/venv/pyvenv.cfg"), "").unwrap();
+        fs::write(root.path().join("tools/py/.venv/pyvenv.cfg"), "").unwrap();
+
+        assert_eq!(
+            find_venvs(root.path()),
+            [root.path().join("env"), root.path().join("tools/py/.venv")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leaves_out_linked_build_outputs() {
+        let (root, _) = workspace();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("apps/web/dist")).unwrap();
+        mkdirs(root.path(), &["apps/web/.next"]);
+        let workspace = Workspace::discover(root.path()).unwrap();
+
+        let web = &workspace.select("web").unwrap()[0];
+        assert_eq!(build_outputs(web), [root.path().join("apps/web/.next")]);
+    }
+
+    #[test]
+    fn deletes_the_other_directories_when_one_fails() {
+        let (root, workspace) = workspace();
+        mkdirs(
+            root.path(),
+            &["apps/web/node_modules", "libs/ui/node_modules"],
+        );
+        let actions: Vec<Action> = [
+            "apps/web/node_modules",
+            "apps/admin/node_modules",
+            "libs/ui/node_modules",
+        ]
+        .iter()
+        .map(|dir| Action::DeleteDir {
+            path: root.path().join(dir),
+        })
+        .collect();
+
+        let error =
+            delete_in_parallel(&workspace, &actions.iter().collect::<Vec<_>>()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "❌ Cannot delete {} 😢",
+                root.path().join("apps/admin/node_modules").display()
+            )
+        );
+        assert!(!root.path().join("apps/web/node_modules").exists());
+        assert!(!root.path().join("libs/ui/node_modules").exists());
+    }
+}
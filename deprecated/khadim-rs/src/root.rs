@@ -0,0 +1,236 @@
+use crate::config::{CONFIG_FILE, PACKAGE_TMPL_JSON};
+use crate::error::KhadimError;
+use log::debug;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// The environment variable that sets the root directory, like `--root`.
+pub const ROOT_ENV: &str = "KHADIM_ROOT";
+
+/// The environment variable that replaces the markers searched for, as a
+/// comma-separated list such as `package-tmpl.json,.git`.
+pub const ROOT_MARKER_ENV: &str = "KHADIM_ROOT_MARKER";
+
+/// The files or directories that mark the root directory.
+pub const DEFAULT_ROOT_MARKERS: [&str; 3] = [CONFIG_FILE, PACKAGE_TMPL_JSON, ".git"];
+
+/// The root directory given with `--root`, which takes precedence over everything else.
+static ROOT_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Sets the root directory for the rest of the process, as given with `--root`.
+pub fn set_root_dir(root_dir: &Path) {
+    if let Ok(mut root_override) = ROOT_OVERRIDE.write() {
+        *root_override = Some(root_dir.to_path_buf());
+    }
+}
+
+/// Finds the root directory of the monorepo.
+///
+/// The root directory is, in order:
+/// 1. The directory given with `--root` (see `set_root_dir`)
+/// 2. The directory in the `KHADIM_ROOT` environment variable
+/// 3. The closest directory, starting from the current directory and going
+///    up, that contains any marker: khadim.json, package-tmpl.json or `.git`,
+///    unless `KHADIM_ROOT_MARKER` lists other ones.
+///
+/// Relative paths are resolved against the current directory, so every
+/// command works from any subdirectory of the monorepo.
+///
+/// # Returns
+///
+/// * `Result<PathBuf, KhadimError>` - The absolute path of the root directory.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The current directory cannot be determined
+/// * The directory given with `--root` or `KHADIM_ROOT` does not exist
+/// * No marker is found in the current directory or its ancestors
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::root::find_root_dir;
+///
+/// let root_dir = find_root_dir().unwrap();
+/// println!("Root directory: {}", root_dir.display());
+/// ```
+pub fn find_root_dir() -> Result<PathBuf, KhadimError> {
+    find_root_in(&env::current_dir()?)
+}
+
+/// Finds the root directory as `find_root_dir` does, from the given directory.
+fn find_root_in(current_dir: &Path) -> Result<PathBuf, KhadimError> {
+    let root_override = ROOT_OVERRIDE.read().ok().and_then(|root| root.clone());
+    let explicit = root_override.map(|root| (root, "--root")).or_else(|| {
+        env::var_os(ROOT_ENV)
+            .filter(|root| !root.is_empty())
+            .map(|root| (PathBuf::from(root), ROOT_ENV))
+    });
+    if let Some((root_dir, source)) = explicit {
+        let root_dir = current_dir.join(root_dir);
+        if !root_dir.is_dir() {
+            return Err(KhadimError::RootDirMissing {
+                path: root_dir,
+                given_with: source.to_string(),
+            });
+        }
+        debug!(
+            "Using root directory {} from {}",
+            root_dir.display(),
+            source
+        );
+        return Ok(root_dir);
+    }
+
+    let markers = root_markers();
+    match find_root_from(current_dir, &markers) {
+        Some(root_dir) => {
+            debug!("Found root directory {}", root_dir.display());
+            Ok(root_dir)
+        }
+        None => Err(KhadimError::RootNotFound {
+            start: current_dir.to_path_buf(),
+            markers,
+        }),
+    }
+}
+
+/// Returns the closest of `start` and its ancestors that contains any of the
+/// markers.
+///
+/// The closest directory wins over the order of the markers, so a
+/// package-tmpl.json in the repository is found before a `.git` further up.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::root::find_root_from;
+/// use std::path::Path;
+///
+/// let markers = vec!["package-tmpl.json".to_string()];
+/// let root_dir = find_root_from(Path::new("/repo/apps/organic-lever-web/src"), &markers);
+/// assert_eq!(root_dir.as_deref(), Some(Path::new("/repo")));
+/// ```
+pub fn find_root_from(start: &Path, markers: &[String]) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| markers.iter().any(|marker| dir.join(marker).exists()))
+        .map(Path::to_path_buf)
+}
+
+/// Returns the markers from `KHADIM_ROOT_MARKER`, or the default markers.
+fn root_markers() -> Vec<String> {
+    let markers: Vec<String> = env::var(ROOT_MARKER_ENV)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|marker| !marker.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if markers.is_empty() {
+        DEFAULT_ROOT_MARKERS.iter().map(|m| m.to_string()).collect()
+    } else {
+        markers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Mutex;
+
+    /// Serializes the tests that set `--root` and the environment variables.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn markers(markers: &[&str]) -> Vec<String> {
+        markers.iter().map(|marker| marker.to_string()).collect()
+    }
+
+    /// Creates `/repo` with a `.git`, holding `/repo/monorepo` with a
+    /// package-tmpl.json, and returns the temporary directory.
+    fn nested_repos() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("repo/.git")).unwrap();
+        fs::create_dir_all(dir.path().join("repo/monorepo/apps/web/src")).unwrap();
+        fs::write(dir.path().join("repo/monorepo/package-tmpl.json"), "{}").unwrap();
+        dir
+    }
+
+    #[test]
+    fn finds_the_closest_directory_with_any_marker() {
+        let dir = nested_repos();
+        let repo = dir.path().join("repo");
+        let src = repo.join("monorepo/apps/web/src");
+
+        let markers = markers(&[".git", PACKAGE_TMPL_JSON]);
+        assert_eq!(find_root_from(&src, &markers), Some(repo.join("monorepo")));
+        assert_eq!(find_root_from(&repo, &markers), Some(repo.clone()));
+        assert_eq!(
+            find_root_from(&src, &markers[..1]),
+            Some(repo.clone()),
+            "only .git"
+        );
+        assert_eq!(find_root_from(dir.path(), &markers), None);
+    }
+
+    #[test]
+    fn prefers_root_then_env_then_markers() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = nested_repos();
+        let src = dir.path().join("repo/monorepo/apps/web/src");
+        let reset = || {
+            *ROOT_OVERRIDE.write().unwrap() = None;
+            env::remove_var(ROOT_ENV);
+            env::remove_var(ROOT_MARKER_ENV);
+        };
+        reset();
+
+        assert_eq!(
+            find_root_in(&src).unwrap(),
+            dir.path().join("repo/monorepo")
+        );
+
+        env::set_var(ROOT_MARKER_ENV, ".git");
+        assert_eq!(find_root_in(&src).unwrap(), dir.path().join("repo"));
+
+        // Relative to the current directory
+        env::set_var(ROOT_ENV, "../..");
+        assert_eq!(
+            find_root_in(&src).unwrap(),
+            dir.path().join("repo/monorepo/apps/web/src/../..")
+        );
+
+        set_root_dir(&dir.path().join("repo/monorepo/apps"));
+        assert_eq!(
+            find_root_in(&src).unwrap(),
+            dir.path().join("repo/monorepo/apps")
+        );
+
+        set_root_dir(Path::new("missing"));
+        let error = find_root_in(&src).unwrap_err();
+        assert!(matches!(
+            &error,
+            KhadimError::RootDirMissing { path, given_with }
+                if path == &src.join("missing") && given_with == "--root"
+        ));
+        assert_eq!(error.exit_code(), 3);
+
+        reset();
+        env::set_var(ROOT_ENV, dir.path().join("missing"));
+        assert!(matches!(
+            find_root_in(&src),
+            Err(KhadimError::RootDirMissing { given_with, .. }) if given_with == ROOT_ENV
+        ));
+
+        reset();
+        env::set_var(ROOT_MARKER_ENV, "khadim.json");
+        let error = find_root_in(&src).unwrap_err();
+        assert!(matches!(error, KhadimError::RootNotFound { .. }));
+        assert_eq!(error.exit_code(), 3);
+        reset();
+    }
+}
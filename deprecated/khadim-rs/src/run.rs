@@ -0,0 +1,898 @@
+use crate::affected::{affected_projects, changed_files, ChangeSource};
+use crate::cache::{CacheEntry, TaskCache};
+use crate::config::PACKAGE_LOCK_JSON;
+use crate::error::KhadimError;
+use crate::graph::DependencyGraph;
+use crate::logger;
+use crate::output::{self, Step};
+use crate::root::find_root_dir;
+use crate::workspace::{Project, Workspace};
+use log::{debug, error, info};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The outcome of running a script in one project during `run_all`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The script exited with code 0.
+    Success,
+    /// The script exited with a non-zero code or could not be started.
+    Failed,
+    /// The script was not run because one of the project's dependencies failed.
+    Skipped,
+    /// The script was not run because an earlier successful run was replayed from the cache.
+    Cached,
+}
+
+impl RunStatus {
+    /// Returns `true` if the script succeeded, either by running or from the cache.
+    pub fn is_success(&self) -> bool {
+        matches!(self, RunStatus::Success | RunStatus::Cached)
+    }
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            RunStatus::Success => "success",
+            RunStatus::Failed => "failed",
+            RunStatus::Skipped => "skipped",
+            RunStatus::Cached => "cached",
+        };
+        f.pad(label)
+    }
+}
+
+/// The result of running a script in a single project.
+#[derive(Debug, Clone)]
+pub struct ProjectRunResult {
+    pub project: String,
+    pub status: RunStatus,
+    pub duration: Duration,
+    /// The exit code of the script, or `None` if it was skipped or could not be started.
+    pub exit_code: Option<i32>,
+}
+
+/// Runs an npm script of a single workspace project, streaming its output.
+///
+/// This function performs the following steps:
+/// 1. Finds the root directory and discovers the workspace
+/// 2. Looks up the project under the apps or libs directory
+/// 3. Checks that the script is defined in the project's package.json
+/// 4. Runs `npm run <script>` in the project directory with inherited stdout/stderr
+///
+/// # Arguments
+///
+/// * `project_name` - The project's directory name or npm package name.
+/// * `script` - The name of the script to run.
+/// * `args` - Extra arguments forwarded to the script after `--`.
+///
+/// # Returns
+///
+/// * `Result<i32, KhadimError>` - The exit code of the script, or an error if the
+///   script could not be started.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * The project does not exist
+/// * The project does not define the script
+/// * npm cannot be executed
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::run::run_script;
+///
+/// let exit_code = run_script("organic-lever-web", "test", &[]).unwrap();
+/// std::process::exit(exit_code);
+/// ```
+pub fn run_script(project_name: &str, script: &str, args: &[&str]) -> Result<i32, KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let project = find_project(&workspace, project_name)?;
+
+    ensure_script_exists(project, script)?;
+
+    info!(
+        "🚀 Running npm script '{}' in {}...",
+        script,
+        project.relative_dir().display()
+    );
+    let step = Step::start(
+        &format!("run {}", script),
+        Some(&project.name),
+        Some(&project.path),
+    );
+    let exit_code = match run_npm_script(project, script, args) {
+        Ok(exit_code) => {
+            step.finish_with_exit_code(exit_code);
+            exit_code
+        }
+        Err(e) => {
+            step.finish_with("failed", None, Some(&e));
+            return Err(e);
+        }
+    };
+
+    if exit_code == 0 {
+        info!(
+            "✅ Finished running npm script '{}' in {} 🎉",
+            script, project.name
+        );
+    } else {
+        error!(
+            "❌ npm script '{}' failed in {} with exit code {} 😢",
+            script, project.name, exit_code
+        );
+    }
+    Ok(exit_code)
+}
+
+/// Finds a project by name, listing the available projects if it does not exist.
+pub fn find_project<'a>(workspace: &'a Workspace, name: &str) -> Result<&'a Project, KhadimError> {
+    workspace
+        .find(name)
+        .ok_or_else(|| workspace.not_found(name))
+}
+
+/// Returns an error listing the project's scripts if `script` is not one of them.
+fn ensure_script_exists(project: &Project, script: &str) -> Result<(), KhadimError> {
+    if project.manifest.scripts.contains_key(script) {
+        return Ok(());
+    }
+
+    let available: Vec<&str> = project
+        .manifest
+        .scripts
+        .keys()
+        .map(String::as_str)
+        .collect();
+    Err(format!(
+        "❌ Script '{}' not found in {}. Available scripts: {} 😢",
+        script,
+        project.name,
+        available.join(", ")
+    )
+    .into())
+}
+
+/// Runs `npm run <script>` in the project directory and waits for it to finish.
+///
+/// Unlike `run_npm_install`, the child's stdout and stderr are inherited, so
+/// its output is streamed live instead of being buffered. In JSON mode the
+/// child's stdout goes to stderr, so that stdout only carries events.
+///
+/// # Returns
+///
+/// * `Result<i32, KhadimError>` - The exit code of npm. A process terminated by a
+///   signal is reported as exit code 1.
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::run::run_npm_script;
+/// use khadim_rs::workspace::Workspace;
+/// use std::path::Path;
+///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+/// let project = workspace.find("hello").unwrap();
+/// let exit_code = run_npm_script(project, "build", &[]).unwrap();
+/// assert_eq!(exit_code, 0);
+/// ```
+pub fn run_npm_script(project: &Project, script: &str, args: &[&str]) -> Result<i32, KhadimError> {
+    let mut command = Command::new("npm");
+    command.args(["run", script]).current_dir(&project.path);
+    if !args.is_empty() {
+        command.arg("--").args(args);
+    }
+    if output::is_json() {
+        command.stdout(io::stderr());
+    }
+
+    debug!("Running `npm run {}` in {}", script, project.path.display());
+    let status = command.status().map_err(|e| KhadimError::spawn("npm", e))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Options for `run_all`.
+#[derive(Debug, Clone, Default)]
+pub struct RunAllOptions {
+    /// If set, only projects affected by these changes are run.
+    pub changes: Option<ChangeSource>,
+    /// If set, successful runs are cached in this directory, relative to the
+    /// root directory unless absolute, and replayed when nothing changed.
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Runs an npm script in every project that defines it, in dependency order.
+///
+/// Projects are processed level by level using the workspace dependency graph,
+/// so a lib's script finishes before the script of any project depending on it
+/// starts. Independent projects within a level run in parallel. If a project
+/// fails, the projects depending on it (directly or transitively) are skipped.
+///
+/// When a cache directory is configured, a project whose inputs did not change
+/// since its last successful run is not executed again: its log is replayed
+/// and its declared output directories are restored from the cache.
+///
+/// A summary table is printed once all projects have finished.
+///
+/// # Arguments
+///
+/// * `script` - The name of the script to run, e.g. `build` or `test`.
+/// * `options` - Filters and caching options.
+///
+/// # Returns
+///
+/// * `Result<Vec<ProjectRunResult>, KhadimError>` - One result per project that
+///   defines the script, in the order the projects were run.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * The workspace cannot be discovered or contains a dependency cycle
+/// * The changed files cannot be determined with git
+/// * The thread pool cannot be created
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::affected::ChangeSource;
+/// use khadim_rs::run::{run_all, RunAllOptions};
+/// use std::path::PathBuf;
+///
+/// let options = RunAllOptions {
+///     changes: Some(ChangeSource::Base("origin/main".to_string())),
+///     cache_dir: Some(PathBuf::from(".khadim-cache")),
+/// };
+/// let results = run_all("test", &options).unwrap();
+/// let failed = results.iter().any(|result| !result.status.is_success());
+/// std::process::exit(if failed { 1 } else { 0 });
+/// ```
+pub fn run_all(
+    script: &str,
+    options: &RunAllOptions,
+) -> Result<Vec<ProjectRunResult>, KhadimError> {
+    run_all_in(&find_root_dir()?, script, options)
+}
+
+/// Runs a script in every project of the workspace at `root_dir`, as `run_all` does.
+fn run_all_in(
+    root_dir: &Path,
+    script: &str,
+    options: &RunAllOptions,
+) -> Result<Vec<ProjectRunResult>, KhadimError> {
+    let workspace = Workspace::discover(root_dir)?;
+    let graph = DependencyGraph::build(&workspace)?;
+
+    let affected: Option<HashSet<PathBuf>> = match &options.changes {
+        Some(source) => {
+            let changed = changed_files(root_dir, source)?;
+            let affected = affected_projects(&graph, &changed);
+            info!(
+                "🔍 {} changed files affect {} projects",
+                changed.len(),
+                affected.len()
+            );
+            Some(affected.into_iter().map(|p| p.path.clone()).collect())
+        }
+        None => None,
+    };
+    let cache = options
+        .cache_dir
+        .as_ref()
+        .map(|dir| TaskCache::new(&root_dir.join(dir)));
+    let lockfile = cache.as_ref().and_then(|_| read_lockfile(root_dir));
+
+    let max_workers = workspace.config.max_workers();
+    info!(
+        "🚀 Running npm script '{}' in all projects (max {} workers)",
+        script, max_workers
+    );
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(max_workers)
+        .build()
+        .map_err(KhadimError::from)?;
+
+    let mut results = Vec::new();
+    // Projects whose script failed or was skipped, including projects without
+    // the script that sit between a failed project and its dependents
+    let mut blocked: HashSet<PathBuf> = HashSet::new();
+    // Content hashes of the projects, computed once for the cache keys of all their dependents
+    let mut content_hashes: HashMap<PathBuf, String> = HashMap::new();
+
+    for level in graph.levels() {
+        let mut runnable = Vec::new();
+        for project in level {
+            let dependencies = graph.dependencies_of(project);
+            let is_blocked = dependencies
+                .iter()
+                .any(|dependency| blocked.contains(&dependency.path));
+            let is_selected = affected
+                .as_ref()
+                .is_none_or(|affected| affected.contains(&project.path));
+            let has_script = is_selected && project.manifest.scripts.contains_key(script);
+
+            if is_blocked {
+                blocked.insert(project.path.clone());
+                if has_script {
+                    info!(
+                        "⏭️ Skipping '{}' in {} because a dependency failed",
+                        script, project.name
+                    );
+                    Step::start(
+                        &format!("run {}", script),
+                        Some(&project.name),
+                        Some(&project.path),
+                    )
+                    .finish_with("skipped", None, None);
+                    results.push(ProjectRunResult {
+                        project: project.name.clone(),
+                        status: RunStatus::Skipped,
+                        duration: Duration::ZERO,
+                        exit_code: None,
+                    });
+                }
+            } else if has_script {
+                let cache_key = cache.as_ref().and_then(|_| {
+                    match cache_key(
+                        &graph,
+                        project,
+                        script,
+                        lockfile.as_ref(),
+                        &mut content_hashes,
+                    ) {
+                        Ok(key) => Some(key),
+                        Err(e) => {
+                            output::warn(&format!(
+                                "Cannot compute cache key for {}, running without cache: {}",
+                                project.name, e
+                            ));
+                            None
+                        }
+                    }
+                });
+                runnable.push((project, cache_key));
+            }
+        }
+
+        let level_results: Vec<ProjectRunResult> = pool.install(|| {
+            runnable
+                .par_iter()
+                .map(|(project, cache_key)| {
+                    let cached = cache.as_ref().zip(cache_key.as_deref());
+                    logger::in_project(&project.name, || run_in_project(project, script, cached))
+                })
+                .collect()
+        });
+
+        for ((project, _), result) in runnable.iter().zip(level_results) {
+            if !result.status.is_success() {
+                blocked.insert(project.path.clone());
+            }
+            results.push(result);
+        }
+    }
+
+    print_summary(script, &results);
+    Ok(results)
+}
+
+/// Returns the exit code of `run-all`: `1` if a script failed or was skipped,
+/// `0` otherwise.
+pub fn exit_code(results: &[ProjectRunResult]) -> i32 {
+    if results.iter().all(|result| result.status.is_success()) {
+        0
+    } else {
+        1
+    }
+}
+
+/// Computes the cache key of running a script in a project, from the content
+/// of the project and of every project it depends on, directly or not, and
+/// from the packages locked for it in the root package-lock.json, if any.
+///
+/// Content hashes are remembered in `content_hashes`, so that a lib shared by
+/// several apps is hashed once.
+///
+/// # Errors
+///
+/// This function will return an error if the script is not defined or a
+/// project file cannot be read.
+pub fn cache_key(
+    graph: &DependencyGraph,
+    project: &Project,
+    script: &str,
+    lockfile: Option<&Value>,
+    content_hashes: &mut HashMap<PathBuf, String>,
+) -> Result<String, KhadimError> {
+    let mut hash_of = |project: &Project| -> Result<String, KhadimError> {
+        if let Some(hash) = content_hashes.get(&project.path) {
+            return Ok(hash.clone());
+        }
+        let hash = TaskCache::content_hash(project)?;
+        content_hashes.insert(project.path.clone(), hash.clone());
+        Ok(hash)
+    };
+
+    let content_hash = hash_of(project)?;
+    let dependency_hashes = graph
+        .transitive_dependencies_of(project)
+        .into_iter()
+        .map(&mut hash_of)
+        .collect::<Result<Vec<String>, KhadimError>>()?;
+    let locked_hash = lockfile
+        .map(|lockfile| TaskCache::locked_packages_hash(lockfile, project))
+        .unwrap_or_default();
+    TaskCache::key(
+        project,
+        script,
+        &content_hash,
+        &dependency_hashes,
+        &locked_hash,
+    )
+}
+
+/// Reads the root package-lock.json, or returns `None` if it is missing or invalid.
+fn read_lockfile(root_dir: &Path) -> Option<Value> {
+    let path = root_dir.join(PACKAGE_LOCK_JSON);
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(lockfile) => Some(lockfile),
+        Err(e) => {
+            output::warn(&format!(
+                "Ignoring {} for the cache keys, it is not valid JSON: {}",
+                path.display(),
+                e
+            ));
+            None
+        }
+    }
+}
+
+/// Runs the script in one project, timing it and capturing the outcome.
+///
+/// With a cache and the run's key, a hit replays the stored log instead of
+/// running the script, and a successful run is stored for next time.
+fn run_in_project(
+    project: &Project,
+    script: &str,
+    cached: Option<(&TaskCache, &str)>,
+) -> ProjectRunResult {
+    let started = Instant::now();
+    let step = Step::start(
+        &format!("run {}", script),
+        Some(&project.name),
+        Some(&project.path),
+    );
+
+    if let Some((cache, key)) = cached {
+        if let Some(exit_code) = replay_from_cache(cache, key, project, script) {
+            step.finish_with("cached", Some(exit_code), None);
+            let result = ProjectRunResult {
+                project: project.name.clone(),
+                status: RunStatus::Cached,
+                duration: started.elapsed(),
+                exit_code: Some(exit_code),
+            };
+            return result;
+        }
+    }
+
+    info!("🚀 Running npm script '{}' in {}...", script, project.name);
+    let (exit_code, log) = match run_npm_script_captured(project, script) {
+        Ok((exit_code, log)) => {
+            step.finish_with_exit_code(exit_code);
+            (Some(exit_code), log)
+        }
+        Err(e) => {
+            error!("{}", e);
+            step.finish_with("failed", None, Some(&e));
+            (None, Vec::new())
+        }
+    };
+    let duration = started.elapsed();
+
+    let status = if exit_code == Some(0) {
+        info!(
+            "✅ Finished running npm script '{}' in {} 🎉",
+            script, project.name
+        );
+        RunStatus::Success
+    } else {
+        error!("❌ npm script '{}' failed in {} 😢", script, project.name);
+        RunStatus::Failed
+    };
+
+    // Only successful runs are cached, so that failures are always retried
+    if let (Some((cache, key)), RunStatus::Success) = (cached, status) {
+        let entry = CacheEntry {
+            project: project.name.clone(),
+            script: script.to_string(),
+            command: project.manifest.scripts[script].clone(),
+            exit_code: 0,
+            outputs: Vec::new(),
+        };
+        if let Err(e) = cache.store(key, project, entry, &log) {
+            output::warn(&format!(
+                "Cannot cache '{}' in {}: {}",
+                script, project.name, e
+            ));
+        }
+    }
+
+    ProjectRunResult {
+        project: project.name.clone(),
+        status,
+        duration,
+        exit_code,
+    }
+}
+
+/// Replays a cached run: prints its log and restores its outputs.
+///
+/// Returns the cached exit code, or `None` on a cache miss or if the entry
+/// cannot be restored, in which case the script should be run.
+fn replay_from_cache(cache: &TaskCache, key: &str, project: &Project, script: &str) -> Option<i32> {
+    let entry = cache.lookup(key)?;
+    let restored = cache
+        .log(key)
+        .and_then(|log| cache.restore_outputs(key, project, &entry).map(|_| log));
+
+    match restored {
+        Ok(log) => {
+            info!(
+                "♻️ Cache hit for '{}' in {}, replaying output",
+                script, project.name
+            );
+            let prefix = logger::project_prefix();
+            for line in log.split_inclusive(|byte| *byte == b'\n') {
+                let _ = if output::is_json() {
+                    io::stderr().write_all(&[prefix.as_bytes(), line].concat())
+                } else {
+                    io::stdout().write_all(&[prefix.as_bytes(), line].concat())
+                };
+            }
+            Some(entry.exit_code)
+        }
+        Err(e) => {
+            output::warn(&format!(
+                "Cannot restore cached '{}' in {}, running it: {}",
+                script, project.name, e
+            ));
+            None
+        }
+    }
+}
+
+/// Runs `npm run <script>` in the project directory, streaming its output
+/// live while also capturing it.
+///
+/// stdout and stderr are forwarded to the corresponding streams of this
+/// process line by line (both to stderr in JSON mode), prefixed with the
+/// project of the current thread, if any, and recorded in a single log in the
+/// order the lines arrived. The lines are also written to the log file of the run.
+///
+/// # Returns
+///
+/// * `Result<(i32, Vec<u8>), KhadimError>` - The exit code of npm and the captured log.
+pub fn run_npm_script_captured(
+    project: &Project,
+    script: &str,
+) -> Result<(i32, Vec<u8>), KhadimError> {
+    debug!("Running `npm run {}` in {}", script, project.path.display());
+    let mut child = Command::new("npm")
+        .args(["run", script])
+        .current_dir(&project.path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| KhadimError::spawn("npm", e))?;
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let json = output::is_json();
+    let prefix = logger::project_prefix();
+    let stdout = child.stdout.take().map(|stream| {
+        let log = Arc::clone(&log);
+        let prefix = prefix.clone();
+        thread::spawn(move || {
+            if json {
+                forward_lines(stream, io::stderr(), &prefix, &log)
+            } else {
+                forward_lines(stream, io::stdout(), &prefix, &log)
+            }
+        })
+    });
+    let stderr = child.stderr.take().map(|stream| {
+        let log = Arc::clone(&log);
+        thread::spawn(move || forward_lines(stream, io::stderr(), &prefix, &log))
+    });
+    for handle in [stdout, stderr].into_iter().flatten() {
+        let _ = handle.join();
+    }
+
+    let status = child.wait()?;
+    let log = log.lock().map(|log| log.clone()).unwrap_or_default();
+    Ok((status.code().unwrap_or(1), log))
+}
+
+/// Copies lines from a child stream to an output stream, with a prefix, and
+/// appends them to the log.
+fn forward_lines(stream: impl Read, mut output: impl Write, prefix: &str, log: &Mutex<Vec<u8>>) {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    while let Ok(read) = reader.read_until(b'\n', &mut line) {
+        if read == 0 {
+            break;
+        }
+        let _ = output.write_all(prefix.as_bytes());
+        let _ = output.write_all(&line);
+        logger::write_to_file(&format!("{}{}", prefix, String::from_utf8_lossy(&line)));
+        if let Ok(mut log) = log.lock() {
+            log.extend_from_slice(&line);
+        }
+        line.clear();
+    }
+}
+
+/// Prints a table with the status, duration and exit code of every project.
+fn print_summary(script: &str, results: &[ProjectRunResult]) {
+    let items: Vec<_> = results
+        .iter()
+        .map(|result| {
+            json!({
+                "project": result.project,
+                "status": result.status.to_string(),
+                "duration_ms": result.duration.as_millis(),
+                "exit_code": result.exit_code,
+            })
+        })
+        .collect();
+    output::report("run-all", &items);
+    if results.is_empty() {
+        info!("⚠️ No project defines the script '{}'", script);
+        return;
+    }
+
+    let width = results
+        .iter()
+        .map(|result| result.project.len())
+        .max()
+        .unwrap_or(0)
+        .max("Project".len());
+
+    info!("\n📊 Summary for '{}':", script);
+    info!(
+        "{:<width$}  {:<8}  {:>9}  {:>9}",
+        "Project",
+        "Status",
+        "Duration",
+        "Exit code",
+        width = width
+    );
+    for result in results {
+        let exit_code = result
+            .exit_code
+            .map(|code| code.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        info!(
+            "{:<width$}  {:<8}  {:>8.1}s  {:>9}",
+            result.project,
+            result.status,
+            result.duration.as_secs_f64(),
+            exit_code,
+            width = width
+        );
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| !result.status.is_success())
+        .count();
+    if failed == 0 {
+        info!(
+            "\n✅ '{}' succeeded in all {} projects 🎉",
+            script,
+            results.len()
+        );
+    } else {
+        info!(
+            "\n❌ '{}' failed or was skipped in {} of {} projects 😢",
+            script,
+            failed,
+            results.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn key_of_b(root: &Path) -> String {
+        let workspace = Workspace::discover(root).unwrap();
+        let graph = DependencyGraph::build(&workspace).unwrap();
+        let b = workspace.find("b").unwrap();
+        cache_key(&graph, b, "build", None, &mut HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn editing_a_script_less_dependency_misses_the_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        write(
+            &root.join("libs/a/package.json"),
+            r#"{ "name": "@libs/a", "version": "1.0.0" }"#,
+        );
+        write(&root.join("libs/a/index.js"), "module.exports = 1;\n");
+        write(
+            &root.join("libs/c/package.json"),
+            r#"{ "name": "@libs/c", "version": "1.0.0", "dependencies": { "@libs/a": "file:../a" } }"#,
+        );
+        write(
+            &root.join("apps/b/package.json"),
+            r#"{ "name": "b", "scripts": { "build": "echo build" }, "dependencies": { "@libs/c": "file:../../libs/c" } }"#,
+        );
+
+        let cache = TaskCache::new(&root.join(".khadim-cache"));
+        let old_key = key_of_b(root);
+        let entry = CacheEntry {
+            project: "b".to_string(),
+            script: "build".to_string(),
+            command: "echo build".to_string(),
+            exit_code: 0,
+            outputs: Vec::new(),
+        };
+        let b = Workspace::discover(root).unwrap();
+        cache
+            .store(&old_key, b.find("b").unwrap(), entry, b"build\n")
+            .unwrap();
+        assert!(cache.lookup(&old_key).is_some());
+        assert_eq!(key_of_b(root), old_key);
+
+        // `a` has no scripts and is only reached through `c`
+        write(&root.join("libs/a/index.js"), "module.exports = 2;\n");
+        let new_key = key_of_b(root);
+        assert_ne!(new_key, old_key);
+        assert!(cache.lookup(&new_key).is_none());
+    }
+
+    /// Serializes the tests that put an npm shim on the PATH.
+    #[cfg(unix)]
+    static PATH_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Runs `run_all` with an `npm` shim first on the PATH, which runs
+    /// `npm run <script>` as `sh <script>.sh` in the project directory.
+    #[cfg(unix)]
+    fn run_all_with_shim(root: &Path, script: &str) -> Vec<ProjectRunResult> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let _guard = PATH_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let bin = tempfile::tempdir().unwrap();
+        let npm = bin.path().join("npm");
+        write(&npm, "#!/bin/sh\nexec sh \"./$2.sh\"\n");
+        fs::set_permissions(&npm, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![bin.path().to_path_buf()];
+        paths.extend(std::env::split_paths(&path));
+        std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+        let results = run_all_in(root, script, &RunAllOptions::default());
+        std::env::set_var("PATH", path);
+        results.unwrap()
+    }
+
+    /// Creates a project whose `build` script appends its name to `order.log`
+    /// at the root, then exits with the given code.
+    #[cfg(unix)]
+    fn project(root: &Path, dir: &str, dependencies: &[&str], exit_code: Option<i32>) {
+        let name = dir.rsplit('/').next().unwrap();
+        let dependencies: Vec<String> = dependencies
+            .iter()
+            .map(|dependency| format!(r#""{}": "*""#, dependency))
+            .collect();
+        let scripts = if exit_code.is_some() {
+            r#""build": "sh build.sh""#
+        } else {
+            ""
+        };
+        write(
+            &root.join(dir).join("package.json"),
+            &format!(
+                r#"{{ "name": "{}", "scripts": {{ {} }}, "dependencies": {{ {} }} }}"#,
+                name,
+                scripts,
+                dependencies.join(", ")
+            ),
+        );
+        if let Some(exit_code) = exit_code {
+            write(
+                &root.join(dir).join("build.sh"),
+                &format!("echo {} >> ../../order.log\nexit {}\n", name, exit_code),
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    fn statuses(results: &[ProjectRunResult]) -> Vec<(&str, RunStatus)> {
+        results
+            .iter()
+            .map(|result| (result.project.as_str(), result.status))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_dependencies_first() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        project(root, "libs/core", &[], Some(0));
+        project(root, "libs/ui", &["core"], Some(0));
+        project(root, "apps/web", &["ui"], Some(0));
+        project(root, "apps/api", &["core"], Some(0));
+
+        let results = run_all_with_shim(root, "build");
+
+        let order = fs::read_to_string(root.join("order.log")).unwrap();
+        let position = |name: &str| order.lines().position(|line| line == name).unwrap();
+        assert!(position("core") < position("ui"));
+        assert!(position("core") < position("api"));
+        assert!(position("ui") < position("web"));
+        assert_eq!(results[0].project, "core");
+        assert_eq!(results[3].project, "web");
+        assert!(results
+            .iter()
+            .all(|result| result.status == RunStatus::Success && result.exit_code == Some(0)));
+        assert_eq!(exit_code(&results), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_the_dependents_of_a_failed_project() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        project(root, "libs/core", &[], Some(3));
+        // Without a build script, but between core and web
+        project(root, "libs/types", &["core"], None);
+        project(root, "libs/ui", &[], Some(0));
+        project(root, "apps/web", &["types", "ui"], Some(0));
+        project(root, "apps/api", &["ui"], Some(0));
+
+        let mut results = run_all_with_shim(root, "build");
+        results.sort_by(|a, b| a.project.cmp(&b.project));
+
+        assert_eq!(
+            statuses(&results),
+            [
+                ("api", RunStatus::Success),
+                ("core", RunStatus::Failed),
+                ("ui", RunStatus::Success),
+                ("web", RunStatus::Skipped),
+            ]
+        );
+        assert_eq!(results[1].exit_code, Some(3));
+        assert_eq!(results[3].exit_code, None);
+        let order = fs::read_to_string(root.join("order.log")).unwrap();
+        assert!(!order.lines().any(|line| line == "web"));
+        assert_eq!(exit_code(&results), 1);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::config::{PACKAGE_JSON, PACKAGE_TMPL_JSON};
+use crate::dependencies::DEPENDENCY_KEYS;
+use crate::error::KhadimError;
+use crate::init::{generate_package_json, package_json_from_template};
+use crate::json_file::{insert_sorted, JsonFile};
+use crate::output;
+use crate::plan;
+use crate::root::find_root_dir;
+use crate::transaction::ManifestTransaction;
+use crate::workspace::Workspace;
+use log::info;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// The top-level key of package.json that is generated rather than copied from the template.
+const GENERATED_KEY: &str = "scripts";
+
+/// A difference between the package.json generated from package-tmpl.json and
+/// the package.json on disk.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drift {
+    /// The keys leading to the value, e.g. `["dependencies", "react"]`.
+    pub path: Vec<String>,
+    /// The value generated from the template, or `None` if only package.json has it.
+    pub template: Option<Value>,
+    /// The value in package.json, or `None` if only the template has it.
+    pub package_json: Option<Value>,
+}
+
+impl Drift {
+    /// Returns the path as a dotted string, e.g. `dependencies.react`.
+    pub fn key(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+/// Compares the package.json generated from package-tmpl.json with the one on disk.
+///
+/// The `scripts` section is ignored, since it is always regenerated from the
+/// apps and libs. Objects are compared key by key, any other values as a whole.
+///
+/// # Arguments
+///
+/// * `workspace` - The workspace whose root holds package-tmpl.json and package.json.
+///
+/// # Returns
+///
+/// * `Result<Vec<Drift>, KhadimError>` - The differences, in key order. Empty
+///   if package.json does not exist yet.
+///
+/// # Errors
+///
+/// This function will return an error if package-tmpl.json or package.json
+/// cannot be read or is not valid JSON.
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::sync::{check_drift, print_drift};
+/// use khadim_rs::workspace::Workspace;
+/// use std::path::Path;
+///
+/// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+/// let drift = check_drift(&workspace).unwrap();
+/// print_drift(&drift);
+/// ```
+pub fn check_drift(workspace: &Workspace) -> Result<Vec<Drift>, KhadimError> {
+    let package_json_path = workspace.root_dir.join(PACKAGE_JSON);
+    if !plan::exists(&package_json_path) {
+        return Ok(Vec::new());
+    }
+
+    let mut generated = generate_package_json(workspace)?.value;
+    let mut existing = JsonFile::read(&package_json_path)?.value;
+
+    for manifest in [&mut generated, &mut existing] {
+        if let Some(manifest) = manifest.as_object_mut() {
+            manifest.remove(GENERATED_KEY);
+        }
+    }
+
+    let mut drift = Vec::new();
+    diff_values(
+        &mut Vec::new(),
+        Some(&generated),
+        Some(&existing),
+        &mut drift,
+    );
+    Ok(drift)
+}
+
+/// Prints the differences as a structured diff, with `-` for values only in
+/// the template, `+` for values only in package.json and `~` for changed values.
+pub fn print_drift(drift: &[Drift]) {
+    output::report("sync", &drift);
+    if drift.is_empty() {
+        info!(
+            "✅ {} is in sync with {} 🎉",
+            PACKAGE_JSON, PACKAGE_TMPL_JSON
+        );
+        return;
+    }
+
+    info!(
+        "⚠️ {} has drifted from {} (ignoring scripts):",
+        PACKAGE_JSON, PACKAGE_TMPL_JSON
+    );
+    for difference in drift {
+        match (&difference.template, &difference.package_json) {
+            (Some(template), Some(package_json)) => {
+                info!("  ~ {}: {} → {}", difference.key(), template, package_json)
+            }
+            (Some(template), None) => info!("  - {}: {}", difference.key(), template),
+            (None, Some(package_json)) => info!("  + {}: {}", difference.key(), package_json),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Checks package.json against package-tmpl.json, then regenerates it.
+///
+/// With `check_only`, the differences are printed and nothing is written. Otherwise,
+/// when package.json has drifted, the user is asked (unless `assume_yes` is set)
+/// whether to pull the non-script changes back into package-tmpl.json before
+/// package.json is regenerated. If they decline, nothing is written.
+///
+/// # Arguments
+///
+/// * `check_only` - Whether to only report the differences.
+/// * `assume_yes` - Whether to pull the changes into the template without asking.
+///
+/// # Returns
+///
+/// * `Result<bool, KhadimError>` - `true` if package.json is in sync when the
+///   function returns, `false` if drift remains.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * The root directory cannot be found
+/// * There are issues reading or writing package.json or package-tmpl.json
+///
+/// # Example
+///
+/// ```no_run
+/// use khadim_rs::sync::sync_package_json;
+///
+/// // On CI: fail when package.json was edited by hand
+/// if !sync_package_json(true, false).unwrap() {
+///     std::process::exit(1);
+/// }
+/// ```
+pub fn sync_package_json(check_only: bool, assume_yes: bool) -> Result<bool, KhadimError> {
+    let root_dir = find_root_dir()?;
+    let workspace = Workspace::discover(&root_dir)?;
+    let drift = check_drift(&workspace)?;
+    print_drift(&drift);
+
+    if check_only {
+        return Ok(drift.is_empty());
+    }
+
+    let mut template = JsonFile::read(&root_dir.join(PACKAGE_TMPL_JSON))?;
+    if !drift.is_empty() {
+        if !assume_yes
+            && !output::confirm(&format!("Pull these changes into {}?", PACKAGE_TMPL_JSON))?
+        {
+            output::warn(&format!("{} left untouched", PACKAGE_JSON));
+            return Ok(false);
+        }
+        pull_into_template(&mut template, &drift);
+    }
+
+    // Write the template and the regenerated package.json together
+    let mut transaction = ManifestTransaction::new();
+    transaction.stage(&template)?;
+    transaction.stage(&package_json_from_template(&workspace, &template))?;
+    transaction.commit()?;
+    info!(
+        "✅ Regenerated {} from {} 📄",
+        PACKAGE_JSON, PACKAGE_TMPL_JSON
+    );
+    Ok(true)
+}
+
+/// Applies the package.json side of each difference to package-tmpl.json.
+fn pull_into_template(template: &mut JsonFile, drift: &[Drift]) {
+    for difference in drift {
+        let (last, parents) = match difference.path.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+
+        // New dependencies go in sorted position, anything else at the end
+        if let ([section], Some(value)) = (parents, &difference.package_json) {
+            if DEPENDENCY_KEYS.contains(&section.as_str()) {
+                insert_sorted(&mut template.value, section, last, value.clone());
+                info!("📥 Pulled {} into {}", difference.key(), PACKAGE_TMPL_JSON);
+                continue;
+            }
+        }
+
+        let mut target = &mut template.value;
+        for key in parents {
+            if !target[key.as_str()].is_object() {
+                target[key.as_str()] = Value::Object(Map::new());
+            }
+            target = &mut target[key.as_str()];
+        }
+
+        if let Some(object) = target.as_object_mut() {
+            match &difference.package_json {
+                Some(value) => {
+                    object.insert(last.clone(), value.clone());
+                }
+                None => {
+                    object.shift_remove(last);
+                }
+            }
+        }
+        info!("📥 Pulled {} into {}", difference.key(), PACKAGE_TMPL_JSON);
+    }
+}
+
+/// Recursively collects the differences between two values.
+fn diff_values(
+    path: &mut Vec<String>,
+    template: Option<&Value>,
+    package_json: Option<&Value>,
+    drift: &mut Vec<Drift>,
+) {
+    match (template, package_json) {
+        (Some(Value::Object(template)), Some(Value::Object(package_json))) => {
+            let mut keys: Vec<&String> = template.keys().chain(package_json.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.push(key.clone());
+                diff_values(path, template.get(key), package_json.get(key), drift);
+                path.pop();
+            }
+        }
+        (template, package_json) if template != package_json => drift.push(Drift {
+            path: path.clone(),
+            template: template.cloned(),
+            package_json: package_json.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    const TEMPLATE: &str = r#"{
+  "name": "root",
+  "config": { "port": { "web": 3000, "api": 4000 } },
+  "dependencies": {
+    "next": "14.2.8",
+    "react": "^18"
+  },
+  "engines": { "node": ">=18" }
+}
+"#;
+
+    /// Creates a workspace with one app, the template, and a package.json
+    /// generated from the template then edited with `edit`.
+    fn workspace(edit: impl Fn(&mut Value)) -> (tempfile::TempDir, Workspace) {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("apps/web")).unwrap();
+        fs::write(
+            root.path().join("apps/web/package.json"),
+            r#"{ "name": "web", "scripts": { "dev": "next dev" } }"#,
+        )
+        .unwrap();
+        fs::write(root.path().join(PACKAGE_TMPL_JSON), TEMPLATE).unwrap();
+        let workspace = Workspace::discover(root.path()).unwrap();
+
+        let mut package_json = generate_package_json(&workspace).unwrap();
+        edit(&mut package_json.value);
+        package_json.write().unwrap();
+        (root, workspace)
+    }
+
+    fn drift(path: &[&str], template: Option<Value>, package_json: Option<Value>) -> Drift {
+        Drift {
+            path: path.iter().map(|key| key.to_string()).collect(),
+            template,
+            package_json,
+        }
+    }
+
+    #[test]
+    fn generated_package_json_has_no_drift() {
+        let (_root, workspace) = workspace(|_| {});
+        assert!(check_drift(&workspace).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ignores_scripts() {
+        let (_root, workspace) = workspace(|package_json| {
+            package_json["scripts"]["web:dev"] = json!("next dev --turbo");
+            package_json["scripts"]["lint"] = json!("eslint .");
+        });
+        assert!(check_drift(&workspace).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_nested_changes_additions_and_removals() {
+        let (_root, workspace) = workspace(|package_json| {
+            package_json["config"]["port"]["api"] = json!(4001);
+            package_json["config"]["port"]["admin"] = json!(5000);
+            package_json["dependencies"]["zod"] = json!("^3.23.8");
+            package_json["dependencies"]["react"] = json!("^19");
+            package_json["engines"].as_object_mut().unwrap().clear();
+            package_json["private"] = json!(true);
+        });
+
+        assert_eq!(
+            check_drift(&workspace).unwrap(),
+            [
+                drift(&["config", "port", "admin"], None, Some(json!(5000))),
+                drift(
+                    &["config", "port", "api"],
+                    Some(json!(4000)),
+                    Some(json!(4001))
+                ),
+                drift(
+                    &["dependencies", "react"],
+                    Some(json!("^18")),
+                    Some(json!("^19"))
+                ),
+                drift(&["dependencies", "zod"], None, Some(json!("^3.23.8"))),
+                drift(&["engines", "node"], Some(json!(">=18")), None),
+                drift(&["private"], None, Some(json!(true))),
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_arrays_and_mismatched_types_as_a_whole() {
+        let mut differences = Vec::new();
+        diff_values(
+            &mut Vec::new(),
+            Some(&json!({ "files": ["dist"], "bin": "cli.js", "same": [1] })),
+            Some(&json!({ "files": ["dist", "types"], "bin": { "cli": "cli.js" }, "same": [1] })),
+            &mut differences,
+        );
+        assert_eq!(
+            differences,
+            [
+                drift(
+                    &["bin"],
+                    Some(json!("cli.js")),
+                    Some(json!({ "cli": "cli.js" }))
+                ),
+                drift(
+                    &["files"],
+                    Some(json!(["dist"])),
+                    Some(json!(["dist", "types"]))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn pulls_changes_into_the_template() {
+        let (root, workspace) = workspace(|package_json| {
+            package_json["config"]["port"]["api"] = json!(4001);
+            package_json["dependencies"]["axios"] = json!("^1.7.0");
+            package_json["dependencies"]["react-dom"] = json!("^18");
+            package_json["engines"].as_object_mut().unwrap().clear();
+            package_json["devDependencies"] = json!({ "typescript": "^5" });
+        });
+        let differences = check_drift(&workspace).unwrap();
+        let mut template = JsonFile::read(&root.path().join(PACKAGE_TMPL_JSON)).unwrap();
+
+        pull_into_template(&mut template, &differences);
+
+        assert_eq!(
+            template.render().unwrap(),
+            r#"{
+  "name": "root",
+  "config": {
+    "port": {
+      "web": 3000,
+      "api": 4001
+    }
+  },
+  "dependencies": {
+    "axios": "^1.7.0",
+    "next": "14.2.8",
+    "react": "^18",
+    "react-dom": "^18"
+  },
+  "engines": {},
+  "devDependencies": {
+    "typescript": "^5"
+  }
+}
+"#
+        );
+        // The template now generates the edited package.json
+        fs::write(
+            root.path().join(PACKAGE_TMPL_JSON),
+            template.render().unwrap(),
+        )
+        .unwrap();
+        assert!(check_drift(&workspace).unwrap().is_empty());
+    }
+}
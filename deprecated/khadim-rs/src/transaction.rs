@@ -0,0 +1,274 @@
+use crate::error::KhadimError;
+use crate::json_file::JsonFile;
+use crate::plan::{self, Action};
+use log::{debug, error, info};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// A set of manifest changes that are applied together or not at all.
+///
+/// Files are staged first and written on commit, each through a temporary
+/// file that is renamed over the original. The original content of every
+/// staged or protected file is kept until the transaction finishes, so that
+/// a failure while writing, or in a step that runs afterwards such as
+/// `npm install`, restores all of them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use khadim_rs::init::run_npm_install;
+/// use khadim_rs::json_file::{insert_sorted, JsonFile};
+/// use khadim_rs::transaction::ManifestTransaction;
+/// use serde_json::json;
+/// use std::path::Path;
+///
+/// let root_dir = Path::new("/path/to/project");
+/// let mut package_json = JsonFile::read(&root_dir.join("package.json")).unwrap();
+/// insert_sorted(&mut package_json.value, "dependencies", "zod", json!("^3.23.8"));
+///
+/// let mut transaction = ManifestTransaction::new();
+/// transaction.stage(&package_json).unwrap();
+/// transaction.protect(&root_dir.join("package-lock.json"));
+/// // Restores package.json and package-lock.json if the install fails
+/// transaction.commit_and_then(|| run_npm_install(root_dir)).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct ManifestTransaction {
+    staged: Vec<(PathBuf, String)>,
+    protected: Vec<PathBuf>,
+    /// The content of each file before the commit, or `None` if it did not exist.
+    backups: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl ManifestTransaction {
+    /// Creates an empty transaction.
+    pub fn new() -> ManifestTransaction {
+        ManifestTransaction::default()
+    }
+
+    /// Stages the current content of a file to be written on commit.
+    /// Staging the same path again replaces the earlier content.
+    pub fn stage(&mut self, file: &JsonFile) -> Result<(), KhadimError> {
+        let content = file.render()?;
+        self.staged.retain(|(path, _)| *path != file.path);
+        self.staged.push((file.path.clone(), content));
+        Ok(())
+    }
+
+    /// Restores a file that is not staged, such as a lockfile written by
+    /// `npm install`, when the transaction is rolled back.
+    pub fn protect(&mut self, path: &Path) {
+        self.protected.push(path.to_path_buf());
+    }
+
+    /// Returns `true` if no file is staged.
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// Writes every staged file, restoring all of them if any write fails.
+    /// In dry-run mode, the writes are only planned and nothing is backed up.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file cannot be backed up or written.
+    pub fn commit(&mut self) -> Result<(), KhadimError> {
+        let paths: Vec<PathBuf> = if plan::is_dry_run() {
+            Vec::new()
+        } else {
+            self.staged
+                .iter()
+                .map(|(path, _)| path.clone())
+                .chain(self.protected.iter().cloned())
+                .collect()
+        };
+        for path in paths {
+            if self.backups.iter().any(|(backup, _)| *backup == path) {
+                continue;
+            }
+            let original = if path.exists() {
+                Some(fs::read(&path).map_err(|e| KhadimError::io(&path, e))?)
+            } else {
+                None
+            };
+            self.backups.push((path, original));
+        }
+
+        let staged = std::mem::take(&mut self.staged);
+        for (path, content) in staged {
+            debug!("Writing {}", path.display());
+            if let Err(e) = plan::perform(Action::WriteFile { path, content }) {
+                self.rollback_after(&e);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits, then runs a step such as `npm install`, rolling every file
+    /// back if the step fails.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the error of the failed write or step.
+    pub fn commit_and_then<F>(mut self, step: F) -> Result<(), KhadimError>
+    where
+        F: FnOnce() -> Result<(), KhadimError>,
+    {
+        self.commit()?;
+        if let Err(e) = step() {
+            if plan::is_dry_run() {
+                return Err(e);
+            }
+            self.rollback_after(&e);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Restores every file written by the commit, and every protected file,
+    /// to its content before the commit.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file cannot be restored. The
+    /// remaining files are still restored.
+    pub fn rollback(&mut self) -> Result<(), KhadimError> {
+        let mut failed = Vec::new();
+        for (path, original) in self.backups.drain(..).rev() {
+            debug!("Restoring {}", path.display());
+            let restored = match original {
+                Some(content) => write_atomic(&path, &content),
+                None if path.exists() => {
+                    fs::remove_file(&path).map_err(|e| KhadimError::io(&path, e))
+                }
+                None => Ok(()),
+            };
+            if let Err(e) = restored {
+                failed.push(format!("{}: {}", path.display(), e));
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("❌ Cannot restore {} 😢", failed.join(", ")).into())
+        }
+    }
+
+    fn rollback_after(&mut self, error: &KhadimError) {
+        info!("↩️ Rolling back manifest changes after error: {}", error);
+        if let Err(e) = self.rollback() {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Writes a file by writing a temporary file next to it and renaming it over
+/// the original, so that readers never see a partially written file.
+///
+/// # Errors
+///
+/// This function will return an error if the temporary file cannot be
+/// written or renamed.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), KhadimError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("❌ Invalid file path {} 😢", path.display()))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(KhadimError::io(path, e));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_file::JsonStyle;
+    use serde_json::json;
+
+    fn json_file(path: &Path, value: serde_json::Value) -> JsonFile {
+        JsonFile::new(path, value, JsonStyle::default())
+    }
+
+    #[test]
+    fn rolls_back_after_a_failed_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("package.json");
+        let created = dir.path().join("new.json");
+        // The parent directory is missing, so the write fails
+        let unwritable = dir.path().join("missing/package.json");
+        fs::write(&existing, "original").unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        transaction
+            .stage(&json_file(&existing, json!({ "name": "changed" })))
+            .unwrap();
+        transaction
+            .stage(&json_file(&created, json!({ "name": "new" })))
+            .unwrap();
+        transaction
+            .stage(&json_file(&unwritable, json!({})))
+            .unwrap();
+
+        assert!(transaction.commit().is_err());
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "original");
+        assert!(!created.exists());
+        let leftovers: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(leftovers.len(), 1);
+    }
+
+    #[test]
+    fn rolls_back_protected_files_after_a_failed_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_json = dir.path().join("package.json");
+        let lockfile = dir.path().join("package-lock.json");
+        fs::write(&package_json, "{}").unwrap();
+        fs::write(&lockfile, "lock").unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        transaction
+            .stage(&json_file(&package_json, json!({ "name": "changed" })))
+            .unwrap();
+        transaction.protect(&lockfile);
+        let result = transaction.commit_and_then(|| {
+            fs::write(&lockfile, "broken").unwrap();
+            Err("install failed".to_string().into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&package_json).unwrap(), "{}");
+        assert_eq!(fs::read_to_string(&lockfile).unwrap(), "lock");
+    }
+
+    #[test]
+    fn keeps_the_writes_after_a_successful_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let package_json = dir.path().join("package.json");
+        fs::write(&package_json, "{}").unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        transaction
+            .stage(&json_file(&package_json, json!({ "name": "changed" })))
+            .unwrap();
+        transaction.commit_and_then(|| Ok(())).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&package_json).unwrap(),
+            "{\n  \"name\": \"changed\"\n}\n"
+        );
+    }
+}
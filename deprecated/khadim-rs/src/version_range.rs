@@ -0,0 +1,412 @@
+use crate::error::KhadimError;
+use semver::Version;
+use std::cmp::Ordering;
+
+/// A comparison operator of a desugared range comparator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Eq,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    version: Version,
+}
+
+impl Comparator {
+    fn matches(&self, version: &Version) -> bool {
+        let ordering = version.cmp(&self.version);
+        match self.op {
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Gte => ordering != Ordering::Less,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Lte => ordering != Ordering::Greater,
+            Op::Eq => ordering == Ordering::Equal,
+        }
+    }
+}
+
+/// An npm-style semver range, such as `^18.2.0`, `~14.2`, `>=1.2 <2`, `1.x || 2.x`
+/// or `1.2.3 - 1.4`.
+///
+/// Ranges are desugared into sets of plain comparators the same way npm does.
+/// As in npm, a prerelease version only satisfies a range if one of the
+/// comparators of the matching set has a prerelease on the same
+/// `major.minor.patch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionRange {
+    /// Alternatives separated by `||`; a version must satisfy every comparator of one set.
+    sets: Vec<Vec<Comparator>>,
+}
+
+impl VersionRange {
+    /// Parses an npm-style range.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the range is not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::version_range::VersionRange;
+    /// use semver::Version;
+    ///
+    /// let range = VersionRange::parse("^18.2").unwrap();
+    /// assert!(range.matches(&Version::parse("18.3.1").unwrap()));
+    /// assert!(!range.matches(&Version::parse("19.0.0").unwrap()));
+    /// ```
+    pub fn parse(range: &str) -> Result<VersionRange, KhadimError> {
+        let sets = range
+            .split("||")
+            .map(parse_comparator_set)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("❌ Invalid version range '{}': {} 😢", range, e))?;
+        Ok(VersionRange { sets })
+    }
+
+    /// Returns `true` if the version satisfies the range.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.sets.iter().any(|set| {
+            set.iter().all(|comparator| comparator.matches(version))
+                && (version.pre.is_empty()
+                    || set.iter().any(|comparator| {
+                        !comparator.version.pre.is_empty()
+                            && comparator.version.major == version.major
+                            && comparator.version.minor == version.minor
+                            && comparator.version.patch == version.patch
+                    }))
+        })
+    }
+
+    /// Returns the lowest version that can satisfy the range, ignoring the
+    /// prerelease rule, e.g. `18.2.0` for `^18.2` and `1.0.0` for `1.x || 2.x`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::version_range::VersionRange;
+    ///
+    /// let range = VersionRange::parse(">=14.1 <15 || ^13.4").unwrap();
+    /// assert_eq!(range.min_version().unwrap().to_string(), "13.4.0");
+    /// ```
+    pub fn min_version(&self) -> Option<Version> {
+        self.sets
+            .iter()
+            .map(|set| {
+                set.iter()
+                    .filter(|comparator| matches!(comparator.op, Op::Gt | Op::Gte | Op::Eq))
+                    .map(|comparator| comparator.version.clone())
+                    .max()
+                    .unwrap_or(Version::new(0, 0, 0))
+            })
+            .min()
+    }
+
+    /// Returns the highest of the given versions that satisfies the range.
+    /// Versions that are not valid semver are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use khadim_rs::version_range::VersionRange;
+    ///
+    /// let range = VersionRange::parse("~14.2").unwrap();
+    /// let versions = ["14.1.0", "14.2.8", "14.2.13", "15.0.0"];
+    /// assert_eq!(range.max_satisfying(versions).as_deref(), Some("14.2.13"));
+    /// ```
+    pub fn max_satisfying<I, S>(&self, versions: I) -> Option<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        versions
+            .into_iter()
+            .filter_map(|version| Version::parse(version.as_ref()).ok())
+            .filter(|version| self.matches(version))
+            .max()
+            .map(|version| version.to_string())
+    }
+}
+
+/// A possibly partial version such as `1`, `1.2`, `1.x` or `1.2.3-beta.1`.
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: String,
+}
+
+impl Partial {
+    fn parse(input: &str) -> Result<Partial, String> {
+        let input = input.trim_start_matches(['v', '=']);
+        let (core, pre) = match input.split_once('-') {
+            Some((core, pre)) => (core, pre),
+            None => (input, ""),
+        };
+        // Build metadata never affects matching
+        let core = core.split('+').next().unwrap_or(core);
+        let pre = pre.split('+').next().unwrap_or(pre);
+
+        let mut parts = core.split('.');
+        let mut next = || -> Result<Option<u64>, String> {
+            match parts.next() {
+                None | Some("x") | Some("X") | Some("*") | Some("") => Ok(None),
+                Some(part) => part
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| format!("invalid version '{}'", input)),
+            }
+        };
+        let major = next()?;
+        let minor = if major.is_some() { next()? } else { None };
+        let patch = if minor.is_some() { next()? } else { None };
+
+        Ok(Partial {
+            major,
+            minor,
+            patch,
+            pre: pre.to_string(),
+        })
+    }
+
+    /// The lowest version matching the partial, e.g. `1.2` -> `1.2.0`.
+    fn floor(&self) -> Result<Version, String> {
+        version(
+            self.major.unwrap_or(0),
+            self.minor.unwrap_or(0),
+            self.patch.unwrap_or(0),
+            &self.pre,
+        )
+    }
+}
+
+fn version(major: u64, minor: u64, patch: u64, pre: &str) -> Result<Version, String> {
+    let text = if pre.is_empty() {
+        format!("{}.{}.{}", major, minor, patch)
+    } else {
+        format!("{}.{}.{}-{}", major, minor, patch, pre)
+    };
+    Version::parse(&text).map_err(|e| e.to_string())
+}
+
+/// The lowest prerelease of a version, used as an exclusive upper bound
+/// so that prereleases of the next version are excluded too.
+fn upper(major: u64, minor: u64, patch: u64) -> Result<Comparator, String> {
+    Ok(Comparator {
+        op: Op::Lt,
+        version: version(major, minor, patch, "0")?,
+    })
+}
+
+fn gte(version: Version) -> Comparator {
+    Comparator {
+        op: Op::Gte,
+        version,
+    }
+}
+
+fn parse_comparator_set(set: &str) -> Result<Vec<Comparator>, String> {
+    let tokens = tokenize(set);
+
+    // Hyphen range: `1.2.3 - 2.3.4`
+    if tokens.len() == 3 && tokens[1] == "-" {
+        let from = Partial::parse(&tokens[0])?;
+        let to = Partial::parse(&tokens[2])?;
+        let mut comparators = vec![gte(from.floor()?)];
+        comparators.extend(desugar("<=", &to)?);
+        return Ok(comparators);
+    }
+
+    let mut comparators = Vec::new();
+    for token in tokens {
+        let split = token
+            .find(|c: char| c.is_ascii_alphanumeric() || c == '*')
+            .unwrap_or(token.len());
+        let (op, rest) = token.split_at(split);
+        comparators.extend(desugar(op, &Partial::parse(rest)?)?);
+    }
+    Ok(comparators)
+}
+
+/// Splits a comparator set into tokens, joining operators separated from
+/// their version by whitespace (e.g. `>= 1.2`).
+fn tokenize(set: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut pending_op = String::new();
+    for word in set.split_whitespace() {
+        if word != "-" && word.chars().all(|c| "<>=~^".contains(c)) {
+            pending_op.push_str(word);
+        } else {
+            tokens.push(format!("{}{}", pending_op, word));
+            pending_op.clear();
+        }
+    }
+    tokens
+}
+
+/// Desugars a single operator and partial version into plain comparators.
+fn desugar(op: &str, partial: &Partial) -> Result<Vec<Comparator>, String> {
+    let (major, minor, patch) = (partial.major, partial.minor, partial.patch);
+    let any = || Ok(vec![gte(version(0, 0, 0, "")?)]);
+
+    match op {
+        "" | "=" => match (major, minor, patch) {
+            (None, _, _) => any(),
+            (Some(major), None, _) => Ok(vec![gte(partial.floor()?), upper(major + 1, 0, 0)?]),
+            (Some(major), Some(minor), None) => {
+                Ok(vec![gte(partial.floor()?), upper(major, minor + 1, 0)?])
+            }
+            (Some(_), Some(_), Some(_)) => Ok(vec![Comparator {
+                op: Op::Eq,
+                version: partial.floor()?,
+            }]),
+        },
+        "~" | "~>" => match (major, minor) {
+            (None, _) => any(),
+            (Some(major), None) => Ok(vec![gte(partial.floor()?), upper(major + 1, 0, 0)?]),
+            (Some(major), Some(minor)) => {
+                Ok(vec![gte(partial.floor()?), upper(major, minor + 1, 0)?])
+            }
+        },
+        "^" => match (major, minor, patch) {
+            (None, _, _) => any(),
+            (Some(0), Some(0), Some(patch)) => {
+                Ok(vec![gte(partial.floor()?), upper(0, 0, patch + 1)?])
+            }
+            (Some(0), Some(minor), _) => Ok(vec![gte(partial.floor()?), upper(0, minor + 1, 0)?]),
+            (Some(major), _, _) => Ok(vec![gte(partial.floor()?), upper(major + 1, 0, 0)?]),
+        },
+        ">" => match (major, minor, patch) {
+            // Nothing is greater than any version
+            (None, _, _) => Ok(vec![upper(0, 0, 0)?]),
+            (Some(major), None, _) => Ok(vec![gte(version(major + 1, 0, 0, "")?)]),
+            (Some(major), Some(minor), None) => Ok(vec![gte(version(major, minor + 1, 0, "")?)]),
+            (Some(_), Some(_), Some(_)) => Ok(vec![Comparator {
+                op: Op::Gt,
+                version: partial.floor()?,
+            }]),
+        },
+        ">=" => match major {
+            None => any(),
+            Some(_) => Ok(vec![gte(partial.floor()?)]),
+        },
+        "<" => match major {
+            None => Ok(vec![upper(0, 0, 0)?]),
+            Some(_) if partial.pre.is_empty() => {
+                let floor = partial.floor()?;
+                Ok(vec![upper(floor.major, floor.minor, floor.patch)?])
+            }
+            Some(_) => Ok(vec![Comparator {
+                op: Op::Lt,
+                version: partial.floor()?,
+            }]),
+        },
+        "<=" => match (major, minor, patch) {
+            (None, _, _) => any(),
+            (Some(major), None, _) => Ok(vec![upper(major + 1, 0, 0)?]),
+            (Some(major), Some(minor), None) => Ok(vec![upper(major, minor + 1, 0)?]),
+            (Some(_), Some(_), Some(_)) => Ok(vec![Comparator {
+                op: Op::Lte,
+                version: partial.floor()?,
+            }]),
+        },
+        other => Err(format!("unknown operator '{}'", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_range(range: &str, matching: &[&str], not_matching: &[&str]) {
+        let parsed = VersionRange::parse(range).unwrap();
+        for version in matching {
+            assert!(
+                parsed.matches(&Version::parse(version).unwrap()),
+                "{} should match {}",
+                range,
+                version
+            );
+        }
+        for version in not_matching {
+            assert!(
+                !parsed.matches(&Version::parse(version).unwrap()),
+                "{} should not match {}",
+                range,
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn caret_below_one_locks_the_first_non_zero_part() {
+        assert_range("^0.2.3", &["0.2.3", "0.2.9"], &["0.2.2", "0.3.0", "1.0.0"]);
+        assert_range("^0.0.3", &["0.0.3"], &["0.0.4", "0.1.0"]);
+        assert_range("^0.2", &["0.2.0", "0.2.7"], &["0.3.0"]);
+        assert_range("^0.x", &["0.0.1", "0.9.9"], &["1.0.0"]);
+        assert_range("^1.2", &["1.2.0", "1.9.0"], &["1.1.9", "2.0.0"]);
+    }
+
+    #[test]
+    fn tilde_allows_patch_updates() {
+        assert_range("~1.2.3", &["1.2.3", "1.2.9"], &["1.2.2", "1.3.0"]);
+        assert_range("~1.2", &["1.2.0", "1.2.9"], &["1.3.0"]);
+        assert_range("~1", &["1.0.0", "1.9.0"], &["2.0.0"]);
+        assert_range("~> 0.2.1", &["0.2.1", "0.2.5"], &["0.3.0"]);
+    }
+
+    #[test]
+    fn hyphen_ranges_are_inclusive() {
+        assert_range("1.2.3 - 2.3.4", &["1.2.3", "2.3.4"], &["1.2.2", "2.3.5"]);
+        assert_range("1.2 - 2.3", &["1.2.0", "2.3.9"], &["1.1.9", "2.4.0"]);
+        assert_range("1 - 2", &["1.0.0", "2.9.9"], &["0.9.9", "3.0.0"]);
+    }
+
+    #[test]
+    fn alternatives_match_any_set() {
+        assert_range(
+            "^1.2 || >=3.1 <3.3 || 5.x",
+            &["1.4.0", "3.2.0", "5.1.0"],
+            &["2.0.0", "3.3.0", "4.0.0", "6.0.0"],
+        );
+        assert_range("*", &["0.0.0", "99.1.2"], &["1.0.0-beta.1"]);
+    }
+
+    #[test]
+    fn prereleases_only_match_their_own_version() {
+        assert_range(
+            "^1.2.3-beta.2",
+            &["1.2.3-beta.2", "1.2.3-beta.10", "1.2.3", "1.9.0"],
+            &["1.2.3-beta.1", "1.2.4-beta.1", "2.0.0-rc.1"],
+        );
+        assert_range(">=1.0.0", &["1.5.0"], &["1.5.0-rc.1"]);
+        assert_range("<2", &["1.9.9"], &["2.0.0-alpha.1", "2.0.0"]);
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert!(VersionRange::parse("^one").is_err());
+        assert!(VersionRange::parse("!1.2.3").is_err());
+    }
+
+    #[test]
+    fn max_satisfying_picks_the_highest_match() {
+        let range = VersionRange::parse("^0.2").unwrap();
+        let versions = ["0.1.9", "0.2.1", "0.2.14", "0.3.0", "0.2.15-rc.1", "junk"];
+        assert_eq!(range.max_satisfying(versions).as_deref(), Some("0.2.14"));
+        assert_eq!(
+            VersionRange::parse(">=14.1 <15 || ^13.4")
+                .unwrap()
+                .min_version()
+                .map(|version| version.to_string())
+                .as_deref(),
+            Some("13.4.0")
+        );
+    }
+}
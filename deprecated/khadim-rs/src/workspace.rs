@@ -0,0 +1,374 @@
+use crate::config::{Config, PACKAGE_JSON};
+use crate::error::KhadimError;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The kind of a workspace project, determined by the directory it lives in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProjectKind {
+    /// A library project under the configured `libsDir`.
+    Lib,
+    /// An application project under the configured `appsDir`.
+    App,
+}
+
+/// The subset of a project's `package.json` that khadim-rs cares about.
+///
+/// Unknown keys are ignored, and missing maps default to empty so that
+/// minimal manifests (for example, ones without `devDependencies`) parse.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    #[serde(default)]
+    pub scripts: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dev_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub peer_dependencies: BTreeMap<String, String>,
+    /// khadim-specific settings from the `project` key.
+    #[serde(default)]
+    pub project: ProjectSettings,
+}
+
+impl Manifest {
+    /// Returns the dependency section of a package.json key, e.g. `peerDependencies`.
+    pub fn dependency_section(&self, dep_key: &str) -> Option<&BTreeMap<String, String>> {
+        match dep_key {
+            "dependencies" => Some(&self.dependencies),
+            "devDependencies" => Some(&self.dev_dependencies),
+            "optionalDependencies" => Some(&self.optional_dependencies),
+            "peerDependencies" => Some(&self.peer_dependencies),
+            _ => None,
+        }
+    }
+}
+
+/// The khadim-specific `project` section of a package.json.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProjectSettings {
+    /// Directories, relative to the project, produced by its scripts, e.g. `.next` or `dist`.
+    ///
+    /// Absolute paths and `..` components are rejected, since restoring an
+    /// output from the cache replaces the directory.
+    #[serde(default, deserialize_with = "relative_paths")]
+    pub outputs: Vec<String>,
+}
+
+/// Deserializes a list of paths that must stay inside the project directory.
+fn relative_paths<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let paths = Vec::<String>::deserialize(deserializer)?;
+    for path in &paths {
+        let components: Vec<Component> = Path::new(path).components().collect();
+        let inside = components
+            .iter()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        let below = components
+            .iter()
+            .any(|component| matches!(component, Component::Normal(_)));
+        if !inside || !below {
+            return Err(serde::de::Error::custom(format!(
+                "output '{}' must be a relative path inside the project",
+                path
+            )));
+        }
+    }
+    Ok(paths)
+}
+
+impl Manifest {
+    /// Reads and parses the manifest at the given path.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * The file cannot be read
+    /// * The file is not a valid `package.json`
+    /// * An entry of `project.outputs` is absolute or contains `..`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::workspace::Manifest;
+    /// use std::path::Path;
+    ///
+    /// let manifest = Manifest::from_path(Path::new("libs/hello/package.json")).unwrap();
+    /// println!("{:?}", manifest.name);
+    /// ```
+    pub fn from_path(path: &Path) -> Result<Manifest, KhadimError> {
+        let content = fs::read_to_string(path).map_err(|e| KhadimError::io(path, e))?;
+        serde_json::from_str(&content).map_err(|e| KhadimError::manifest_parse(path, &e))
+    }
+}
+
+/// A single app or lib discovered in the workspace.
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// The project's directory name, e.g. `organic-lever-web`.
+    pub name: String,
+    pub kind: ProjectKind,
+    /// The workspace directory that holds the project, e.g. `apps`.
+    pub kind_dir: String,
+    /// The absolute path to the project directory.
+    pub path: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    /// Returns the project directory relative to the workspace root,
+    /// e.g. `apps/organic-lever-web`.
+    pub fn relative_dir(&self) -> PathBuf {
+        Path::new(&self.kind_dir).join(&self.name)
+    }
+
+    /// Returns `true` if the project's directory name, npm package name or
+    /// relative directory matches a glob.
+    pub fn matches(&self, glob: &glob::Pattern) -> bool {
+        glob.matches(&self.name)
+            || glob.matches(self.package_name())
+            || glob.matches_path(&self.relative_dir())
+    }
+
+    /// Returns the npm package name, falling back to the directory name
+    /// when the manifest does not declare one.
+    pub fn package_name(&self) -> &str {
+        self.manifest.name.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Every npm project found under the apps and libs directories of a root directory.
+///
+/// A directory counts as a project when it contains a `package.json` and the
+/// configuration includes it. Projects are ordered libs first, then apps, each
+/// sorted by directory name.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root_dir: PathBuf,
+    /// The configuration read from the root's `khadim.json`.
+    pub config: Config,
+    pub projects: Vec<Project>,
+}
+
+impl Workspace {
+    /// Discovers all projects below the given root directory, as configured
+    /// by its `khadim.json`.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The monorepo root containing the apps and libs directories.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * The root's `khadim.json` is not valid
+    /// * The apps or libs directory exists but cannot be read
+    /// * A project's `package.json` cannot be read or parsed
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::workspace::Workspace;
+    /// use std::path::Path;
+    ///
+    /// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+    /// for project in workspace.apps() {
+    ///     println!("{}", project.relative_dir().display());
+    /// }
+    /// ```
+    pub fn discover(root_dir: &Path) -> Result<Workspace, KhadimError> {
+        let config = Config::load(root_dir)?;
+        let mut projects = Vec::new();
+        for kind in [ProjectKind::Lib, ProjectKind::App] {
+            projects.extend(discover_projects(root_dir, &config, kind)?);
+        }
+
+        Ok(Workspace {
+            root_dir: root_dir.to_path_buf(),
+            config,
+            projects,
+        })
+    }
+
+    /// Returns all app projects.
+    pub fn apps(&self) -> impl Iterator<Item = &Project> {
+        self.projects
+            .iter()
+            .filter(|project| project.kind == ProjectKind::App)
+    }
+
+    /// Returns all lib projects.
+    pub fn libs(&self) -> impl Iterator<Item = &Project> {
+        self.projects
+            .iter()
+            .filter(|project| project.kind == ProjectKind::Lib)
+    }
+
+    /// Finds a project by its directory name or its npm package name.
+    pub fn find(&self, name: &str) -> Option<&Project> {
+        self.projects
+            .iter()
+            .find(|project| project.name == name || project.package_name() == name)
+    }
+
+    /// Selects the projects whose directory name, npm package name or relative
+    /// directory matches a name or glob, e.g. `organic-lever-web`, `apps/*` or `*-web`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the glob is invalid or no project matches.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use khadim_rs::workspace::Workspace;
+    /// use std::path::Path;
+    ///
+    /// let workspace = Workspace::discover(Path::new("/path/to/project")).unwrap();
+    /// for project in workspace.select("libs/*").unwrap() {
+    ///     println!("{}", project.name);
+    /// }
+    /// ```
+    pub fn select(&self, pattern: &str) -> Result<Vec<&Project>, KhadimError> {
+        let glob = glob::Pattern::new(pattern).map_err(|e| KhadimError::InvalidPattern {
+            pattern: pattern.to_string(),
+            message: e.to_string(),
+        })?;
+        let selected: Vec<&Project> = self
+            .projects
+            .iter()
+            .filter(|project| project.matches(&glob))
+            .collect();
+
+        if selected.is_empty() {
+            return Err(self.not_found(pattern));
+        }
+        Ok(selected)
+    }
+
+    /// Creates the error for a name or glob that no project matches, listing them all.
+    pub fn not_found(&self, pattern: &str) -> KhadimError {
+        KhadimError::ProjectNotFound {
+            pattern: pattern.to_string(),
+            available: self
+                .projects
+                .iter()
+                .map(|project| project.name.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Collects the included projects of one kind, sorted by directory name.
+fn discover_projects(
+    root_dir: &Path,
+    config: &Config,
+    kind: ProjectKind,
+) -> Result<Vec<Project>, KhadimError> {
+    let kind_dir = config.dir_of(kind);
+    let dir = root_dir.join(kind_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut projects = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let package_json_path = path.join(PACKAGE_JSON);
+        if !path.is_dir() || !package_json_path.exists() {
+            continue;
+        }
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let project = Project {
+            name,
+            kind,
+            kind_dir: kind_dir.to_string(),
+            manifest: Manifest::from_path(&package_json_path)?,
+            path,
+        };
+        if config.includes(&project) {
+            projects.push(project);
+        }
+    }
+
+    projects.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(projects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(json: &str) -> Result<Vec<String>, serde_json::Error> {
+        serde_json::from_str::<Manifest>(json).map(|manifest| manifest.project.outputs)
+    }
+
+    #[test]
+    fn accepts_outputs_inside_the_project() {
+        let parsed = outputs(r#"{ "project": { "outputs": [".next", "./dist", "build/out"] } }"#);
+        assert_eq!(parsed.unwrap(), vec![".next", "./dist", "build/out"]);
+    }
+
+    #[test]
+    fn rejects_outputs_outside_the_project() {
+        for output in ["/tmp", "..", "../other", "dist/../..", ".", ""] {
+            let json = format!(r#"{{ "project": {{ "outputs": ["{}"] }} }}"#, output);
+            let error = outputs(&json).unwrap_err();
+            assert!(
+                error.to_string().contains("must be a relative path"),
+                "{}: {}",
+                output,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn reports_unmatched_and_invalid_patterns() {
+        let root = tempfile::tempdir().unwrap();
+        for (dir, name) in [("apps/web", "web"), ("libs/ui", "@libs/ui")] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+            fs::write(
+                root.path().join(dir).join(PACKAGE_JSON),
+                format!(r#"{{ "name": "{}" }}"#, name),
+            )
+            .unwrap();
+        }
+        let workspace = Workspace::discover(root.path()).unwrap();
+
+        let names = |pattern| -> Vec<String> {
+            let selected = workspace.select(pattern).unwrap();
+            selected
+                .iter()
+                .map(|project| project.name.clone())
+                .collect()
+        };
+        assert_eq!(names("apps/*"), ["web"]);
+        assert_eq!(names("*"), ["ui", "web"]);
+
+        let error = workspace.select("api").unwrap_err();
+        assert!(matches!(
+            &error,
+            KhadimError::ProjectNotFound { pattern, available }
+                if pattern == "api" && available == &["ui", "web"]
+        ));
+        assert_eq!(error.exit_code(), 11);
+        assert!(matches!(
+            workspace.select("apps/[web"),
+            Err(KhadimError::InvalidPattern { .. })
+        ));
+    }
+}